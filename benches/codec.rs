@@ -0,0 +1,118 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use chainpack::{ChainPackReader, ChainPackWriter, CponReader, CponWriter, DateTime, Decimal, Map, Reader, RpcMessage, RpcMessageMetaTags, RpcValue, Writer};
+
+/// Request as sent by SHV broker to device, params is a small map
+fn rpc_request() -> RpcValue {
+    let mut params = Map::new();
+    params.insert("since".to_string(), RpcValue::from(DateTime::from_epoch_msec_tz(1_700_000_000_123, 3600)));
+    params.insert("until".to_string(), RpcValue::from(DateTime::from_epoch_msec_tz(1_700_003_600_000, 3600)));
+    params.insert("pathPattern".to_string(), RpcValue::from("status/**"));
+    params.insert("recordCountLimit".to_string(), RpcValue::from(1000));
+    params.insert("withSnapshot".to_string(), RpcValue::from(true));
+    let mut msg = RpcMessage::create_request_with_id(1234, "shv/eu/pl/lublin/odpojovace/15/getLog", "getLog", Some(RpcValue::from(params)));
+    msg.set_caller_ids(&vec![3, 17]);
+    msg.as_rpcvalue().clone()
+}
+
+/// Response of `dir` method, list of method descriptions
+fn rpc_response() -> RpcValue {
+    let request = RpcMessage::create_request_with_id(1234, "test/device", "dir", None);
+    let mut msg = request.prepare_response().unwrap();
+    let methods = ["dir", "ls", "get", "set", "chng", "typeInfo", "getLog", "reset"];
+    let result: Vec<RpcValue> = methods.iter().enumerate().map(|(ix, name)| {
+        let mut method = Map::new();
+        method.insert("name".to_string(), RpcValue::from(*name));
+        method.insert("signature".to_string(), RpcValue::from(ix as i32 % 4));
+        method.insert("flags".to_string(), RpcValue::from((ix as u32) << 1));
+        method.insert("accessGrant".to_string(), RpcValue::from(if ix % 2 == 0 { "rd" } else { "wr" }));
+        RpcValue::from(method)
+    }).collect();
+    msg.set_result(RpcValue::from(result));
+    msg.as_rpcvalue().clone()
+}
+
+/// List of `n` log entries, every entry is list of DateTime, path, value and flags
+fn large_list(n: usize) -> RpcValue {
+    let list: Vec<RpcValue> = (0 .. n).map(|ix| {
+        let ix = ix as i64;
+        RpcValue::from(vec![
+            RpcValue::from(DateTime::from_epoch_msec(1_700_000_000_000 + ix * 1013)),
+            RpcValue::from(format!("status/motor{}/current", ix % 16)),
+            RpcValue::from(Decimal::new(ix * 7 - 3000, -2)),
+            RpcValue::from(ix * ix),
+            RpcValue::from(ix % 3 == 0),
+        ])
+    }).collect();
+    RpcValue::from(list)
+}
+
+fn values() -> Vec<(&'static str, RpcValue)> {
+    vec![
+        ("rpc_request", rpc_request()),
+        ("rpc_response", rpc_response()),
+        ("large_list_10k", large_list(10_000)),
+    ]
+}
+
+fn write_chainpack<W: std::io::Write>(write: &mut W, rv: &RpcValue) -> usize {
+    ChainPackWriter::new(write).write(rv).unwrap()
+}
+
+fn write_cpon<W: std::io::Write>(write: &mut W, rv: &RpcValue) -> usize {
+    CponWriter::new(write).write(rv).unwrap()
+}
+
+fn bench_encode(c: &mut Criterion, format: &str, write_vec: fn(&mut Vec<u8>, &RpcValue) -> usize, write_file: fn(&mut std::fs::File, &RpcValue) -> usize) {
+    // every write to unbuffered file is a syscall
+    let mut dev_null = std::fs::OpenOptions::new().write(true).open("/dev/null").ok();
+    if dev_null.is_none() {
+        eprintln!("{}/encode/unbuffered_file skipped, /dev/null cannot be opened", format);
+    }
+    let mut group = c.benchmark_group(format!("{}/encode", format));
+    for (name, rv) in values() {
+        let len = write_vec(&mut Vec::new(), &rv);
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("vec", name), &rv, |b, rv| b.iter(|| {
+            let mut buff = Vec::new();
+            write_vec(&mut buff, black_box(rv));
+            buff
+        }));
+        if let Some(file) = dev_null.as_mut() {
+            group.bench_with_input(BenchmarkId::new("unbuffered_file", name), &rv, |b, rv| b.iter(|| {
+                write_file(file, black_box(rv))
+            }));
+        }
+    }
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    bench_encode(c, "chainpack", write_chainpack, write_chainpack);
+    bench_encode(c, "cpon", write_cpon, write_cpon);
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chainpack/decode");
+    for (name, rv) in values() {
+        let data = rv.to_chainpack();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("slice", name), &data, |b, data| b.iter(|| {
+            let mut buff = &data[..];
+            ChainPackReader::new(&mut buff).read().unwrap()
+        }));
+    }
+    group.finish();
+    let mut group = c.benchmark_group("cpon/decode");
+    for (name, rv) in values() {
+        let data = rv.to_cpon().into_bytes();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("slice", name), &data, |b, data| b.iter(|| {
+            let mut buff = &data[..];
+            CponReader::new(&mut buff).read().unwrap()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
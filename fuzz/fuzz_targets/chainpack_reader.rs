@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use chainpack::ChainPackReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut buff = data;
+    let mut rd = ChainPackReader::new(&mut buff);
+    // whole stream, not only the first value
+    for rv in rd.values() {
+        if rv.is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use chainpack::{CponReader, Reader};
+
+fuzz_target!(|data: &[u8]| {
+    for strict in [false, true].iter() {
+        let mut buff = data;
+        let mut rd = CponReader::new(&mut buff);
+        rd.set_strict(*strict);
+        if let Err(err) = rd.read() {
+            // diagnostic must not panic on any source text
+            if let Ok(src) = std::str::from_utf8(data) {
+                let _ = err.diagnostic(src).to_string();
+            }
+        }
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use chainpack::rpcframe::RpcFrame;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(Some((_, frame))) = RpcFrame::parse(data) {
+        let _ = frame.to_rpcmesage();
+        let _ = format!("{:#}", frame);
+    }
+});
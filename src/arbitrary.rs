@@ -0,0 +1,131 @@
+//! proptest `Arbitrary` impls of `RpcValue`, `MetaMap`, `DateTime` and `Decimal`
+//!
+//! ```
+//! use chainpack::RpcValue;
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     fn chainpack_round_trip(rv in any::<RpcValue>()) {
+//!         prop_assert_eq!(RpcValue::from_chainpack(&rv.to_chainpack()).unwrap(), rv);
+//!     }
+//! }
+//! # chainpack_round_trip();
+//! ```
+use proptest::prelude::*;
+use proptest::collection::vec;
+use proptest::num::f64 as float;
+use crate::{DateTime, Decimal, MetaMap, RpcValue};
+use crate::rpcvalue::{IMap, Map};
+
+/// Parameters of `RpcValue` and `MetaMap` strategies
+#[derive(Clone, Debug)]
+pub struct ArbitraryOptions {
+    /// Max nesting of containers
+    pub depth: u32,
+    /// Generate `Double` values, NaN is never generated since `NaN != NaN`
+    pub doubles: bool,
+    /// Attach meta data to containers
+    pub meta: bool,
+}
+impl Default for ArbitraryOptions {
+    fn default() -> Self {
+        ArbitraryOptions {
+            depth: 3,
+            doubles: true,
+            meta: true,
+        }
+    }
+}
+
+/// Mantissa fits 56 bits stored in `Decimal`
+impl Arbitrary for Decimal {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Decimal>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (-(1_i64 << 55) .. (1_i64 << 55), any::<i8>())
+            .prop_map(|(mantissa, exponent)| Decimal::new(mantissa, exponent))
+            .boxed()
+    }
+}
+
+/// Years 855 - 3085 with UTC offset up to +-13:45 in quarters of hour
+impl Arbitrary for DateTime {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<DateTime>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (-(1_i64 << 45) .. (1_i64 << 45), -55 ..= 55)
+            .prop_map(|(msec, quarters)| DateTime::from_epoch_msec_tz(msec, quarters * 15 * 60))
+            .boxed()
+    }
+}
+
+impl Arbitrary for RpcValue {
+    type Parameters = ArbitraryOptions;
+    type Strategy = BoxedStrategy<RpcValue>;
+
+    fn arbitrary_with(opts: ArbitraryOptions) -> Self::Strategy {
+        let leaf = prop_oneof![
+            Just(RpcValue::null()),
+            any::<bool>().prop_map(RpcValue::from),
+            any::<i64>().prop_map(RpcValue::from),
+            any::<u64>().prop_map(RpcValue::from),
+            any::<Decimal>().prop_map(RpcValue::from),
+            any::<DateTime>().prop_map(RpcValue::from),
+            string().prop_map(RpcValue::from),
+            vec(any::<u8>(), 0 .. 64).prop_map(RpcValue::from),
+        ];
+        let leaf = if opts.doubles {
+            let double = float::POSITIVE | float::NEGATIVE | float::NORMAL | float::SUBNORMAL | float::ZERO | float::INFINITE;
+            prop_oneof![7 => leaf, 1 => double.prop_map(RpcValue::from)].boxed()
+        } else {
+            leaf.boxed()
+        };
+        let with_meta = opts.meta;
+        leaf.prop_recursive(opts.depth, 64, 8, move |inner| {
+            let container = prop_oneof![
+                vec(inner.clone(), 0 .. 8).prop_map(RpcValue::from),
+                vec((string(), inner.clone()), 0 .. 8).prop_map(|items| RpcValue::from(items.into_iter().collect::<Map>())),
+                vec((any::<i32>(), inner.clone()), 0 .. 8).prop_map(|items| RpcValue::from(items.into_iter().collect::<IMap>())),
+            ];
+            if with_meta {
+                (container, proptest::option::of(metamap(inner))).prop_map(|(rv, meta)| rv.set_meta(meta)).boxed()
+            } else {
+                container.boxed()
+            }
+        }).boxed()
+    }
+}
+
+/// Never empty, since empty meta is not serialized
+impl Arbitrary for MetaMap {
+    type Parameters = ArbitraryOptions;
+    type Strategy = BoxedStrategy<MetaMap>;
+
+    fn arbitrary_with(opts: ArbitraryOptions) -> Self::Strategy {
+        metamap(any_with::<RpcValue>(opts)).boxed()
+    }
+}
+
+fn metamap(value: BoxedStrategy<RpcValue>) -> impl Strategy<Value = MetaMap> {
+    let item = prop_oneof![
+        (any::<i32>(), value.clone()).prop_map(|(key, val)| (Ok(key), val)),
+        (string(), value).prop_map(|(key, val)| (Err(key), val)),
+    ];
+    vec(item, 1 .. 4).prop_map(|items| {
+        let mut mm = MetaMap::new();
+        for (key, val) in items {
+            match key {
+                Ok(key) => mm.insert(key, val),
+                Err(key) => mm.insert(key.as_str(), val),
+            };
+        }
+        mm
+    })
+}
+
+/// Any chars, `any::<String>()` does not generate control characters
+fn string() -> impl Strategy<Value = String> {
+    vec(any::<char>(), 0 .. 16).prop_map(|chars| chars.into_iter().collect())
+}
@@ -1,8 +1,9 @@
 use std::{process, io, fs};
-use std::io::{BufReader, BufRead, BufWriter, stdout};
+use std::io::{BufReader, BufRead, BufWriter, Read, stdout};
 use std::path::PathBuf;
 use flexi_logger::Logger;
-use chainpack::{CponReader, ChainPackReader, ChainPackWriter, CponWriter};
+use chainpack::{CponReader, ChainPackReader, ChainPackWriter, ChainPackWriterOptions, CponWriter};
+use chainpack::rpcvalue::Redaction;
 use chainpack::Reader;
 use chainpack::Writer;
 use structopt::StructOpt;
@@ -14,8 +15,14 @@ struct Cli {
     indent: Option<String>,
     #[structopt(long = "--ip", help = "Cpon input")]
     cpon_input: bool,
+    #[structopt(long, help = "Strict Cpon input validation")]
+    strict: bool,
     #[structopt(long = "--oc", help = "ChainPack output")]
     chainpack_output: bool,
+    #[structopt(long, help = "Zero terminated CString instead of String in ChainPack output")]
+    cstring: bool,
+    #[structopt(long, help = "Replace RpcMessage secrets like login password or access grant with \"***\"")]
+    redact: bool,
     /// File to process
     #[structopt(name = "FILE", parse(from_os_str))]
     file: Option<PathBuf>,
@@ -33,28 +40,44 @@ fn main() {
     //log::info!("=====================================================");
     //log::info!("Verbosity levels: {}", verbosity_string);
 
-    let mut reader: Box<dyn BufRead> = match cli.file {
+    let file_name = cli.file.as_ref().map(|f| f.to_string_lossy().to_string());
+    let mut reader: Box<dyn BufRead> = match &cli.file {
         None => Box::new(BufReader::new(io::stdin())),
         Some(filename) => Box::new(BufReader::new(fs::File::open(filename).unwrap()))
     };
 
     let res = if cli.cpon_input {
-        let mut rd = CponReader::new(&mut reader);
-        rd.read()
+        // keep source text to show the offending line on parse error
+        let mut source = String::new();
+        if let Err(e) = reader.read_to_string(&mut source) {
+            eprintln!("Read input error: {}", e);
+            process::exit(1);
+        }
+        let mut buff = source.as_bytes();
+        let mut rd = CponReader::new(&mut buff);
+        rd.set_strict(cli.strict);
+        rd.read().map_err(|e| {
+            let diag = e.diagnostic(&source);
+            match &file_name {
+                Some(file_name) => diag.file_name(file_name).to_string(),
+                None => diag.to_string(),
+            }
+        })
     } else {
         let mut rd = ChainPackReader::new(&mut reader);
-        rd.read()
+        rd.read().map_err(|e| e.to_string())
     };
     let rv = match res {
         Err(e) => {
-            eprintln!("Parse input error: {:?}", e);
+            eprintln!("Parse input error:\n{}", e);
             process::exit(1);
         }
         Ok(rv) => rv,
     };
+    let rv = if cli.redact { Redaction::default().apply(&rv) } else { rv };
     let mut writer = BufWriter::new(stdout());
     let res = if cli.chainpack_output {
-        let mut wr = ChainPackWriter::new(&mut writer);
+        let mut wr = ChainPackWriter::with_options(&mut writer, ChainPackWriterOptions { cstring: cli.cstring, ..Default::default() });
         wr.write(&rv)
     } else {
         let mut wr = CponWriter::new(&mut writer);
@@ -0,0 +1,45 @@
+use std::{process, io, fs};
+use std::io::Read;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "cpdump", version = env!("CARGO_PKG_VERSION"), author = env!("CARGO_PKG_AUTHORS"), about = "Annotated ChainPack hex dump")]
+struct Cli {
+    #[structopt(long, help = "Input is hex text, whitespace is ignored")]
+    hex: bool,
+    /// File to process
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: Option<PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::from_args();
+
+    let mut data = Vec::new();
+    let res = match &cli.file {
+        None => io::stdin().read_to_end(&mut data),
+        Some(filename) => fs::File::open(filename).and_then(|mut f| f.read_to_end(&mut data)),
+    };
+    if let Err(e) = res {
+        eprintln!("Read input error: {}", e);
+        process::exit(1);
+    }
+    if cli.hex {
+        let text: Vec<u8> = data.iter().filter(|b| !b.is_ascii_whitespace()).cloned().collect();
+        let hex = std::str::from_utf8(&text).ok().and_then(|s| {
+            if s.len() % 2 != 0 {
+                return None
+            }
+            (0 .. s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i .. i + 2)?, 16).ok()).collect::<Option<Vec<u8>>>()
+        });
+        data = match hex {
+            Some(data) => data,
+            None => {
+                eprintln!("Invalid hex input");
+                process::exit(1);
+            }
+        };
+    }
+    print!("{}", chainpack::dump::annotate(&data));
+}
@@ -0,0 +1,532 @@
+use crate::prelude::*;
+use crate::io::{ByteSink, ByteSource};
+use crate::{RpcValue, MetaMap, Value, Decimal, DateTime};
+use crate::datetime::IncludeMilliseconds;
+use crate::writer::{WriteResult, Writer, ByteWriter};
+use crate::metamap::MetaKey;
+use crate::reader::{Reader, ByteReader, ReadError};
+use crate::rpcvalue::{Map, IMap};
+
+/// CBOR tag of value with meta, tagged item is array `[meta, value]`, where meta is map with integer and text keys.
+/// The number is in the first come first served range, it is ASCII "SHV".
+pub const CBOR_META_TAG: u64 = 0x53_48_56;
+
+const TAG_DATETIME_STRING: u64 = 0;
+const TAG_DATETIME_EPOCH: u64 = 1;
+const TAG_DECIMAL: u64 = 4;
+const BREAK: u8 = 0xff;
+
+mod major {
+    pub(super) const UINT: u8 = 0;
+    pub(super) const NEG_INT: u8 = 1;
+    pub(super) const BYTES: u8 = 2;
+    pub(super) const TEXT: u8 = 3;
+    pub(super) const ARRAY: u8 = 4;
+    pub(super) const MAP: u8 = 5;
+    pub(super) const TAG: u8 = 6;
+    pub(super) const SIMPLE: u8 = 7;
+}
+
+/// CBOR (RFC 8949) writer
+///
+/// | RpcValue          | CBOR                                                            |
+/// |-------------------|-----------------------------------------------------------------|
+/// | `Int`, `UInt`     | major type 0 or 1                                               |
+/// | `Double`          | 64-bit float                                                    |
+/// | `Decimal`         | tag 4 `[exponent, mantissa]`                                    |
+/// | `DateTime`        | UTC as tag 1 epoch seconds, integer or float with milliseconds, |
+/// |                   | other offsets as tag 0 RFC 3339 string                          |
+/// | `String`, `Blob`  | text string, byte string                                        |
+/// | `List`            | array                                                           |
+/// | `Map`, `IMap`     | map with text keys, map with integer keys                       |
+/// | meta              | tag `CBOR_META_TAG` `[{key: value, ...}, value]`                |
+///
+/// CBOR does not distinguish signed and unsigned integers, so `CborReader` reads `UInt` up to `i64::MAX` back as `Int`.
+/// Empty `IMap` is read back as empty `Map`.
+pub struct CborWriter<'a, W>
+    where W: ByteSink
+{
+    byte_writer: ByteWriter<'a, W>,
+}
+
+impl<'a, W> CborWriter<'a, W>
+    where W: ByteSink
+{
+    pub fn new(write: &'a mut W) -> Self {
+        CborWriter { byte_writer: ByteWriter::new(write) }
+    }
+
+    fn write_byte(&mut self, b: u8) -> WriteResult {
+        self.byte_writer.write_byte(b)
+    }
+    fn write_bytes(&mut self, b: &[u8]) -> WriteResult {
+        self.byte_writer.write_bytes(b)
+    }
+    /// Write initial byte and argument in the shortest form
+    fn write_head(&mut self, major: u8, n: u64) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        let major = major << 5;
+        if n < 24 {
+            self.write_byte(major | n as u8)?;
+        } else if n <= 0xff {
+            self.write_byte(major | 24)?;
+            self.write_byte(n as u8)?;
+        } else if n <= 0xffff {
+            self.write_byte(major | 25)?;
+            self.write_bytes(&(n as u16).to_be_bytes())?;
+        } else if n <= 0xffff_ffff {
+            self.write_byte(major | 26)?;
+            self.write_bytes(&(n as u32).to_be_bytes())?;
+        } else {
+            self.write_byte(major | 27)?;
+            self.write_bytes(&n.to_be_bytes())?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_int(&mut self, n: i64) -> WriteResult {
+        if n >= 0 {
+            self.write_head(major::UINT, n as u64)
+        } else {
+            // -1 - n
+            self.write_head(major::NEG_INT, !n as u64)
+        }
+    }
+    fn write_double(&mut self, n: f64) -> WriteResult {
+        let cnt = self.write_byte(major::SIMPLE << 5 | 27)?;
+        Ok(cnt + self.write_bytes(&n.to_be_bytes())?)
+    }
+    fn write_string(&mut self, s: &str) -> WriteResult {
+        let cnt = self.write_head(major::TEXT, s.len() as u64)?;
+        Ok(cnt + self.write_bytes(s.as_bytes())?)
+    }
+    fn write_blob(&mut self, b: &[u8]) -> WriteResult {
+        let cnt = self.write_head(major::BYTES, b.len() as u64)?;
+        Ok(cnt + self.write_bytes(b)?)
+    }
+    fn write_decimal(&mut self, decimal: &Decimal) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        let (mantissa, exponent) = decimal.decode();
+        self.write_head(major::TAG, TAG_DECIMAL)?;
+        self.write_head(major::ARRAY, 2)?;
+        self.write_int(exponent as i64)?;
+        self.write_int(mantissa)?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_datetime(&mut self, dt: &DateTime) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        let (msec, offset) = dt.epoc_msec_utc_offset();
+        if offset != 0 {
+            self.write_head(major::TAG, TAG_DATETIME_STRING)?;
+            self.write_string(&dt.to_rfc3339_string(&IncludeMilliseconds::WhenNonZero))?;
+        } else if msec % 1000 == 0 {
+            self.write_head(major::TAG, TAG_DATETIME_EPOCH)?;
+            self.write_int(msec / 1000)?;
+        } else {
+            self.write_head(major::TAG, TAG_DATETIME_EPOCH)?;
+            self.write_double(msec as f64 / 1000.)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_list(&mut self, lst: &[RpcValue]) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_head(major::ARRAY, lst.len() as u64)?;
+        for v in lst {
+            self.do_write(v)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_map(&mut self, map: &Map) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_head(major::MAP, map.len() as u64)?;
+        for (k, v) in map.iter() {
+            self.write_string(k)?;
+            self.do_write(v)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_imap(&mut self, map: &IMap) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_head(major::MAP, map.len() as u64)?;
+        for (k, v) in map.iter() {
+            self.write_int(*k as i64)?;
+            self.do_write(v)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+}
+
+impl<'a, W> CborWriter<'a, W>
+    where W: ByteSink
+{
+    fn do_write(&mut self, val: &RpcValue) -> WriteResult {
+        let mm = val.meta();
+        if mm.is_empty() {
+            return self.do_write_value(val.value())
+        }
+        let cnt = self.byte_writer.count();
+        self.write_head(major::TAG, CBOR_META_TAG)?;
+        self.write_head(major::ARRAY, 2)?;
+        self.do_write_meta(mm)?;
+        self.do_write_value(val.value())?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn do_write_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_head(major::MAP, map.len() as u64)?;
+        for kv in map.0.iter() {
+            match &kv.key {
+                MetaKey::Int(i) => self.write_int(*i as i64)?,
+                MetaKey::Str(s) => self.write_string(s)?,
+            };
+            self.do_write(&kv.value)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn do_write_value(&mut self, val: &Value) -> WriteResult {
+        match val {
+            Value::Null => self.write_byte(major::SIMPLE << 5 | 22),
+            Value::Bool(b) => self.write_byte(major::SIMPLE << 5 | if *b { 21 } else { 20 }),
+            Value::Int(n) => self.write_int(*n),
+            Value::UInt(n) => self.write_head(major::UINT, *n),
+            Value::String(s) => self.write_string(s),
+            Value::Blob(b) => self.write_blob(b),
+            Value::Double(n) => self.write_double(*n),
+            Value::Decimal(d) => self.write_decimal(d),
+            Value::DateTime(d) => self.write_datetime(d),
+            Value::List(lst) => self.write_list(lst),
+            Value::Map(map) => self.write_map(map),
+            Value::IMap(map) => self.write_imap(map),
+        }
+    }
+}
+
+impl<'a, W> Writer for CborWriter<'a, W>
+    where W: ByteSink
+{
+    fn write(&mut self, val: &RpcValue) -> WriteResult {
+        let res = self.do_write(val);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let res = self.do_write_meta(map);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_value(&mut self, val: &Value) -> WriteResult {
+        let res = self.do_write_value(val);
+        self.byte_writer.flush_result(res)
+    }
+}
+
+/// CBOR reader, see `CborWriter` for the mapping.
+///
+/// Indefinite length items, 16 and 32-bit floats and `undefined`, which is read as `Null`, are accepted.
+/// Unknown tags are ignored, the tagged item is read as it is.
+pub struct CborReader<'a, R>
+    where R: ByteSource
+{
+    byte_reader: ByteReader<'a, R>,
+    /// tag consumed by `try_read_meta()` which is not `CBOR_META_TAG`
+    pending_tag: Option<u64>,
+}
+
+impl<'a, R> CborReader<'a, R>
+    where R: ByteSource
+{
+    pub fn new(read: &'a mut R) -> Self {
+        CborReader { byte_reader: ByteReader::new(read), pending_tag: None }
+    }
+
+    fn peek_byte(&mut self) -> u8 {
+        self.byte_reader.peek_byte()
+    }
+    fn get_byte(&mut self) -> Result<u8, ReadError> {
+        self.byte_reader.get_byte()
+    }
+    fn make_error(&self, msg: &str) -> ReadError {
+        self.byte_reader.make_error(msg)
+    }
+
+    fn read_be(&mut self, len: usize) -> Result<u64, ReadError> {
+        let mut n = 0;
+        for _ in 0 .. len {
+            n = n << 8 | self.get_byte()? as u64;
+        }
+        Ok(n)
+    }
+    /// Read argument following the initial byte, `None` for indefinite length
+    fn read_argument(&mut self, info: u8) -> Result<Option<u64>, ReadError> {
+        match info {
+            0 ..= 23 => Ok(Some(info as u64)),
+            24 => Ok(Some(self.read_be(1)?)),
+            25 => Ok(Some(self.read_be(2)?)),
+            26 => Ok(Some(self.read_be(4)?)),
+            27 => Ok(Some(self.read_be(8)?)),
+            31 => Ok(None),
+            _ => Err(self.make_error(&format!("Invalid additional information: {}", info))),
+        }
+    }
+    fn read_definite_argument(&mut self, info: u8) -> Result<u64, ReadError> {
+        self.read_argument(info)?.ok_or_else(|| self.make_error("Unexpected indefinite length"))
+    }
+    fn read_length(&mut self, n: u64) -> Result<usize, ReadError> {
+        if n > usize::MAX as u64 {
+            return Err(self.make_error(&format!("Length too big: {}", n)))
+        }
+        Ok(n as usize)
+    }
+    /// Return `true` when all items of container are read, consume break of indefinite length container
+    fn at_container_end(&mut self, len: Option<u64>, item_count: u64) -> Result<bool, ReadError> {
+        match len {
+            Some(len) => Ok(item_count == len),
+            None if self.peek_byte() == BREAK => {
+                self.get_byte()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    fn read_string_data(&mut self, major: u8, len: Option<u64>) -> Result<Vec<u8>, ReadError> {
+        if let Some(len) = len {
+            let len = self.read_length(len)?;
+            return self.byte_reader.get_bytes(len)
+        }
+        // indefinite length string is a sequence of definite length chunks of the same major type
+        let mut data = Vec::new();
+        loop {
+            let b = self.get_byte()?;
+            if b == BREAK {
+                break;
+            }
+            if b >> 5 != major {
+                return Err(self.make_error("Invalid chunk of indefinite length string"))
+            }
+            let len = self.read_definite_argument(b & 0x1f)?;
+            let len = self.read_length(len)?;
+            data.extend(self.byte_reader.get_bytes(len)?);
+        }
+        Ok(data)
+    }
+    fn read_text(&mut self, len: Option<u64>) -> Result<String, ReadError> {
+        let data = self.read_string_data(major::TEXT, len)?;
+        String::from_utf8(data).map_err(|e| self.make_error(&e.to_string()))
+    }
+    fn read_list(&mut self, len: Option<u64>) -> Result<Value, ReadError> {
+        let mut lst = Vec::new();
+        while !self.at_container_end(len, lst.len() as u64)? {
+            lst.push(self.read()?);
+        }
+        Ok(Value::from(lst))
+    }
+    fn read_map(&mut self, len: Option<u64>) -> Result<Value, ReadError> {
+        let mut map = Map::new();
+        let mut imap = IMap::new();
+        let mut n = 0;
+        while !self.at_container_end(len, n)? {
+            let key = self.read_value()?;
+            let val = self.read()?;
+            match key {
+                Value::String(s) if imap.is_empty() => { map.insert(s.to_string(), val); }
+                Value::Int(i) if map.is_empty() && i >= i32::MIN as i64 && i <= i32::MAX as i64 => { imap.insert(i as i32, val); }
+                _ => return Err(self.make_error(&format!("Map keys must be all text or all Int, got: {}", key.type_name()))),
+            }
+            n += 1;
+        }
+        if imap.is_empty() {
+            Ok(Value::from(map))
+        } else {
+            Ok(Value::from(imap))
+        }
+    }
+    fn read_meta(&mut self) -> Result<MetaMap, ReadError> {
+        let b = self.get_byte()?;
+        if b >> 5 != major::ARRAY || self.read_argument(b & 0x1f)? != Some(2) {
+            return Err(self.make_error("Meta tag must contain array of 2 items"))
+        }
+        let b = self.get_byte()?;
+        if b >> 5 != major::MAP {
+            return Err(self.make_error("MetaMap must be map"))
+        }
+        let len = self.read_argument(b & 0x1f)?;
+        let mut map = MetaMap::new();
+        let mut n = 0;
+        while !self.at_container_end(len, n)? {
+            let key = self.read_value()?;
+            let val = self.read()?;
+            match key {
+                Value::Int(i) if i >= i32::MIN as i64 && i <= i32::MAX as i64 => { map.insert(i as i32, val); }
+                Value::String(s) => { map.insert(s.as_str(), val); }
+                _ => return Err(self.make_error(&format!("MetaMap key must be int or string, got: {}", key.type_name()))),
+            }
+            n += 1;
+        }
+        Ok(map)
+    }
+    fn read_tagged(&mut self, tag: u64) -> Result<Value, ReadError> {
+        match tag {
+            CBOR_META_TAG => {
+                // meta is not wanted by caller
+                self.read_meta()?;
+                self.read_value()
+            }
+            TAG_DATETIME_STRING => match self.read_value()? {
+                Value::String(s) => DateTime::from_rfc3339_str(&s)
+                    .map(Value::from)
+                    .map_err(|e| self.make_error(&e)),
+                v => Err(self.make_error(&format!("DateTime string expected, got: {}", v.type_name()))),
+            },
+            TAG_DATETIME_EPOCH => {
+                let msec = match self.read_value()? {
+                    Value::Int(sec) => sec.checked_mul(1000),
+                    Value::Double(sec) => {
+                        // f64::round() is not available in core
+                        let msec = sec * 1000.;
+                        let msec = if msec < 0. { msec - 0.5 } else { msec + 0.5 };
+                        // NaN fails both comparisons
+                        if msec >= i64::MIN as f64 && msec < i64::MAX as f64 { Some(msec as i64) } else { None }
+                    }
+                    v => return Err(self.make_error(&format!("DateTime epoch number expected, got: {}", v.type_name()))),
+                };
+                msec.and_then(|msec| DateTime::checked_from_epoch_msec_tz(msec, 0))
+                    .map(Value::from)
+                    .ok_or_else(|| self.make_error("DateTime out of range"))
+            }
+            TAG_DECIMAL => match self.read_value()? {
+                Value::List(lst) if lst.len() == 2 => match (lst[0].value(), lst[1].value()) {
+                    (Value::Int(exponent), Value::Int(mantissa)) if *exponent >= i8::MIN as i64 && *exponent <= i8::MAX as i64 => {
+                        Ok(Value::from(Decimal::new(*mantissa, *exponent as i8)))
+                    }
+                    _ => Err(self.make_error("Unsupported Decimal exponent or mantissa")),
+                },
+                _ => Err(self.make_error("Decimal must be array [exponent, mantissa]")),
+            },
+            _ => self.read_value(),
+        }
+    }
+    fn read_simple(&mut self, info: u8) -> Result<Value, ReadError> {
+        let v = match info {
+            20 => Value::from(false),
+            21 => Value::from(true),
+            22 | 23 => Value::Null,
+            25 => Value::from(f16_to_f64(self.read_be(2)? as u16)),
+            26 => Value::from(f32::from_bits(self.read_be(4)? as u32) as f64),
+            27 => Value::from(f64::from_bits(self.read_be(8)?)),
+            _ => return Err(self.make_error(&format!("Unsupported simple value: {}", info))),
+        };
+        Ok(v)
+    }
+}
+
+fn f16_to_f64(half: u16) -> f64 {
+    let exponent = (half >> 10 & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f64;
+    let val = match exponent {
+        0 => mantissa * pow2(-24),
+        31 if mantissa == 0. => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.) * pow2(exponent - 25),
+    };
+    if half & 0x8000 != 0 { -val } else { val }
+}
+/// Exact power of two for normal `f64` exponents, `f64::powi()` is not available without `std`
+fn pow2(exponent: i32) -> f64 {
+    f64::from_bits(((exponent + 1023) as u64) << 52)
+}
+
+impl<'a, R> Reader for CborReader<'a, R>
+    where R: ByteSource
+{
+    fn try_read_meta(&mut self) -> Result<Option<MetaMap>, ReadError> {
+        if self.pending_tag.is_some() || self.peek_byte() >> 5 != major::TAG {
+            return Ok(None)
+        }
+        let b = self.get_byte()?;
+        let tag = self.read_definite_argument(b & 0x1f)?;
+        if tag != CBOR_META_TAG {
+            self.pending_tag = Some(tag);
+            return Ok(None)
+        }
+        Ok(Some(self.read_meta()?))
+    }
+    fn read_value(&mut self) -> Result<Value, ReadError> {
+        if let Some(tag) = self.pending_tag.take() {
+            return self.read_tagged(tag)
+        }
+        let b = self.get_byte()?;
+        let (major, info) = (b >> 5, b & 0x1f);
+        if major == major::SIMPLE {
+            return self.read_simple(info)
+        }
+        let arg = self.read_argument(info)?;
+        match (major, arg) {
+            (major::UINT, Some(n)) if n <= i64::MAX as u64 => Ok(Value::from(n as i64)),
+            (major::UINT, Some(n)) => Ok(Value::from(n)),
+            (major::NEG_INT, Some(n)) if n <= i64::MAX as u64 => Ok(Value::from(!(n as i64))),
+            (major::NEG_INT, Some(_)) => Err(self.make_error("Negative integer out of Int range")),
+            (major::BYTES, len) => Ok(Value::from(self.read_string_data(major::BYTES, len)?)),
+            (major::TEXT, len) => Ok(Value::from(self.read_text(len)?)),
+            (major::ARRAY, len) => self.read_list(len),
+            (major::MAP, len) => self.read_map(len),
+            (major::TAG, Some(tag)) => self.read_tagged(tag),
+            _ => Err(self.make_error(&format!("Unexpected indefinite length of major type {}", major))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::{DateTime, Decimal, RpcValue};
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    fn unhex(s: &str) -> Vec<u8> {
+        (0 .. s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i .. i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_write() {
+        let cbor = |cpon: &str| hex(&RpcValue::from_cpon(cpon).unwrap().to_cbor().unwrap());
+        assert_eq!(cbor("[0,23,24,-1,-25,1000000,null,true,false]"), "89001718182038181a000f4240f6f5f4");
+        assert_eq!(cbor("18446744073709551615u"), "1bffffffffffffffff");
+        assert_eq!(cbor(r#"["a",x"0102",{"b":1},i{-2:3}]"#), "846161420102a1616201a12103");
+        assert_eq!(cbor("1.23"), "c48221187b");
+        assert_eq!(cbor(r#"d"2013-03-21T20:04:00Z""#), "c11a514b67b0");
+        assert_eq!(cbor(r#"d"2013-03-21T20:04:00.500Z""#), "c1fb41d452d9ec200000");
+        assert_eq!(cbor(r#"d"2013-03-21T20:04:00+01""#), format!("c0{}", hex(&RpcValue::from("2013-03-21T20:04:00+01:00").to_cbor().unwrap())));
+        assert_eq!(cbor(r#"<1:2,"a":3>4"#), "da0053485682a2010261610304");
+    }
+
+    #[test]
+    fn test_read() {
+        let cpon = |data: &str| RpcValue::from_cbor(&unhex(data)).unwrap_or_else(|e| panic!("{}: {}", data, e)).to_cpon();
+        // RFC 8949 appendix A examples
+        assert_eq!(cpon("f93c00"), "1e0");
+        assert_eq!(cpon("f90001"), "5.960464477539063e-8");
+        assert_eq!(cpon("fa47c35000"), "1e5");
+        assert_eq!(cpon("f7"), "null");
+        assert_eq!(cpon("5f42010243030405ff"), r#"b"\01\02\03\04\05""#);
+        assert_eq!(cpon("7f657374726561646d696e67ff"), r#""streaming""#);
+        assert_eq!(cpon("9f018202039f0405ffff"), "[1,[2,3],[4,5]]");
+        assert_eq!(cpon("bf61610161629f0203ffff"), r#"{"a":1,"b":[2,3]}"#);
+        assert_eq!(cpon("d8207368747470733a2f2f6578616d706c652e636f6d"), r#""https://example.com""#);
+        assert_eq!(RpcValue::from_cbor(&unhex("c074323031332d30332d32315432303a30343a30305a")).unwrap(),
+                   RpcValue::from(DateTime::from_epoch_msec(1363896240000)));
+        assert_eq!(RpcValue::from_cbor(&unhex("c48221196ab3")).unwrap(), RpcValue::from(Decimal::new(27315, -2)));
+        for data in ["1c", "3bffffffffffffffff", "a20102616101", "c001", "ff", "c48201", "9f01",
+                "c11b7fffffffffffffff", "c11b0100000000000000", "c1fb7fefffffffffffff", "c1fb7ff8000000000000"].iter() {
+            assert!(RpcValue::from_cbor(&unhex(data)).is_err(), "{}", data);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for cpon in [r#"<1:2,"a":<3:4>[]>{"$":[1,-2,3.5e-1,1.23,-5e-10,d"2021-11-08T01:02:03.456+0130",d"1969-12-31T23:59:59.999Z",x"00ff",i{-1:null,2:{}}]}"#,
+                "9223372036854775808u", r#""\u0000ř€""#].iter() {
+            let rv = RpcValue::from_cpon(cpon).unwrap();
+            assert_eq!(RpcValue::from_cbor(&rv.to_cbor().unwrap()).unwrap(), rv, "{}", cpon);
+        }
+        let rv = RpcValue::from(i64::MIN);
+        assert_eq!(RpcValue::from_cbor(&rv.to_cbor().unwrap()).unwrap(), rv);
+    }
+}
@@ -1,9 +1,8 @@
-use crate::{RpcValue, MetaMap, metamap::MetaKey, Decimal, DateTime, WriteResult, Value};
-use std::io;
+use crate::{RpcValue, MetaMap, metamap::{MetaKey, MetaKeyVal}, Decimal, DateTime, WriteResult, Value};
+use crate::prelude::*;
+use crate::io::{ByteSink, ByteSource};
 use crate::writer::{ByteWriter, Writer};
-use std::io::{Write, Read};
-use std::collections::BTreeMap;
-use crate::reader::{Reader, ByteReader, ReadError};
+use crate::reader::{Reader, ByteReader, ReadError, Values};
 use crate::rpcvalue::{Map, IMap};
 
 #[warn(non_camel_case_types)]
@@ -29,21 +28,179 @@ pub(crate) enum PackingSchema {
     TERM = 255,
 }
 
-const SHV_EPOCH_MSEC: i64 = 1517529600000;
+pub(crate) const SHV_EPOCH_MSEC: i64 = 1517529600000;
 
+/// see https://en.wikipedia.org/wiki/Find_first_set#CLZ
+fn significant_bits_part_length(num: u64) -> u32 {
+    let mut len = 0;
+    let mut n = num;
+    if (n & 0xFFFFFFFF00000000) != 0 {
+        len += 32;
+        n >>= 32;
+    }
+    if (n & 0xFFFF0000) != 0 {
+        len += 16;
+        n >>= 16;
+    }
+    if (n & 0xFF00) != 0 {
+        len += 8;
+        n >>= 8;
+    }
+    if (n & 0xF0) != 0 {
+        len += 4;
+        n >>= 4;
+    }
+    const SIG_TABLE_4BIT: [u8; 16] =  [ 0, 1, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4 ];
+    len += SIG_TABLE_4BIT[n as usize];
+    return len as u32
+}
+/// number of bytes needed to encode bit_len
+fn bytes_needed(bit_len: u32) -> u32 {
+    let cnt;
+    if bit_len == 0 {
+        cnt = 1;
+    } else if bit_len <= 28 {
+        cnt = (bit_len - 1) / 7 + 1;
+    } else {
+        cnt = (bit_len - 1) / 8 + 2;
+    }
+    return cnt
+}
+/// return max bit length >= bit_len, which can be encoded by same number of bytes
+fn expand_bit_len(bit_len: u32) -> u32 {
+    let byte_cnt = bytes_needed(bit_len);
+    if bit_len <= 28 {
+        byte_cnt * (8 - 1) - 1
+    } else {
+        (byte_cnt - 1) * 8 - 1
+    }
+}
+fn uint_data_len(num: u64) -> usize {
+    bytes_needed(significant_bits_part_length(num)) as usize
+}
+fn int_data_len(num: i64) -> usize {
+    // add sign bit
+    bytes_needed(significant_bits_part_length(num.unsigned_abs()) + 1) as usize
+}
+/// DateTime is packed as Int with msec since SHV_EPOCH_MSEC and flags in lower bits
+fn datetime_data(dt: &DateTime) -> i64 {
+    let mut msecs = dt.epoch_msec() - SHV_EPOCH_MSEC;
+    let offset = (dt.utc_offset() / 60 / 15) & 0x7F;
+    let ms = msecs % 1000;
+    if ms == 0 {
+        msecs /= 1000;
+    }
+    if offset != 0 {
+        msecs <<= 7;
+        msecs |= offset as i64;
+    }
+    msecs <<= 2;
+    if offset != 0 {
+        msecs |= 1;
+    }
+    if ms == 0 {
+        msecs |= 2;
+    }
+    msecs
+}
+/// Inverse of `datetime_data()`
+/// `None` if date time does not fit `DateTime`
+pub(crate) fn datetime_from_data(mut d: i64) -> Option<DateTime> {
+    let mut offset = 0;
+    let has_tz_offset = (d & 1) != 0;
+    let has_not_msec = (d & 2) != 0;
+    d >>= 2;
+    if has_tz_offset {
+        offset = (d & 0x7F) as i8;
+        offset <<= 1;
+        offset >>= 1; // sign extension
+        d >>= 7;
+    }
+    if has_not_msec {
+        d = d.checked_mul(1000)?;
+    }
+    d = d.checked_add(SHV_EPOCH_MSEC)?;
+    DateTime::checked_from_epoch_msec_tz(d, (offset as i32 * 15) * 60)
+}
+fn int_len(n: i64) -> usize {
+    if (0..64).contains(&n) { 1 } else { 1 + int_data_len(n) }
+}
+fn string_len(s: &[u8]) -> usize {
+    1 + uint_data_len(s.len() as u64) + s.len()
+}
+/// Size of ChainPack encoded value, computed without serialization
+pub(crate) fn rpcvalue_len(rv: &RpcValue) -> usize {
+    let mm = rv.meta();
+    let meta_len = if mm.is_empty() { 0 } else { meta_len(mm) };
+    meta_len + value_len(rv.value())
+}
+pub(crate) fn meta_len(mm: &MetaMap) -> usize {
+    let mut len = 2;
+    for kv in mm.0.iter() {
+        len += match &kv.key {
+            MetaKey::Str(s) => string_len(s.as_bytes()),
+            MetaKey::Int(i) => int_len(*i as i64),
+        };
+        len += rpcvalue_len(&kv.value);
+    }
+    len
+}
+pub(crate) fn value_len(val: &Value) -> usize {
+    match val {
+        Value::Null => 1,
+        Value::Bool(_) => 1,
+        Value::Int(n) => int_len(*n),
+        Value::UInt(n) => if *n < 64 { 1 } else { 1 + uint_data_len(*n) },
+        Value::String(s) => string_len(s.as_bytes()),
+        Value::Blob(b) => string_len(b),
+        Value::Double(_) => 1 + 8,
+        Value::Decimal(d) => {
+            let (mantisa, exponent) = d.decode();
+            1 + int_data_len(mantisa) + int_data_len(exponent as i64)
+        }
+        Value::DateTime(dt) => 1 + int_data_len(datetime_data(dt)),
+        Value::List(lst) => 2 + lst.iter().map(rpcvalue_len).sum::<usize>(),
+        Value::Map(map) => 2 + map.iter().map(|(k, v)| string_len(k.as_bytes()) + rpcvalue_len(v)).sum::<usize>(),
+        Value::IMap(map) => 2 + map.iter().map(|(k, v)| int_len(*k as i64) + rpcvalue_len(v)).sum::<usize>(),
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChainPackWriterOptions {
+    /// canonical encoding, equal values are always encoded to the same bytes, see `ChainPackWriter`
+    pub canonical: bool,
+    /// strings are written as zero terminated `CString` with `\\` and `\0` escaped instead of length
+    /// prefixed `String`, for peers which stream strings without knowing their length up front,
+    /// ignored in canonical mode
+    pub cstring: bool,
+}
+
+/// Canonical mode, see `ChainPackWriterOptions::canonical`, produces encoding suitable for hashing and signing:
+/// `Map` and `IMap` keys are sorted, `MetaMap` has `Int` keys first in ascending order followed by `String`
+/// keys in byte order, `Decimal` is normalized to mantissa without trailing zeros (zero is `0e0`),
+/// NaN is always written with the same bits and `-0.0` as `0.0`. Integers are always minimal length and strings
+/// are never `CString`, which holds in default mode too.
 pub struct ChainPackWriter<'a, W>
-    where W: Write
+    where W: ByteSink
 {
     byte_writer: ByteWriter<'a, W>,
+    options: ChainPackWriterOptions,
 }
 
 impl<'a, W> ChainPackWriter<'a, W>
-    where W: 'a + io::Write
+    where W: 'a + ByteSink
 {
     pub fn new(write: &'a mut W) -> Self {
-        ChainPackWriter {
-            byte_writer: ByteWriter::new(write),
-        }
+        Self::with_options(write, ChainPackWriterOptions::default())
+    }
+    pub fn with_options(write: &'a mut W, options: ChainPackWriterOptions) -> Self {
+        ChainPackWriter { byte_writer: ByteWriter::new(write), options }
+    }
+    pub fn options(&self) -> &ChainPackWriterOptions {
+        &self.options
+    }
+    pub fn set_options(&mut self, options: ChainPackWriterOptions) {
+        self.options = options;
     }
 
     fn write_byte(&mut self, b: u8) -> WriteResult {
@@ -53,51 +210,6 @@ impl<'a, W> ChainPackWriter<'a, W>
         self.byte_writer.write_bytes(b)
     }
 
-    /// see https://en.wikipedia.org/wiki/Find_first_set#CLZ
-    fn significant_bits_part_length(num: u64) -> u32 {
-        let mut len = 0;
-        let mut n = num;
-        if (n & 0xFFFFFFFF00000000) != 0 {
-            len += 32;
-            n >>= 32;
-        }
-        if (n & 0xFFFF0000) != 0 {
-            len += 16;
-            n >>= 16;
-        }
-        if (n & 0xFF00) != 0 {
-            len += 8;
-            n >>= 8;
-        }
-        if (n & 0xF0) != 0 {
-            len += 4;
-            n >>= 4;
-        }
-        const SIG_TABLE_4BIT: [u8; 16] =  [ 0, 1, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4 ];
-        len += SIG_TABLE_4BIT[n as usize];
-        return len as u32
-    }
-    /// number of bytes needed to encode bit_len
-    fn bytes_needed(bit_len: u32) -> u32 {
-        let cnt;
-        if bit_len == 0 {
-            cnt = 1;
-        } else if bit_len <= 28 {
-            cnt = (bit_len - 1) / 7 + 1;
-        } else {
-            cnt = (bit_len - 1) / 8 + 2;
-        }
-        return cnt
-    }
-    /// return max bit length >= bit_len, which can be encoded by same number of bytes
-    fn expand_bit_len(bit_len: u32) -> u32 {
-        let byte_cnt = Self::bytes_needed(bit_len);
-        if bit_len <= 28 {
-            byte_cnt * (8 - 1) - 1
-        } else {
-            (byte_cnt - 1) * 8 - 1
-        }
-    }
     /** UInt
     0 ...  7 bits  1  byte  |0|x|x|x|x|x|x|x|<-- LSB
     8 ... 14 bits  2  bytes |1|0|x|x|x|x|x|x| |x|x|x|x|x|x|x|x|<-- LSB
@@ -109,9 +221,10 @@ impl<'a, W> ChainPackWriter<'a, W>
                     n == 14 -> 18 bytes number
                     n == 15 -> for future (number of bytes will be specified in next byte)
     */
-    fn write_uint_data_helper(&mut self, number: u64, bit_len: u32) -> WriteResult {
+    /// `u128` since `i64::MIN` needs sign bit above 64 bits
+    fn write_uint_data_helper(&mut self, number: u128, bit_len: u32) -> WriteResult {
         const BYTE_CNT_MAX: u32 = 32;
-        let byte_cnt = Self::bytes_needed(bit_len);
+        let byte_cnt = bytes_needed(bit_len);
         assert!(byte_cnt <= BYTE_CNT_MAX, "Max int byte size {} exceeded", BYTE_CNT_MAX);
         let mut bytes: [u8; BYTE_CNT_MAX as usize] = [0; BYTE_CNT_MAX as usize];
         let mut num = number;
@@ -137,28 +250,24 @@ impl<'a, W> ChainPackWriter<'a, W>
         return Ok(self.byte_writer.count() - cnt)
     }
     pub fn write_uint_data(&mut self, number: u64) -> WriteResult {
-        let bitlen = Self::significant_bits_part_length(number);
-        let cnt = self.write_uint_data_helper(number, bitlen)?;
-        Ok(self.byte_writer.count() - cnt)
+        let res = self.do_write_uint_data(number);
+        self.byte_writer.flush_result(res)
+    }
+    fn do_write_uint_data(&mut self, number: u64) -> WriteResult {
+        let bitlen = significant_bits_part_length(number);
+        self.write_uint_data_helper(number as u128, bitlen)
     }
     fn write_int_data(&mut self, number: i64) -> WriteResult {
-        let mut num;
-        let neg;
-        if number < 0 {
-            num = (-number) as u64;
-            neg = true;
-        } else {
-            num = number as u64;
-            neg = false;
-        };
+        let mut num = number.unsigned_abs() as u128;
+        let neg = number < 0;
 
-        let bitlen = Self::significant_bits_part_length(num as u64) + 1; // add sign bit
+        let bitlen = significant_bits_part_length(number.unsigned_abs()) + 1; // add sign bit
         if neg {
-            let sign_pos = Self::expand_bit_len(bitlen);
-            let sign_bit_mask = (1 as u64) << sign_pos;
+            let sign_pos = expand_bit_len(bitlen);
+            let sign_bit_mask = (1 as u128) << sign_pos;
             num |= sign_bit_mask;
         }
-        let cnt = self.write_uint_data_helper(num as u64, bitlen)?;
+        let cnt = self.write_uint_data_helper(num, bitlen)?;
         Ok(self.byte_writer.count() - cnt)
     }
 
@@ -180,112 +289,163 @@ impl<'a, W> ChainPackWriter<'a, W>
         }
         else {
             self.write_byte(PackingSchema::UInt as u8)?;
-            self.write_uint_data(n)?;
+            self.do_write_uint_data(n)?;
         }
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_double(&mut self, n: f64) -> WriteResult {
         let cnt = self.write_byte(PackingSchema::Double as u8)?;
+        let n = match n {
+            _ if !self.options.canonical => n,
+            _ if n.is_nan() => f64::NAN,
+            // -0.0 == 0.0
+            _ if n == 0.0 => 0.0,
+            _ => n,
+        };
         let bytes = n.to_le_bytes();
         self.write_bytes(&bytes)?;
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_decimal(&mut self, decimal: &Decimal) -> WriteResult {
         let cnt = self.write_byte(PackingSchema::Decimal as u8)?;
-        let (mantisa, exponent) = decimal.decode();
+        let (mut mantisa, mut exponent) = decimal.decode();
+        if self.options.canonical {
+            if mantisa == 0 {
+                exponent = 0;
+            }
+            while mantisa != 0 && mantisa % 10 == 0 && exponent < i8::MAX {
+                mantisa /= 10;
+                exponent += 1;
+            }
+        }
         self.write_int_data(mantisa)?;
         self.write_int_data(exponent as i64)?;
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_datetime(&mut self, dt: &DateTime) -> WriteResult {
         let cnt = self.write_byte(PackingSchema::DateTime as u8)?;
-        let mut msecs = dt.epoch_msec() - SHV_EPOCH_MSEC;
-        let offset = (dt.utc_offset() / 60 / 15) & 0x7F;
-        let ms = msecs % 1000;
-        if ms == 0 {
-            msecs /= 1000;
-        }
-        if offset != 0 {
-            msecs <<= 7;
-            msecs |= offset as i64;
-        }
-        msecs <<= 2;
-        if offset != 0 {
-            msecs |= 1;
-        }
-        if ms == 0 {
-            msecs |= 2;
-        }
-        self.write_int_data(msecs)?;
+        self.write_int_data(datetime_data(dt))?;
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_list(&mut self, lst: &Vec<RpcValue>) -> WriteResult {
         let cnt = self.write_byte(PackingSchema::List as u8)?;
         for v in lst {
-            self.write(v)?;
+            self.do_write(v)?;
         }
         self.write_byte(PackingSchema::TERM as u8)?;
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_map(&mut self, map: &Map) -> WriteResult {
         let cnt = self.write_byte(PackingSchema::Map as u8)?;
-        for (k, v) in map {
-            self.write_string(k)?;
-            self.write(v)?;
+        if self.options.canonical {
+            let mut items: Vec<_> = map.iter().collect();
+            items.sort_by(|a, b| a.0.cmp(b.0));
+            for (k, v) in items {
+                self.write_string(k)?;
+                self.do_write(v)?;
+            }
+        } else {
+            for (k, v) in map.iter() {
+                self.write_string(k)?;
+                self.do_write(v)?;
+            }
         }
         self.write_byte(PackingSchema::TERM as u8)?;
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_imap(&mut self, map: &IMap) -> WriteResult {
         let cnt = self.write_byte(PackingSchema::IMap as u8)?;
-        for (k, v) in map {
-            self.write_int(*k as i64)?;
-            self.write(v)?;
+        if self.options.canonical {
+            let mut items: Vec<_> = map.iter().collect();
+            items.sort_by(|a, b| a.0.cmp(b.0));
+            for (k, v) in items {
+                self.write_int(*k as i64)?;
+                self.do_write(v)?;
+            }
+        } else {
+            for (k, v) in map.iter() {
+                self.write_int(*k as i64)?;
+                self.do_write(v)?;
+            }
         }
         self.write_byte(PackingSchema::TERM as u8)?;
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_string(&mut self, s: &str) -> WriteResult {
+        if self.options.cstring && !self.options.canonical {
+            return self.write_cstring(s)
+        }
         let cnt = self.write_byte(PackingSchema::String as u8)?;
         let data = s.as_bytes();
-        self.write_uint_data(data.len() as u64)?;
+        self.do_write_uint_data(data.len() as u64)?;
         self.write_bytes(data)?;
         Ok(self.byte_writer.count() - cnt)
     }
+    fn write_cstring(&mut self, s: &str) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_byte(PackingSchema::CString as u8)?;
+        for b in s.bytes() {
+            match b {
+                b'\\' => self.write_bytes(b"\\\\")?,
+                0 => self.write_bytes(b"\\0")?,
+                _ => self.write_byte(b)?,
+            };
+        }
+        self.write_byte(0)?;
+        Ok(self.byte_writer.count() - cnt)
+    }
     fn write_blob(&mut self, data: &[u8]) -> WriteResult {
         let cnt = self.write_byte(PackingSchema::Blob as u8)?;
-        self.write_uint_data(data.len() as u64)?;
+        self.do_write_uint_data(data.len() as u64)?;
         self.write_bytes(data)?;
         Ok(self.byte_writer.count() - cnt)
     }
 }
 
-impl<'a, W> Writer for ChainPackWriter<'a, W>
-    where W: io::Write
+impl<'a, W> ChainPackWriter<'a, W>
+    where W: ByteSink
 {
-    fn write_meta(&mut self, map: &MetaMap) -> WriteResult {
+    fn do_write_meta(&mut self, map: &MetaMap) -> WriteResult {
         let cnt = self.byte_writer.count();
         self.write_byte(PackingSchema::MetaMap as u8)?;
-        for k in map.0.iter() {
-            match &k.key {
-                MetaKey::Str(s) => self.write_string(s)?,
-                MetaKey::Int(i) => self.write_int(*i as i64)?,
-            };
-            self.write(&k.value)?;
+        if self.options.canonical {
+            let mut items: Vec<_> = map.0.iter().collect();
+            items.sort_by(|a, b| match (&a.key, &b.key) {
+                (MetaKey::Int(a), MetaKey::Int(b)) => a.cmp(b),
+                (MetaKey::Int(_), MetaKey::Str(_)) => core::cmp::Ordering::Less,
+                (MetaKey::Str(_), MetaKey::Int(_)) => core::cmp::Ordering::Greater,
+                (MetaKey::Str(a), MetaKey::Str(b)) => a.as_bytes().cmp(b.as_bytes()),
+            });
+            for kv in items {
+                self.do_write_meta_item(kv)?;
+            }
+        } else {
+            for kv in map.0.iter() {
+                self.do_write_meta_item(kv)?;
+            }
         }
         self.write_byte(PackingSchema::TERM as u8)?;
         Ok(self.byte_writer.count() - cnt)
     }
-    fn write(&mut self, val: &RpcValue) -> WriteResult {
+    fn do_write_meta_item(&mut self, kv: &MetaKeyVal) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        match &kv.key {
+            MetaKey::Str(s) => self.write_string(s)?,
+            MetaKey::Int(i) => self.write_int(*i as i64)?,
+        };
+        self.do_write(&kv.value)?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn do_write(&mut self, val: &RpcValue) -> WriteResult {
         let cnt = self.byte_writer.count();
         let mm = val.meta();
         if !mm.is_empty() {
-            self.write_meta(mm)?;
+            self.do_write_meta(mm)?;
         }
-        self.write_value(val.value())?;
+        self.do_write_value(val.value())?;
         Ok(self.byte_writer.count() - cnt)
     }
-    fn write_value(&mut self, val: &Value) -> WriteResult {
+    fn do_write_value(&mut self, val: &Value) -> WriteResult {
         let cnt = self.byte_writer.count();
         match val {
             Value::Null => self.write_byte(PackingSchema::Null as u8)?,
@@ -309,18 +469,42 @@ impl<'a, W> Writer for ChainPackWriter<'a, W>
     }
 }
 
+impl<'a, W> Writer for ChainPackWriter<'a, W>
+    where W: ByteSink
+{
+    fn write(&mut self, val: &RpcValue) -> WriteResult {
+        let res = self.do_write(val);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let res = self.do_write_meta(map);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_value(&mut self, val: &Value) -> WriteResult {
+        let res = self.do_write_value(val);
+        self.byte_writer.flush_result(res)
+    }
+}
+
 pub struct ChainPackReader<'a, R>
-    where R: Read
+    where R: ByteSource
 {
     byte_reader: ByteReader<'a, R>,
 }
 
 impl<'a, R> ChainPackReader<'a, R>
-    where R: Read
+    where R: ByteSource
 {
     pub fn new(read: &'a mut R) -> Self {
         ChainPackReader { byte_reader: ByteReader::new(read) }
     }
+    /// Iterate over back-to-back top level values until end of stream
+    pub fn values(&mut self) -> Values<'_, Self> {
+        Values::new(self, Self::at_end, Self::read)
+    }
+    fn at_end(&mut self) -> Result<bool, ReadError> {
+        self.byte_reader.is_eof()
+    }
 
     fn peek_byte(&mut self) -> u8 {
         self.byte_reader.peek_byte()
@@ -333,39 +517,46 @@ impl<'a, R> ChainPackReader<'a, R>
     }
 
     /// return (n, bitlen)
-    /// bitlen is used to enable same function usage for signed int unpacking
-    fn read_uint_data_helper(&mut self) -> Result<(u64, u8), ReadError> {
+    /// bitlen is used to enable same function usage for signed int unpacking,
+    /// `u128` since `i64::MIN` needs 9 data bytes
+    fn read_uint_data_helper(&mut self) -> Result<(u128, u8), ReadError> {
         let mut num = 0;
         let head = self.get_byte()?;
         let bytes_to_read_cnt;
         let bitlen;
-        if (head & 128) == 0 {bytes_to_read_cnt = 0; num = (head & 127) as u64; bitlen = 7;}
-        else if (head &  64) == 0 {bytes_to_read_cnt = 1; num = (head & 63) as u64; bitlen = 6 + 8;}
-        else if (head &  32) == 0 {bytes_to_read_cnt = 2; num = (head & 31) as u64; bitlen = 5 + 2*8;}
-        else if (head &  16) == 0 {bytes_to_read_cnt = 3; num = (head & 15) as u64; bitlen = 4 + 3*8;}
+        if (head & 128) == 0 {bytes_to_read_cnt = 0; num = (head & 127) as u128; bitlen = 7;}
+        else if (head &  64) == 0 {bytes_to_read_cnt = 1; num = (head & 63) as u128; bitlen = 6 + 8;}
+        else if (head &  32) == 0 {bytes_to_read_cnt = 2; num = (head & 31) as u128; bitlen = 5 + 2*8;}
+        else if (head &  16) == 0 {bytes_to_read_cnt = 3; num = (head & 15) as u128; bitlen = 4 + 3*8;}
         else {
             bytes_to_read_cnt = (head & 0xf) + 4;
+            if bytes_to_read_cnt > 16 {
+                return Err(self.make_error(&format!("Unsupported number length: {} bytes", bytes_to_read_cnt)))
+            }
             bitlen = bytes_to_read_cnt * 8;
         }
         for _ in 0 .. bytes_to_read_cnt {
             let r = self.get_byte()?;
-            num = (num << 8) + (r as u64);
+            num = (num << 8) + (r as u128);
         }
         Ok((num, bitlen))
     }
     pub fn read_uint_data(&mut self) -> Result<u64, ReadError> {
         let (num, _) = self.read_uint_data_helper()?;
-        return Ok(num);
+        if num > u64::MAX as u128 {
+            return Err(self.make_error(&format!("UInt out of range: {}", num)))
+        }
+        return Ok(num as u64);
     }
     fn read_int_data(&mut self) -> Result<i64, ReadError> {
         let (num, bitlen) = self.read_uint_data_helper()?;
-        let sign_bit_mask = (1 as u64) << (bitlen - 1);
+        let sign_bit_mask = (1 as u128) << (bitlen - 1);
         let neg = (num & sign_bit_mask) != 0;
-        let mut snum = num as i64;
-        if neg {
-            snum &= !(sign_bit_mask as i64);
-            snum = -snum;
+        let abs = num & !sign_bit_mask;
+        if abs > if neg { i64::MIN.unsigned_abs() as u128 } else { i64::MAX as u128 } {
+            return Err(self.make_error(&format!("Int out of range: {}{}", if neg { "-" } else { "" }, abs)))
         }
+        let snum = if neg { (abs as i64).wrapping_neg() } else { abs as i64 };
         return Ok(snum);
     }
 
@@ -391,7 +582,7 @@ impl<'a, R> ChainPackReader<'a, R>
                 }
             }
         }
-        let s = std::str::from_utf8(&buff);
+        let s = core::str::from_utf8(&buff);
         match s {
             Ok(s) => return Ok(Value::from(s)),
             Err(e) => return Err(self.make_error(&format!("Invalid string, Utf8 error: {}", e))),
@@ -404,7 +595,7 @@ impl<'a, R> ChainPackReader<'a, R>
             let b = self.get_byte()?;
             buff.push(b);
         }
-        let s = std::str::from_utf8(&buff);
+        let s = core::str::from_utf8(&buff);
         match s {
             Ok(s) => return Ok(Value::from(s)),
             Err(e) => return Err(self.make_error(&format!("Invalid string, Utf8 error: {}", e))),
@@ -454,7 +645,7 @@ impl<'a, R> ChainPackReader<'a, R>
         return Ok(Value::from(map))
     }
     fn read_imap_data(&mut self) -> Result<Value, ReadError> {
-        let mut map: BTreeMap<i32, RpcValue> = BTreeMap::new();
+        let mut map: IMap = IMap::new();
         loop {
             let b = self.peek_byte();
             if b == PackingSchema::TERM as u8 {
@@ -475,29 +666,16 @@ impl<'a, R> ChainPackReader<'a, R>
         return Ok(Value::from(map))
     }
     fn read_datetime_data(&mut self) -> Result<Value, ReadError> {
-        let mut d = self.read_int_data()?;
-        let mut offset = 0;
-        let has_tz_offset = (d & 1) != 0;
-        let has_not_msec = (d & 2) != 0;
-        d >>= 2;
-        if has_tz_offset {
-            offset = (d & 0x7F) as i8;
-            offset <<= 1;
-            offset >>= 1; // sign extension
-            //log::debug!("1----------> offset: {}", offset);
-            d >>= 7;
-        }
-        if has_not_msec {
-            d *= 1000;
-        }
-        d += SHV_EPOCH_MSEC;
-        let dt = DateTime::from_epoch_msec_tz(d, (offset as i32 * 15) * 60);
-        return Ok(Value::from(dt))
+        let d = self.read_int_data()?;
+        match datetime_from_data(d) {
+            Some(dt) => Ok(Value::from(dt)),
+            None => Err(self.make_error(&format!("DateTime out of range, data: {}", d))),
+        }
     }
     fn read_double_data(&mut self) -> Result<Value, ReadError> {
         let mut buff: [u8;8] = [0;8];
-        if let Err(e) = self.byte_reader.read.read(&mut buff) {
-            return Err(self.make_error(&format!("{}", e)))
+        for b in buff.iter_mut() {
+            *b = self.get_byte()?;
         }
         let d = f64::from_le_bytes(buff);
         return Ok(Value::from(d))
@@ -511,7 +689,7 @@ impl<'a, R> ChainPackReader<'a, R>
 }
 
 impl<'a, R> Reader for ChainPackReader<'a, R>
-    where R: Read
+    where R: ByteSource
 {
     fn try_read_meta(&mut self) -> Result<Option<MetaMap>, ReadError> {
         let b = self.peek_byte();
@@ -599,4 +777,17 @@ impl<'a, R> Reader for ChainPackReader<'a, R>
 
         Ok(v)
     }
-}
\ No newline at end of file
+}
+/// Check that `data` is single value in canonical encoding, see `ChainPackWriterOptions::canonical`
+pub fn is_canonical(data: &[u8]) -> bool {
+    let mut buff = data;
+    let mut rd = ChainPackReader::new(&mut buff);
+    let rv = match rd.read() {
+        Ok(rv) => rv,
+        Err(_) => return false,
+    };
+    if !matches!(rd.at_end(), Ok(true)) {
+        return false
+    }
+    matches!(rv.to_canonical_chainpack(), Ok(canonical) if canonical == data)
+}
@@ -1,107 +1,201 @@
-use std::io::{Write, Read};
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+use core::convert::TryFrom;
+use crate::io::{ByteSink, ByteSource, NullSink};
 use crate::{RpcValue, MetaMap, Value, Decimal, DateTime};
-use std::collections::BTreeMap;
 use crate::datetime::{IncludeMilliseconds, ToISOStringOptions};
 use crate::writer::{WriteResult, Writer, ByteWriter};
 use crate::metamap::MetaKey;
-use crate::reader::{Reader, ByteReader, ReadError};
-use crate::rpcvalue::{Map};
+use crate::reader::{Reader, ByteReader, ReadError, ReadResult, Values};
+use crate::rpcvalue::{Map, IMap};
+
+/// How `CponWriter` writes blobs
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlobFormat {
+    /// `b"..."`, printable ASCII is written as is, other bytes as `\hh`
+    Escaped,
+    /// `x"..."` hex dump
+    Hex,
+}
+
+/// How `CponWriter` escapes characters in `ascii_only` mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnicodeEscape {
+    /// JSON compatible `\uXXXX`, characters outside BMP as surrogate pair
+    Utf16,
+    /// `\u{X...}` with code point
+    Braced,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CponWriterOptions {
+    /// indentation string, empty string means single line output
+    pub indent: String,
+    /// when set, indented containers are written on single line if they fit in `max_line_width` columns,
+    /// otherwise lists up to 10 items, maps up to 4 items and meta up to 5 items without nested containers are single line
+    pub max_line_width: Option<usize>,
+    /// no space after comma in single line containers of indented output
+    pub compact: bool,
+    /// non-ASCII and control characters in strings are written as unicode escapes
+    pub ascii_only: bool,
+    pub unicode_escape: UnicodeEscape,
+    pub blob_format: BlobFormat,
+    /// comma after the last item of multi line container in indented output
+    pub trailing_commas: bool,
+    /// `Map` and `IMap` keys are written sorted, useful with `preserve_order` feature
+    pub sort_keys: bool,
+}
+
+impl Default for CponWriterOptions {
+    fn default() -> Self {
+        CponWriterOptions {
+            indent: String::new(),
+            max_line_width: None,
+            compact: false,
+            ascii_only: false,
+            unicode_escape: UnicodeEscape::Utf16,
+            blob_format: BlobFormat::Escaped,
+            trailing_commas: false,
+            sort_keys: false,
+        }
+    }
+}
 
 pub struct CponWriter<'a, W>
-    where W: Write
+    where W: ByteSink
 {
     byte_writer: ByteWriter<'a, W>,
-    indent: Vec<u8>,
+    options: CponWriterOptions,
     nest_count: usize,
+    line_start: usize,
+    /// single line lengths of containers by address, valid during one `write()`
+    one_line_lens: BTreeMap<usize, usize>,
 }
 
 impl<'a, W> CponWriter<'a, W>
-    where W: Write
+    where W: ByteSink
 {
     pub fn new(write: &'a mut W) -> Self {
+        Self::with_options(write, CponWriterOptions::default())
+    }
+    pub fn with_options(write: &'a mut W, options: CponWriterOptions) -> Self {
         CponWriter {
             byte_writer: ByteWriter::new(write),
-            indent: "".as_bytes().to_vec(),
+            options,
             nest_count: 0,
+            line_start: 0,
+            one_line_lens: BTreeMap::new(),
         }
     }
     pub fn set_indent(&mut self, indent: &[u8]) {
-        self.indent = indent.to_vec();
+        self.options.indent = String::from_utf8_lossy(indent).to_string();
+    }
+    pub fn options(&self) -> &CponWriterOptions {
+        &self.options
+    }
+    pub fn set_options(&mut self, options: CponWriterOptions) {
+        self.options = options;
     }
 
-    fn is_oneliner_list(lst: &Vec<RpcValue>) -> bool {
-        if lst.len() > 10 {
-            return false;
+    fn has_few_scalar_items<'b>(mut iter: impl Iterator<Item = &'b RpcValue>, max_items: usize) -> bool {
+        let mut n = 0;
+        iter.all(|val| {
+            n += 1;
+            n <= max_items && !matches!(val.value(), Value::List(_) | Value::Map(_) | Value::IMap(_))
+        })
+    }
+    /// Check if container of `len` bytes written on single line fits on the current line
+    fn fits_on_line(&self, width: usize, len: usize) -> bool {
+        let column = self.byte_writer.count() - self.line_start;
+        column < width && len <= width - column
+    }
+    /// Single line length of container at `addr`, lengths of nested containers are computed
+    /// in the same pass and cached, so every container is measured once per `write()`
+    fn one_line_len(&mut self, width: usize, addr: usize, measure: impl FnOnce(&mut OneLineLen) -> usize) -> usize {
+        if let Some(len) = self.one_line_lens.get(&addr) {
+            return *len
         }
-        for it in lst.iter() {
-            match it.value() {
-                Value::List(_) => return false,
-                Value::Map(_) => return false,
-                Value::IMap(_) => return false,
-                _ => continue,
+        let mut sink = NullSink;
+        let mut lens = core::mem::take(&mut self.one_line_lens);
+        let len = measure(&mut OneLineLen {
+            writer: CponWriter::with_options(&mut sink, self.options.clone()),
+            lens: &mut lens,
+            cap: width,
+        });
+        self.one_line_lens = lens;
+        len
+    }
+    fn is_oneliner_list(&mut self, lst: &[RpcValue]) -> bool {
+        match self.options.max_line_width {
+            _ if self.options.indent.is_empty() => true,
+            Some(usize::MAX) => true,
+            Some(width) => {
+                let len = self.one_line_len(width, lst.as_ptr() as usize, |m| m.list(lst));
+                self.fits_on_line(width, len)
             }
+            None => Self::has_few_scalar_items(lst.iter(), 10),
         }
-        return true;
     }
-    fn is_oneliner_map<K>(iter: &mut dyn Iterator<Item = (K, &RpcValue)>) -> bool {
-        let mut n = 0;
-        loop {
-            if n == 5 {
-                return false
-            }
-            match iter.next() {
-                Some(val) => {
-                    match val.1.value() {
-                        Value::List(_) => return false,
-                        Value::Map(_) => return false,
-                        Value::IMap(_) => return false,
-                        _ => (),
-                    }
-                },
-                None => break,
-            };
-            n += 1;
-        };
-        return true
+    fn is_oneliner_map(&mut self, map: &Map) -> bool {
+        match self.options.max_line_width {
+            _ if self.options.indent.is_empty() => true,
+            Some(usize::MAX) => true,
+            Some(width) => {
+                let len = self.one_line_len(width, map as *const Map as usize, |m| m.map(map));
+                self.fits_on_line(width, len)
+            }
+            None => Self::has_few_scalar_items(map.values(), 4),
+        }
     }
-
-    fn is_oneliner_meta(map: &MetaMap) -> bool {
-        if map.0.len() > 5 {
-            return false;
+    fn is_oneliner_imap(&mut self, map: &IMap) -> bool {
+        match self.options.max_line_width {
+            _ if self.options.indent.is_empty() => true,
+            Some(usize::MAX) => true,
+            Some(width) => {
+                let len = self.one_line_len(width, map as *const IMap as usize, |m| m.imap(map));
+                self.fits_on_line(width, len)
+            }
+            None => Self::has_few_scalar_items(map.values(), 4),
         }
-        for k in map.0.iter() {
-            match k.value.value() {
-                Value::List(_) => return false,
-                Value::Map(_) => return false,
-                Value::IMap(_) => return false,
-                _ => continue,
+    }
+    fn is_oneliner_meta(&mut self, map: &MetaMap) -> bool {
+        match self.options.max_line_width {
+            _ if self.options.indent.is_empty() => true,
+            Some(usize::MAX) => true,
+            Some(width) => {
+                let len = self.one_line_len(width, map as *const MetaMap as usize, |m| m.meta(map));
+                self.fits_on_line(width, len)
             }
+            None => Self::has_few_scalar_items(map.0.iter().map(|kv| &kv.value), 5),
         }
-        return true;
     }
 
     fn start_block(&mut self) {
         self.nest_count += 1;
     }
-    fn end_block(&mut self, is_oneliner: bool) -> WriteResult {
+    fn end_block(&mut self, is_oneliner: bool, item_count: usize) -> WriteResult {
         let cnt = self.byte_writer.count();
         self.nest_count -= 1;
-        if !self.indent.is_empty() {
+        if !self.options.indent.is_empty() {
+            if !is_oneliner && item_count > 0 && self.options.trailing_commas {
+                self.write_byte(b',')?;
+            }
             self.indent_element(is_oneliner, true)?;
         }
         Ok(self.byte_writer.count() - cnt)
     }
     fn indent_element(&mut self, is_oneliner: bool, is_first_field: bool) -> WriteResult {
         let cnt = self.byte_writer.count();
-        if !self.indent.is_empty() {
+        if !self.options.indent.is_empty() {
             if is_oneliner {
-                if !is_first_field {
+                if !is_first_field && !self.options.compact {
                     self.write_byte(b' ')?;
                 }
             } else {
                 self.write_byte(b'\n')?;
+                self.line_start = self.byte_writer.count();
                 for _ in 0 .. self.nest_count {
-                    self.byte_writer.write_bytes(&self.indent)?;
+                    self.byte_writer.write_bytes(self.options.indent.as_bytes())?;
                 }
             }
         }
@@ -160,6 +254,19 @@ impl<'a, W> CponWriter<'a, W>
                     self.write_byte(b'\\')?;
                     self.write_byte(b'"')?;
                 }
+                _ if self.options.ascii_only && (!c.is_ascii() || c.is_ascii_control()) => {
+                    match self.options.unicode_escape {
+                        UnicodeEscape::Utf16 => {
+                            let mut b = [0; 2];
+                            for u in c.encode_utf16(&mut b) {
+                                self.write_bytes(format!("\\u{:04x}", u).as_bytes())?;
+                            }
+                        }
+                        UnicodeEscape::Braced => {
+                            self.write_bytes(format!("\\u{{{:x}}}", c as u32).as_bytes())?;
+                        }
+                    }
+                }
                 _ => {
                     let mut b = [0; 4];
                     let bytes = c.encode_utf8(&mut b);
@@ -170,8 +277,14 @@ impl<'a, W> CponWriter<'a, W>
         self.write_byte(b'"')?;
         Ok(self.byte_writer.count() - cnt)
     }
-    /// Escape blob to be UTF8 compatible
     fn write_blob(&mut self, bytes: &[u8]) -> WriteResult {
+        match self.options.blob_format {
+            BlobFormat::Escaped => self.write_blob_esc(bytes),
+            BlobFormat::Hex => self.write_blob_hex(bytes),
+        }
+    }
+    /// Escape blob to be UTF8 compatible
+    fn write_blob_esc(&mut self, bytes: &[u8]) -> WriteResult {
         let cnt = self.byte_writer.count();
         self.write_bytes(b"b\"")?;
         for b in bytes {
@@ -218,14 +331,15 @@ impl<'a, W> CponWriter<'a, W>
         self.write_byte(b'"')?;
         Ok(self.byte_writer.count() - cnt)
     }
-    // fn write_bytes_hex(&mut self, b: &[u8]) -> WriteResult {
-    //     let cnt = self.byte_writer.count();
-    //     self.write_bytes(b"x\"")?;
-    //     let s = hex::encode(b);
-    //     self.write_bytes(s.as_bytes())?;
-    //     self.write_byte(b'"')?;
-    //     Ok(self.byte_writer.count() - cnt)
-    // }
+    fn write_blob_hex(&mut self, bytes: &[u8]) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_bytes(b"x\"")?;
+        for b in bytes {
+            self.write_bytes(format!("{:02x}", b).as_bytes())?;
+        }
+        self.write_byte(b'"')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
     fn write_decimal(&mut self, decimal: &Decimal) -> WriteResult {
         let s = decimal.to_cpon_string();
         let cnt = self.write_bytes(s.as_bytes())?;
@@ -241,9 +355,9 @@ impl<'a, W> CponWriter<'a, W>
         self.write_byte(b'"')?;
         return Ok(self.byte_writer.count() - cnt)
     }
-    fn write_list(&mut self, lst: &Vec<RpcValue>) -> WriteResult {
+    fn write_list(&mut self, lst: &[RpcValue]) -> WriteResult {
         let cnt = self.byte_writer.count();
-        let is_oneliner = Self::is_oneliner_list(lst);
+        let is_oneliner = self.is_oneliner_list(lst);
         self.write_byte(b'[')?;
         self.start_block();
         let mut n = 0;
@@ -253,73 +367,93 @@ impl<'a, W> CponWriter<'a, W>
                 self.write_byte(b',')?;
             }
             self.indent_element(is_oneliner, n == 0)?;
-            self.write(v)?;
+            self.do_write(v)?;
             n += 1;
         }
-        self.end_block(is_oneliner)?;
+        self.end_block(is_oneliner, n)?;
         self.write_byte(b']')?;
         Ok(self.byte_writer.count() - cnt)
     }
     fn write_map(&mut self, map: &Map) -> WriteResult {
         let cnt = self.byte_writer.count();
-        let is_oneliner = Self::is_oneliner_map(&mut map.iter());
+        let is_oneliner = self.is_oneliner_map(map);
         self.write_byte(b'{')?;
         self.start_block();
+        let n = if self.options.sort_keys {
+            let mut items: Vec<_> = map.iter().collect();
+            items.sort_by(|a, b| a.0.cmp(b.0));
+            self.write_map_items(items.into_iter(), is_oneliner)?
+        } else {
+            self.write_map_items(map.iter(), is_oneliner)?
+        };
+        self.end_block(is_oneliner, n)?;
+        self.write_byte(b'}')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_map_items<'b>(&mut self, items: impl Iterator<Item = (&'b String, &'b RpcValue)>, is_oneliner: bool) -> WriteResult {
         let mut n = 0;
-        for (k, v) in map {
+        for (k, v) in items {
             if n > 0 {
                 self.write_byte(b',')?;
             }
             self.indent_element(is_oneliner, n == 0)?;
             self.write_string(k)?;
             self.write_byte(b':')?;
-            self.write(v)?;
+            self.do_write(v)?;
             n += 1;
         }
-        self.end_block(is_oneliner)?;
-        self.write_byte(b'}')?;
-        Ok(self.byte_writer.count() - cnt)
+        Ok(n)
     }
-    fn write_imap(&mut self, map: &BTreeMap<i32, RpcValue>) -> WriteResult {
+    fn write_imap(&mut self, map: &IMap) -> WriteResult {
         let cnt = self.byte_writer.count();
-        let is_oneliner = Self::is_oneliner_map(&mut map.iter());
+        let is_oneliner = self.is_oneliner_imap(map);
         self.write_byte(b'i')?;
         self.write_byte(b'{')?;
         self.start_block();
+        let n = if self.options.sort_keys {
+            let mut items: Vec<_> = map.iter().collect();
+            items.sort_by(|a, b| a.0.cmp(b.0));
+            self.write_imap_items(items.into_iter(), is_oneliner)?
+        } else {
+            self.write_imap_items(map.iter(), is_oneliner)?
+        };
+        self.end_block(is_oneliner, n)?;
+        self.write_byte(b'}')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_imap_items<'b>(&mut self, items: impl Iterator<Item = (&'b i32, &'b RpcValue)>, is_oneliner: bool) -> WriteResult {
         let mut n = 0;
-        for (k, v) in map {
+        for (k, v) in items {
             if n > 0 {
                 self.write_byte(b',')?;
             }
             self.indent_element(is_oneliner, n == 0)?;
             self.write_int(*k as i64)?;
             self.write_byte(b':')?;
-            self.write(v)?;
+            self.do_write(v)?;
             n += 1;
         }
-        self.end_block(is_oneliner)?;
-        self.write_byte(b'}')?;
-        Ok(self.byte_writer.count() - cnt)
+        Ok(n)
     }
 }
 
-impl<'a, W> Writer for CponWriter<'a, W>
-    where W: Write
+impl<'a, W> CponWriter<'a, W>
+    where W: ByteSink
 {
-    fn write(&mut self, val: &RpcValue) -> WriteResult
+    fn do_write(&mut self, val: &RpcValue) -> WriteResult
     {
         let cnt = self.byte_writer.count();
         let mm = val.meta();
         if !mm.is_empty() {
-            self.write_meta(mm)?;
+            self.do_write_meta(mm)?;
         }
-        self.write_value(val.value())?;
+        self.do_write_value(val.value())?;
         Ok(self.byte_writer.count() - cnt)
     }
-    fn write_meta(&mut self, map: &MetaMap) -> WriteResult
+    fn do_write_meta(&mut self, map: &MetaMap) -> WriteResult
     {
         let cnt: usize = self.byte_writer.count();
-        let is_oneliner = Self::is_oneliner_meta(map);
+        let is_oneliner = self.is_oneliner_meta(map);
         self.write_byte(b'<')?;
         self.start_block();
         let mut n = 0;
@@ -337,14 +471,14 @@ impl<'a, W> Writer for CponWriter<'a, W>
                 },
             }
             self.write_byte(b':')?;
-            self.write(&k.value)?;
+            self.do_write(&k.value)?;
             n += 1;
         }
-        self.end_block(is_oneliner)?;
+        self.end_block(is_oneliner, n)?;
         self.write_byte(b'>')?;
         Ok(self.byte_writer.count() - cnt)
     }
-    fn write_value(&mut self, val: &Value) -> WriteResult
+    fn do_write_value(&mut self, val: &Value) -> WriteResult
     {
         let cnt: usize = self.byte_writer.count();
         match val {
@@ -372,17 +506,153 @@ impl<'a, W> Writer for CponWriter<'a, W>
     }
 }
 
+/// Computes single line lengths of containers bottom-up for `CponWriter` with `max_line_width`.
+/// Lengths are capped at `cap + 1`, longer containers do not fit anyway.
+struct OneLineLen<'a, 'b> {
+    writer: CponWriter<'a, NullSink>,
+    lens: &'b mut BTreeMap<usize, usize>,
+    cap: usize,
+}
+
+impl OneLineLen<'_, '_> {
+    fn add(&self, a: usize, b: usize) -> usize {
+        a.saturating_add(b).min(self.cap + 1)
+    }
+    /// `n` items separators
+    fn separators(&self, n: usize) -> usize {
+        let sep = if self.writer.options.compact { 1 } else { 2 };
+        n.saturating_sub(1) * sep
+    }
+    fn scalar(&mut self, write_fn: impl FnOnce(&mut CponWriter<NullSink>) -> WriteResult) -> usize {
+        let cnt = self.writer.byte_writer.count();
+        let _ = write_fn(&mut self.writer);
+        self.writer.byte_writer.count() - cnt
+    }
+    fn cached(&mut self, addr: usize, measure: impl FnOnce(&mut Self) -> usize) -> usize {
+        if let Some(len) = self.lens.get(&addr) {
+            return *len
+        }
+        let len = measure(self);
+        self.lens.insert(addr, len);
+        len
+    }
+    fn rpcvalue(&mut self, rv: &RpcValue) -> usize {
+        let mm = rv.meta();
+        let meta_len = if mm.is_empty() { 0 } else { self.meta(mm) };
+        let value_len = self.value(rv.value());
+        self.add(meta_len, value_len)
+    }
+    fn value(&mut self, val: &Value) -> usize {
+        match val {
+            Value::List(lst) => self.list(lst),
+            Value::Map(map) => self.map(map),
+            Value::IMap(map) => self.imap(map),
+            // escaped string or blob is never shorter
+            Value::String(s) if s.len() > self.cap => self.cap + 1,
+            Value::Blob(b) if b.len() > self.cap => self.cap + 1,
+            _ => self.scalar(|wr| wr.do_write_value(val)),
+        }
+    }
+    fn list(&mut self, lst: &[RpcValue]) -> usize {
+        self.cached(lst.as_ptr() as usize, |m| {
+            lst.iter().fold(2 + m.separators(lst.len()), |len, v| {
+                let n = m.rpcvalue(v);
+                m.add(len, n)
+            })
+        })
+    }
+    fn map(&mut self, map: &Map) -> usize {
+        self.cached(map as *const Map as usize, |m| {
+            map.iter().fold(2 + m.separators(map.len()), |len, (k, v)| {
+                let n = m.scalar(|wr| wr.write_string(k)) + 1 + m.rpcvalue(v);
+                m.add(len, n)
+            })
+        })
+    }
+    fn imap(&mut self, map: &IMap) -> usize {
+        self.cached(map as *const IMap as usize, |m| {
+            map.iter().fold(3 + m.separators(map.len()), |len, (k, v)| {
+                let n = m.scalar(|wr| wr.write_int(*k as i64)) + 1 + m.rpcvalue(v);
+                m.add(len, n)
+            })
+        })
+    }
+    fn meta(&mut self, map: &MetaMap) -> usize {
+        self.cached(map as *const MetaMap as usize, |m| {
+            map.0.iter().fold(2 + m.separators(map.0.len()), |len, kv| {
+                let key_len = match &kv.key {
+                    MetaKey::Str(s) => m.scalar(|wr| wr.write_string(s)),
+                    MetaKey::Int(i) => m.scalar(|wr| wr.write_int(*i as i64)),
+                };
+                let n = key_len + 1 + m.rpcvalue(&kv.value);
+                m.add(len, n)
+            })
+        })
+    }
+}
+
+impl<'a, W> Writer for CponWriter<'a, W>
+    where W: ByteSink
+{
+    fn write(&mut self, val: &RpcValue) -> WriteResult {
+        let res = self.do_write(val);
+        self.one_line_lens.clear();
+        self.byte_writer.flush_result(res)
+    }
+    fn write_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let res = self.do_write_meta(map);
+        self.one_line_lens.clear();
+        self.byte_writer.flush_result(res)
+    }
+    fn write_value(&mut self, val: &Value) -> WriteResult {
+        let res = self.do_write_value(val);
+        self.one_line_lens.clear();
+        self.byte_writer.flush_result(res)
+    }
+}
+
 pub struct CponReader<'a, R>
-    where R: Read
+    where R: ByteSource
 {
     byte_reader: ByteReader<'a, R>,
+    strict: bool,
 }
 
 impl<'a, R> CponReader<'a, R>
-    where R: Read
+    where R: ByteSource
 {
     pub fn new(read: &'a mut R) -> Self {
-        CponReader { byte_reader: ByteReader::new(read) }
+        CponReader { byte_reader: ByteReader::new(read), strict: false }
+    }
+    /// Strict mode requires `,` between items and `:` between key and value,
+    /// rejects duplicate keys and anything but white space and comments after the top level value.
+    /// Lenient mode, which is the default, treats separators as white space.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+    /// Iterate over back-to-back top level values until end of stream, values can be separated by white space and comments.
+    /// ```
+    /// use chainpack::CponReader;
+    /// let mut buff = "1 [2]\n{\"a\":3} // end\n".as_bytes();
+    /// let mut rd = CponReader::new(&mut buff);
+    /// let values: Vec<String> = rd.values().map(|rv| rv.unwrap().to_cpon()).collect();
+    /// assert_eq!(values, ["1", "[2]", r#"{"a":3}"#]);
+    /// ```
+    pub fn values(&mut self) -> Values<'_, Self> {
+        Values::new(self, Self::at_end, Self::read_next)
+    }
+    fn at_end(&mut self) -> Result<bool, ReadError> {
+        self.skip_white_insignificant()?;
+        self.byte_reader.is_eof()
+    }
+    fn read_next(&mut self) -> ReadResult {
+        self.read_nested()
+    }
+    /// Value without the strict end of input check of top level `read()`
+    fn read_nested(&mut self) -> ReadResult {
+        let m = self.try_read_meta()?;
+        let v = self.read_value()?;
+        Ok(RpcValue::new(v, m))
     }
 
     fn peek_byte(&mut self) -> u8 {
@@ -399,7 +669,10 @@ impl<'a, R> CponReader<'a, R>
         loop {
             let b = self.peek_byte();
             if b == 0 {
-                break;
+                if self.byte_reader.is_eof()? {
+                    break;
+                }
+                return Err(self.make_error("Unexpected NUL byte"))
             }
             if b > b' ' {
                 match b {
@@ -433,10 +706,10 @@ impl<'a, R> CponReader<'a, R>
                             }
                         }
                     }
-                    b':' => {
+                    b':' if !self.strict => {
                         self.get_byte()?; // skip key delimiter
                     }
-                    b',' => {
+                    b',' if !self.strict => {
                         self.get_byte()?; // skip val delimiter
                     }
                     _ => {
@@ -465,7 +738,15 @@ impl<'a, R> CponReader<'a, R>
                         b'r' => buff.push(b'\r'),
                         b't' => buff.push(b'\t'),
                         b'0' => buff.push(b'\0'),
-                        _ => buff.push(b),
+                        b'b' => buff.push(0x08),
+                        b'f' => buff.push(0x0c),
+                        b'/' => buff.push(b'/'),
+                        b'u' => {
+                            let c = self.byte_reader.read_unicode_escape()?;
+                            let mut b = [0; 4];
+                            buff.extend_from_slice(c.encode_utf8(&mut b).as_bytes());
+                        }
+                        _ => return Err(self.make_error(&format!("Invalid escape sequence: \\{}", char::from(b)))),
                     }
                 }
                 b'"' => {
@@ -477,19 +758,14 @@ impl<'a, R> CponReader<'a, R>
                 }
             }
         }
-        let s = std::str::from_utf8(&buff);
+        let s = core::str::from_utf8(&buff);
         match s {
             Ok(s) => return Ok(Value::from(s)),
             Err(e) => return Err(self.make_error(&format!("Invalid String, Utf8 error: {}", e))),
         }
     }
     fn decode_byte(&self, b: u8) -> Result<u8, ReadError> {
-        match b {
-            b'A' ..= b'F' => Ok(b - b'A' + 10),
-            b'a' ..= b'f' => Ok(b - b'a' + 10),
-            b'0' ..= b'9' => Ok(b - b'0'),
-            c => Err(self.make_error(&format!("Illegal hex encoding character: {}", c))),
-        }
+        self.byte_reader.decode_hex(b)
     }
     fn read_blob_esc(&mut self) -> Result<Value, ReadError> {
         let mut buff: Vec<u8> = Vec::new();
@@ -582,9 +858,7 @@ impl<'a, R> CponReader<'a, R>
                 }
                 b'0' ..= b'9' => {
                     self.get_byte()?;
-                    val *= base;
-                    //log::debug!("val: {:x} {}", val, (b as i64));
-                    val += (b - b'0') as u64;
+                    val = self.push_digit(val, base, b - b'0')?;
                     digit_cnt += 1;
                 }
                 b'A' ..= b'F' => {
@@ -592,8 +866,7 @@ impl<'a, R> CponReader<'a, R>
                         break;
                     }
                     self.get_byte()?;
-                    val *= base;
-                    val += (b - b'A') as u64 + 10;
+                    val = self.push_digit(val, base, b - b'A' + 10)?;
                     digit_cnt += 1;
                 }
                 b'a' ..= b'f' => {
@@ -601,8 +874,7 @@ impl<'a, R> CponReader<'a, R>
                         break;
                     }
                     self.get_byte()?;
-                    val *= base;
-                    val += (b - b'a') as u64 + 10;
+                    val = self.push_digit(val, base, b - b'a' + 10)?;
                     digit_cnt += 1;
                 }
                 _ => break,
@@ -611,6 +883,11 @@ impl<'a, R> CponReader<'a, R>
         }
         Ok((val, neg, digit_cnt))
     }
+    fn push_digit(&self, val: u64, base: u64, digit: u8) -> Result<u64, ReadError> {
+        val.checked_mul(base)
+            .and_then(|val| val.checked_add(digit as u64))
+            .ok_or_else(|| self.make_error("Number overflow"))
+    }
     fn read_number(&mut self) -> Result<Value, ReadError>
     {
         let mut mantisa;
@@ -667,7 +944,7 @@ impl<'a, R> CponReader<'a, R>
                     self.get_byte()?;
                     let (n, neg, digit_cnt) = self.read_int(false)?;
                     exponent = n as i64;
-                    if neg == true { exponent = -exponent; }
+                    if neg == true { exponent = exponent.wrapping_neg(); }
                     if digit_cnt == 0 {
                         return Err(self.make_error("Malformed number exponetional part."))
                     }
@@ -678,32 +955,68 @@ impl<'a, R> CponReader<'a, R>
         }
         if is_decimal {
             for _i in 0 .. dec_cnt {
-                mantisa *= 10;
+                mantisa = self.push_digit(mantisa, 10, 0)?;
             }
-            mantisa += decimals;
-            let mut snum = mantisa as i64;
-            if is_neg { snum = -snum }
-            return Ok(Value::from(Decimal::new(snum, (exponent - dec_cnt) as i8)))
+            mantisa = mantisa.checked_add(decimals).ok_or_else(|| self.make_error("Number overflow"))?;
+            let snum = self.to_signed(mantisa, is_neg)?;
+            let exponent = exponent.checked_sub(dec_cnt)
+                .filter(|exp| (i8::MIN as i64 ..= i8::MAX as i64).contains(exp))
+                .ok_or_else(|| self.make_error("Decimal exponent out of range"))?;
+            return Ok(Value::from(Decimal::new(snum, exponent as i8)))
         }
         if is_uint {
             return Ok(Value::from(mantisa))
         }
-        let mut snum = mantisa as i64;
-        if is_neg { snum = -snum }
+        let snum = self.to_signed(mantisa, is_neg)?;
         return Ok(Value::from(snum))
     }
+    fn to_signed(&self, mantisa: u64, is_neg: bool) -> Result<i64, ReadError> {
+        let max = if is_neg { i64::MIN.unsigned_abs() } else { i64::MAX as u64 };
+        if mantisa > max {
+            return Err(self.make_error("Number overflow"))
+        }
+        Ok(if is_neg { (mantisa as i64).wrapping_neg() } else { mantisa as i64 })
+    }
+    /// Skip to next container item, return `true` if container end was read.
+    /// In strict mode items must be separated by `,`, trailing comma is allowed.
+    fn read_item_separator(&mut self, end: u8, item_count: usize, item_name: &str) -> Result<bool, ReadError> {
+        self.skip_white_insignificant()?;
+        if self.peek_byte() == end {
+            self.get_byte()?;
+            return Ok(true)
+        }
+        if self.strict && item_count > 0 {
+            if self.peek_byte() != b',' {
+                return Err(self.make_error(&format!("Expected ',' or '{}' after {}", char::from(end), item_name)))
+            }
+            self.get_byte()?;
+            self.skip_white_insignificant()?;
+            if self.peek_byte() == end {
+                self.get_byte()?;
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+    fn read_key_separator(&mut self, key_name: &str) -> Result<(), ReadError> {
+        self.skip_white_insignificant()?;
+        if self.strict {
+            if self.peek_byte() != b':' {
+                return Err(self.make_error(&format!("Expected ':' after {}", key_name)))
+            }
+            self.get_byte()?;
+        }
+        Ok(())
+    }
     fn read_list(&mut self) -> Result<Value, ReadError>
     {
         let mut lst = Vec::new();
         self.get_byte()?; // eat '['
         loop {
-            self.skip_white_insignificant()?;
-            let b = self.peek_byte();
-            if b == b']' {
-                self.get_byte()?;
+            if self.read_item_separator(b']', lst.len(), "list item")? {
                 break;
             }
-            let val = self.read()?;
+            let val = self.read_nested()?;
             lst.push(val);
         }
         return Ok(Value::from(lst))
@@ -713,12 +1026,16 @@ impl<'a, R> CponReader<'a, R>
         let mut map: Map = Map::new();
         self.get_byte()?; // eat '{'
         loop {
-            self.skip_white_insignificant()?;
-            let b = self.peek_byte();
-            if b == b'}' {
-                self.get_byte()?;
+            if self.read_item_separator(b'}', map.len(), "map item")? {
                 break;
             }
+            let b = self.peek_byte();
+            if b == 0 {
+                return Err(self.make_error("Unexpected end of stream, expected string key or '}' in map"))
+            }
+            if b != b'"' {
+                return Err(self.make_error(&format!("Invalid char '{}', expected string key or '}}' in map", char::from(b))))
+            }
             let key = self.read_string();
             let skey = match &key {
                 Ok(b) => {
@@ -729,10 +1046,13 @@ impl<'a, R> CponReader<'a, R>
                         _ => return Err(self.make_error("Read MetaMap key internal error")),
                     }
                 },
-                _ => return Err(self.make_error(&format!("Invalid Map key '{}'", b))),
+                Err(err) => return Err(self.make_error(&format!("Invalid Map key, {}", err.msg))),
             };
-            self.skip_white_insignificant()?;
-            let val = self.read()?;
+            if self.strict && map.contains_key(skey.as_str()) {
+                return Err(self.make_error(&format!("Duplicate Map key '{}'", skey)))
+            }
+            self.read_key_separator("map key")?;
+            let val = self.read_nested()?;
             map.insert(skey.to_string(), val);
         }
         return Ok(Value::from(map))
@@ -743,19 +1063,24 @@ impl<'a, R> CponReader<'a, R>
         if b != b'{' {
             return Err(self.make_error("Wrong IMap prefix, '{' expected."))
         }
-        let mut map: BTreeMap<i32, RpcValue> = BTreeMap::new();
+        let mut map: IMap = IMap::new();
         loop {
-            self.skip_white_insignificant()?;
-            let b = self.peek_byte();
-            if b == b'}' {
-                self.get_byte()?;
+            if self.read_item_separator(b'}', map.len(), "imap item")? {
                 break;
             }
             let (k, neg, _) = self.read_int(false)?;
-            let key = if neg == true { k as i64 * -1 } else { k as i64 };
-            self.skip_white_insignificant()?;
-            let val = self.read()?;
-            map.insert(key as i32, val);
+            let key = if self.strict {
+                let key = i64::try_from(k).ok().and_then(|k| i32::try_from(if neg { -k } else { k }).ok());
+                key.ok_or_else(|| self.make_error(&format!("IMap key '{}{}' out of range", if neg { "-" } else { "" }, k)))?
+            } else {
+                (if neg == true { k as i64 * -1 } else { k as i64 }) as i32
+            };
+            if self.strict && map.contains_key(&key) {
+                return Err(self.make_error(&format!("Duplicate IMap key '{}'", key)))
+            }
+            self.read_key_separator("imap key")?;
+            let val = self.read_nested()?;
+            map.insert(key, val);
         }
         return Ok(Value::from(map))
     }
@@ -799,8 +1124,19 @@ impl<'a, R> CponReader<'a, R>
 }
 
 impl<'a, R> Reader for CponReader<'a, R>
-    where R: Read
+    where R: ByteSource
 {
+    fn read(&mut self) -> ReadResult {
+        let rv = self.read_nested()?;
+        if self.strict {
+            self.skip_white_insignificant()?;
+            let b = self.peek_byte();
+            if b != 0 {
+                return Err(self.make_error(&format!("Unexpected char '{}' after value, expected end of input", char::from(b))))
+            }
+        }
+        Ok(rv)
+    }
     fn try_read_meta(&mut self) -> Result<Option<MetaMap>, ReadError> {
         self.skip_white_insignificant()?;
         let b = self.peek_byte();
@@ -810,17 +1146,30 @@ impl<'a, R> Reader for CponReader<'a, R>
         self.get_byte()?;
         let mut map = MetaMap::new();
         loop {
-            self.skip_white_insignificant()?;
-            let b = self.peek_byte();
-            if b == b'>' {
-                self.get_byte()?;
+            if self.read_item_separator(b'>', map.0.len(), "meta item")? {
                 break;
             }
-            let key = self.read()?;
-            self.skip_white_insignificant()?;
-            let val = self.read()?;
-            if key.is_int() {
-                map.insert(key.as_i32(), val);
+            let key = self.read_nested()?;
+            let int_key = match key.value() {
+                Value::Int(i) if self.strict => Some(i32::try_from(*i)
+                    .map_err(|_| self.make_error(&format!("MetaMap key '{}' out of range", i)))?),
+                Value::Int(i) => Some(*i as i32),
+                _ => None,
+            };
+            if self.strict {
+                let duplicate = match (int_key, key.value()) {
+                    (Some(i), _) => map.get(i).is_some(),
+                    (None, Value::String(s)) => map.get(s.as_str()).is_some(),
+                    _ => return Err(self.make_error(&format!("Invalid MetaMap key '{}'", key))),
+                };
+                if duplicate {
+                    return Err(self.make_error(&format!("Duplicate MetaMap key '{}'", key)))
+                }
+            }
+            self.read_key_separator("meta key")?;
+            let val = self.read_nested()?;
+            if let Some(i) = int_key {
+                map.insert(i, val);
             }
             else {
                 map.insert(key.as_str(), val);
@@ -843,7 +1192,8 @@ impl<'a, R> Reader for CponReader<'a, R>
             b't' => self.read_true(),
             b'f' => self.read_false(),
             b'n' => self.read_null(),
-            _ => Err(self.make_error(&format!("Invalid char {}, code: {}", char::from(b), b))),
+            0 => Err(self.make_error("Unexpected end of stream, expected value")),
+            _ => Err(self.make_error(&format!("Invalid char '{}', code: {}, expected value", char::from(b), b))),
         }?;
         Ok(v)
     }
@@ -852,13 +1202,17 @@ impl<'a, R> Reader for CponReader<'a, R>
 #[cfg(test)]
 mod test
 {
-    use crate::{DateTime, MetaMap, RpcValue};
+    use crate::{MetaMap, RpcValue};
     use crate::Decimal;
-    use std::collections::BTreeMap;
-    use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
-    use crate::cpon::CponReader;
-    use crate::reader::Reader;
-    use crate::rpcvalue::Map;
+    #[cfg(feature = "chrono")]
+    use crate::DateTime;
+    #[cfg(feature = "chrono")]
+        use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+    use crate::cpon::{CponReader, CponWriter};
+    use crate::reader::{ReadError, Reader};
+    use crate::writer::Writer;
+    use crate::rpcvalue::{Map, IMap};
+    use crate::cpon::{BlobFormat, CponWriterOptions, UnicodeEscape};
 
     #[test]
     fn test_read() {
@@ -888,41 +1242,50 @@ mod test
         assert_eq!(RpcValue::from_cpon("[").is_err(), true);
 
         assert_eq!(RpcValue::from_cpon("{}").unwrap().to_cpon(), "{}");
+        #[cfg(not(feature = "preserve_order"))]
         assert_eq!(RpcValue::from_cpon(r#"{"foo": 1, "bar":"baz", }"#).unwrap().to_cpon(), r#"{"bar":"baz","foo":1}"#);
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(RpcValue::from_cpon(r#"{"foo": 1, "bar":"baz", }"#).unwrap().to_cpon(), r#"{"foo":1,"bar":"baz"}"#);
         assert_eq!(RpcValue::from_cpon("{").is_err(), true);
 
         assert_eq!(RpcValue::from_cpon("i{}").unwrap().to_cpon(), "i{}");
+        #[cfg(not(feature = "preserve_order"))]
         assert_eq!(RpcValue::from_cpon(r#"i{1: "foo", -1:"bar", 0:"baz", }"#).unwrap().to_cpon(), r#"i{-1:"bar",0:"baz",1:"foo"}"#);
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(RpcValue::from_cpon(r#"i{1: "foo", -1:"bar", 0:"baz", }"#).unwrap().to_cpon(), r#"i{1:"foo",-1:"bar",0:"baz"}"#);
         assert_eq!(RpcValue::from_cpon("i{").is_err(), true);
 
-        let ndt = NaiveDateTime::new(NaiveDate::from_ymd(2022, 01, 02), NaiveTime::from_hms_milli(12, 59, 06, 0));
-        assert_eq!(RpcValue::from_cpon(r#"d"2022-01-02T12:59:06Z""#).unwrap().as_datetime(), DateTime::from_naive_datetime(&ndt));
-        let dt = chrono::DateTime::<Utc>::from_utc(ndt, Utc);
-        assert_eq!(RpcValue::from_cpon(r#"d"2022-01-02T12:59:06Z""#).unwrap().as_datetime(), DateTime::from_datetime(&dt));
-
-        let minute = 60;
-        let hour = 60 * minute;
-
-        let dt_str = r#"d"2021-11-08T01:02:03+05""#;
-        let dt = FixedOffset::east(5 * hour)
-            .ymd(2021, 11, 08)
-            .and_hms(1, 2, 3);
-        assert_eq!(RpcValue::from_cpon(dt_str).unwrap().as_datetime(), DateTime::from_datetime(&dt));
-        assert_eq!(RpcValue::from_cpon(dt_str).unwrap().to_cpon(), dt_str.to_string());
-
-        let dt_str = r#"d"2021-11-08T01:02:03-0815""#;
-        let dt = FixedOffset::west(8 * hour + 15 * minute)
-            .ymd(2021, 11, 08)
-            .and_hms(1, 2, 3);
-        assert_eq!(RpcValue::from_cpon(dt_str).unwrap().as_datetime(), DateTime::from_datetime(&dt));
-        assert_eq!(RpcValue::from_cpon(dt_str).unwrap().to_cpon(), dt_str.to_string());
-
-        let dt_str = r#"d"2021-11-08T01:02:03.456-0815""#;
-        let dt = FixedOffset::west(8 * hour + 15 * minute)
-            .ymd(2021, 11, 08)
-            .and_hms_milli(1, 2, 3, 456);
-        assert_eq!(RpcValue::from_cpon(dt_str).unwrap().as_datetime(), DateTime::from_datetime(&dt));
-        assert_eq!(RpcValue::from_cpon(dt_str).unwrap().to_cpon(), dt_str.to_string());
+        #[cfg(feature = "chrono")]
+        {
+            let ndt = NaiveDateTime::new(NaiveDate::from_ymd(2022, 01, 02), NaiveTime::from_hms_milli(12, 59, 06, 0));
+            assert_eq!(RpcValue::from_cpon(r#"d"2022-01-02T12:59:06Z""#).unwrap().as_datetime(), DateTime::from_naive_datetime(&ndt));
+            let dt = chrono::DateTime::<Utc>::from_utc(ndt, Utc);
+            assert_eq!(RpcValue::from_cpon(r#"d"2022-01-02T12:59:06Z""#).unwrap().as_datetime(), DateTime::from_datetime(&dt));
+
+            let minute = 60;
+            let hour = 60 * minute;
+
+            let dt_str = r#"d"2021-11-08T01:02:03+05""#;
+            let dt = FixedOffset::east(5 * hour)
+                .ymd(2021, 11, 08)
+                .and_hms(1, 2, 3);
+            assert_eq!(RpcValue::from_cpon(dt_str).unwrap().as_datetime(), DateTime::from_datetime(&dt));
+            assert_eq!(RpcValue::from_cpon(dt_str).unwrap().to_cpon(), dt_str.to_string());
+
+            let dt_str = r#"d"2021-11-08T01:02:03-0815""#;
+            let dt = FixedOffset::west(8 * hour + 15 * minute)
+                .ymd(2021, 11, 08)
+                .and_hms(1, 2, 3);
+            assert_eq!(RpcValue::from_cpon(dt_str).unwrap().as_datetime(), DateTime::from_datetime(&dt));
+            assert_eq!(RpcValue::from_cpon(dt_str).unwrap().to_cpon(), dt_str.to_string());
+
+            let dt_str = r#"d"2021-11-08T01:02:03.456-0815""#;
+            let dt = FixedOffset::west(8 * hour + 15 * minute)
+                .ymd(2021, 11, 08)
+                .and_hms_milli(1, 2, 3, 456);
+            assert_eq!(RpcValue::from_cpon(dt_str).unwrap().as_datetime(), DateTime::from_datetime(&dt));
+            assert_eq!(RpcValue::from_cpon(dt_str).unwrap().to_cpon(), dt_str.to_string());
+        }
 
         let lst1 = vec![RpcValue::from(123), RpcValue::from("foo")];
         let cpon = r#"[123 , "foo"]"#;
@@ -936,7 +1299,7 @@ mod test
         let cpon = r#"{"foo": 123,"bar":"baz"}"#;
         assert_eq!(RpcValue::from_cpon(cpon).unwrap().as_map(), &map);
 
-        let mut map: BTreeMap<i32, RpcValue> = BTreeMap::new();
+        let mut map: IMap = IMap::new();
         map.insert(1, RpcValue::from(123));
         map.insert(2, RpcValue::from("baz"));
         let cpon = r#"i{1: 123,2:"baz"}"#;
@@ -957,4 +1320,135 @@ mod test
         //assert_eq!(cpon1, cpon2);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_write_options() {
+        let write = |cpon: &str, options: &CponWriterOptions| RpcValue::from_cpon(cpon).unwrap().to_cpon_with_options(options).unwrap();
+        let cpon = r#"<1:2>{"a":[1,2,3],"b":{"c":[4,5]}}"#;
+        assert_eq!(write(cpon, &CponWriterOptions::default()), cpon);
+        let opts = CponWriterOptions { indent: "  ".into(), ..Default::default() };
+        assert_eq!(write(cpon, &opts), "<1:2>{\n  \"a\":[1, 2, 3],\n  \"b\":{\n    \"c\":[4, 5]\n  }\n}");
+        // single line thresholds without max_line_width
+        assert_eq!(write(r#"[1,2,3,4,5,6,7,8,9,10]"#, &opts), "[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]");
+        assert_eq!(write(r#"[1,2,3,4,5,6,7,8,9,10,11]"#, &opts), "[\n  1,\n  2,\n  3,\n  4,\n  5,\n  6,\n  7,\n  8,\n  9,\n  10,\n  11\n]");
+        assert_eq!(write(r#"i{1:1,2:2,3:3,4:4}"#, &opts), "i{1:1, 2:2, 3:3, 4:4}");
+        assert_eq!(write(r#"i{1:1,2:2,3:3,4:4,5:5}"#, &opts), "i{\n  1:1,\n  2:2,\n  3:3,\n  4:4,\n  5:5\n}");
+        assert_eq!(write(r#"{"a":1,"b":2,"c":3,"d":4}"#, &opts), r#"{"a":1, "b":2, "c":3, "d":4}"#);
+        assert_eq!(write(r#"{"a":1,"b":2,"c":3,"d":4,"e":5}"#, &opts), "{\n  \"a\":1,\n  \"b\":2,\n  \"c\":3,\n  \"d\":4,\n  \"e\":5\n}");
+        assert_eq!(write(r#"<1:1,2:2,3:3,4:4,5:5>null"#, &opts), "<1:1, 2:2, 3:3, 4:4, 5:5>null");
+        assert_eq!(write(r#"<1:1,2:2,3:3,4:4,5:5,6:6>null"#, &opts), "<\n  1:1,\n  2:2,\n  3:3,\n  4:4,\n  5:5,\n  6:6\n>null");
+        let opts = CponWriterOptions { indent: "  ".into(), compact: true, trailing_commas: true, ..Default::default() };
+        assert_eq!(write(cpon, &opts), "<1:2>{\n  \"a\":[1,2,3],\n  \"b\":{\n    \"c\":[4,5],\n  },\n}");
+        let opts = CponWriterOptions { indent: "  ".into(), max_line_width: Some(20), ..Default::default() };
+        assert_eq!(write(cpon, &opts), "<1:2>{\n  \"a\":[1, 2, 3],\n  \"b\":{\"c\":[4, 5]}\n}");
+        let opts = CponWriterOptions { indent: "  ".into(), max_line_width: Some(100), ..Default::default() };
+        assert_eq!(write(cpon, &opts), r#"<1:2>{"a":[1, 2, 3], "b":{"c":[4, 5]}}"#);
+        // shared subtrees are measured once, lengths are not reused by next write
+        let lst = RpcValue::from_cpon(r#"<"k":"v">[1,2]"#).unwrap();
+        let rv = RpcValue::from(vec![lst.clone(), lst.clone(), lst]);
+        let opts = CponWriterOptions { indent: "  ".into(), max_line_width: Some(20), ..Default::default() };
+        let mut buff = Vec::new();
+        let mut wr = CponWriter::with_options(&mut buff, opts);
+        wr.write(&rv).unwrap();
+        wr.write(&RpcValue::from_cpon("[[1,2,3]]").unwrap()).unwrap();
+        assert_eq!(String::from_utf8(buff).unwrap(), "[\n  <\"k\":\"v\">[1, 2],\n  <\"k\":\"v\">[1, 2],\n  <\"k\":\"v\">[1, 2]\n][[1, 2, 3]]");
+
+        let opts = CponWriterOptions { ascii_only: true, blob_format: BlobFormat::Hex, ..Default::default() };
+        assert_eq!(write(r#"["žluť 😀", b"a\00\ff"]"#, &opts), r#"["\u017elu\u0165 \ud83d\ude00",x"6100ff"]"#);
+        assert_eq!(RpcValue::from_cpon(r#"x"6100ff""#).unwrap().as_blob(), b"a\x00\xff");
+        assert_eq!(RpcValue::from_cpon(r#""\u017elu\u0165 \ud83d\ude00""#).unwrap().as_str(), "žluť 😀");
+
+        let opts = CponWriterOptions { sort_keys: true, ..Default::default() };
+        let mut map = Map::new();
+        map.insert("b".into(), RpcValue::from(1));
+        map.insert("a".into(), RpcValue::from(2));
+        let mut imap = IMap::new();
+        imap.insert(2, RpcValue::from(1));
+        imap.insert(1, RpcValue::from(2));
+        assert_eq!(RpcValue::from(vec![RpcValue::from(map), RpcValue::from(imap)]).to_cpon_with_options(&opts).unwrap(), r#"[{"a":2,"b":1},i{1:2,2:1}]"#);
+    }
+
+    #[test]
+    fn test_unicode_escapes() {
+        assert_eq!(RpcValue::from_cpon(r#""žluť""#).unwrap().as_str(), "žluť");
+        assert_eq!(RpcValue::from_cpon(r#""😀""#).unwrap().as_str(), "😀");
+        assert_eq!(RpcValue::from_cpon(r#""\u{1f600}\u{41}""#).unwrap().as_str(), "😀A");
+        assert_eq!(RpcValue::from_cpon(r#""\b\f\/""#).unwrap().as_str(), "\x08\x0c/");
+        assert!(RpcValue::from_cpon(r#""\q""#).is_err());
+        assert!(RpcValue::from_cpon(r#""\u12""#).is_err());
+        assert!(RpcValue::from_cpon(r#""\ud83d""#).is_err());
+        assert!(RpcValue::from_cpon(r#""\ude00""#).is_err());
+        assert!(RpcValue::from_cpon(r#""\ud83dA""#).is_err());
+        assert!(RpcValue::from_cpon(r#""\u{}""#).is_err());
+        assert!(RpcValue::from_cpon(r#""\u{110000}""#).is_err());
+        assert!(RpcValue::from_cpon(r#""\u{d800}""#).is_err());
+
+        let rv = RpcValue::from("žluť 😀\x01\n");
+        let opts = CponWriterOptions { ascii_only: true, ..Default::default() };
+        let cpon = rv.to_cpon_with_options(&opts).unwrap();
+        assert_eq!(cpon, r#""\u017elu\u0165 \ud83d\ude00\u0001\n""#);
+        assert_eq!(RpcValue::from_cpon(&cpon).unwrap(), rv);
+        let opts = CponWriterOptions { ascii_only: true, unicode_escape: UnicodeEscape::Braced, ..Default::default() };
+        let cpon = rv.to_cpon_with_options(&opts).unwrap();
+        assert_eq!(cpon, r#""\u{17e}lu\u{165} \u{1f600}\u{1}\n""#);
+        assert_eq!(RpcValue::from_cpon(&cpon).unwrap(), rv);
+    }
+
+    #[test]
+    fn test_strict() {
+        for cpon in [r#"[1 2 3]"#, r#"{"a" 1}"#, r#"[,,1::2]"#, r#"i{1 2}"#, r#"<1 2>3"#, r#"[1,,2]"#, r#"[,]"#,
+                     r#"{"a":1,"a":2}"#, r#"i{1:1,1:2}"#, r#"<1:1,1:2>3"#, r#"<[1]:2>3"#, r#"1 2"#, r#"[1]]"#, r#"{"a":1}x"#,
+                     r#"i{4294967297:1}"#, r#"i{-2147483649:1}"#, r#"i{1:1,4294967297:2}"#, r#"<4294967297:1>2"#, r#"<1:1,4294967297:2>3"#].iter() {
+            assert!(RpcValue::from_cpon(cpon).is_ok(), "lenient: {}", cpon);
+            assert!(RpcValue::from_cpon_strict(cpon).is_err(), "strict: {}", cpon);
+        }
+        for cpon in [r#"[1, 2 , 3]"#, r#"[1,2,]"#, r#"{ "a" : 1, "b":2, }"#, r#"i{1:2}"#, r#"i{-2147483648:1,2147483647:2}"#, r#"<1:2,"a":3>[]"#, "[] // comment\n", r#"[] /* comment */ "#, "  1  "].iter() {
+            assert_eq!(RpcValue::from_cpon_strict(cpon).unwrap(), RpcValue::from_cpon(cpon).unwrap(), "strict: {}", cpon);
+        }
+        assert_eq!(RpcValue::from_cpon(r#"{"a":1,"a":2}"#).unwrap().to_cpon(), r#"{"a":2}"#);
+
+        // error inside value does not disable end of input check of next read
+        let mut buff = r#"[1,tru] 2 3"#.as_bytes();
+        let mut rd = CponReader::new(&mut buff);
+        rd.set_strict(true);
+        assert!(rd.read().is_err());
+        assert_eq!(rd.read().unwrap_err().msg, "Unexpected char '3' after value, expected end of input");
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let err = RpcValue::from_cpon("[1,\n\tfoo]").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 4));
+        assert_eq!(err.diagnostic("[1,\n\tfoo]").to_string(), "error: Incomplete 'false' literal.\n --> 2:4\n  |\n2 | \tfoo]\n  | \t  ^");
+        let src = "{\r\n  \"ip\": \"1.2.3.4\",\r\n  \"mask\" 24\r\n}";
+        let err = RpcValue::from_cpon_strict(src).unwrap_err();
+        assert_eq!(err.msg, "Expected ':' after map key");
+        assert_eq!(err.diagnostic(src).file_name("device.cpon").to_string(),
+                   "error: Expected ':' after map key\n --> device.cpon:3:10\n  |\n3 |   \"mask\" 24\n  |          ^");
+        let err = RpcValue::from_cpon("[1, 2").unwrap_err();
+        assert_eq!(err.msg, "Unexpected end of stream, expected value");
+        assert_eq!((err.line, err.col), (1, 6));
+        let err = ReadError { msg: "foo".to_string(), line: 1, col: 0 };
+        assert_eq!(err.diagnostic("[1]").to_string(), "error: foo\n --> 1:0\n  |\n1 | [1]\n  | ^");
+    }
+
+    #[test]
+    fn test_number_overflow() {
+        assert_eq!(RpcValue::from_cpon("-9223372036854775808").unwrap().as_i64(), i64::MIN);
+        assert_eq!(RpcValue::from_cpon("18446744073709551615u").unwrap().as_u64(), u64::MAX);
+        assert_eq!(RpcValue::from_cpon("1e-128").unwrap().as_decimal(), Decimal::new(1, -128));
+        assert_eq!(RpcValue::from_cpon("18446744073709551616u").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("0x10000000000000000").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("1.84467440737095516150").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("9223372036854775807").unwrap().as_i64(), i64::MAX);
+        assert_eq!(RpcValue::from_cpon("9223372036854775808").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("18446744073709551615").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("-18446744073709551615").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("-9223372036854775809").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("-92233720368547758.08").unwrap().as_decimal(), Decimal::new(i64::MIN, -2));
+        assert_eq!(RpcValue::from_cpon("92233720368547758.08").unwrap_err().msg, "Number overflow");
+        assert_eq!(RpcValue::from_cpon("1e128").unwrap_err().msg, "Decimal exponent out of range");
+        assert_eq!(RpcValue::from_cpon("1e-9223372036854775808").unwrap_err().msg, "Decimal exponent out of range");
+        assert_eq!(Decimal::new(5, i8::MAX).to_cpon_string(), "5e127");
+        assert_eq!(Decimal::new(-5, i8::MIN).to_cpon_string(), "-5e-128");
+    }
+}
@@ -0,0 +1,476 @@
+//! Lossless Cpon document model.
+//!
+//! `CponDocument` keeps comments, white space, separators and key order of the parsed text,
+//! `to_string()` returns the source text unchanged. Values can be edited in place, the rest
+//! of the document is left untouched.
+//! ```
+//! use chainpack::RpcValue;
+//! use chainpack::cpondoc::CponDocument;
+//! let mut doc = CponDocument::parse("{\n  // device config\n  \"device\": {\"ip\": \"10.0.0.1\"}\n}").unwrap();
+//! doc.set(&[RpcValue::from("device"), RpcValue::from("ip")], &RpcValue::from("10.0.0.2")).unwrap();
+//! assert_eq!(doc.to_string(), "{\n  // device config\n  \"device\": {\"ip\": \"10.0.0.2\"}\n}");
+//! ```
+use core::fmt;
+use crate::{RpcValue, Value};
+use crate::reader::{ReadError, ReadResult};
+use crate::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CponDocument {
+    leading: String,
+    root: Node,
+    trailing: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Node {
+    /// meta container and trivia between meta and value
+    meta: Option<(Container, String)>,
+    value: NodeValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum NodeValue {
+    /// source text of scalar value
+    Scalar(String),
+    Container(Container),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ContainerKind {
+    List,
+    Map,
+    IMap,
+    Meta,
+}
+
+impl ContainerKind {
+    fn open(&self) -> &'static str {
+        match self {
+            ContainerKind::List => "[",
+            ContainerKind::Map => "{",
+            ContainerKind::IMap => "i{",
+            ContainerKind::Meta => "<",
+        }
+    }
+    fn close(&self) -> u8 {
+        match self {
+            ContainerKind::List => b']',
+            ContainerKind::Map | ContainerKind::IMap => b'}',
+            ContainerKind::Meta => b'>',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Container {
+    kind: ContainerKind,
+    items: Vec<Item>,
+    /// trivia before closing bracket
+    trailing: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Item {
+    /// trivia before item including separator from previous one
+    leading: String,
+    /// key source text and trivia between key and value including ':'
+    key: Option<(String, String)>,
+    value: Node,
+}
+
+impl Item {
+    fn matches(&self, kind: ContainerKind, ix: usize, segment: &RpcValue) -> bool {
+        match (kind, segment.value()) {
+            (ContainerKind::List, Value::Int(i)) => *i >= 0 && *i as usize == ix,
+            (_, _) => match &self.key {
+                Some((key, _)) => match RpcValue::from_cpon(key) {
+                    Ok(key) => match (key.value(), segment.value()) {
+                        (Value::String(k), Value::String(s)) => k == s,
+                        (Value::Int(k), Value::Int(s)) => k == s,
+                        _ => false,
+                    },
+                    Err(_) => false,
+                },
+                None => false,
+            },
+        }
+    }
+}
+
+impl CponDocument {
+    pub fn parse(src: &str) -> Result<Self, ReadError> {
+        let mut parser = Parser { src: src.as_bytes(), pos: 0 };
+        let leading = parser.trivia()?;
+        let root = parser.node()?;
+        let trailing = parser.trivia()?;
+        if parser.pos < src.len() {
+            return Err(parser.error("Unexpected char after value, expected end of input"))
+        }
+        Ok(CponDocument { leading, root, trailing })
+    }
+    pub fn to_rpcvalue(&self) -> ReadResult {
+        RpcValue::from_cpon(&self.to_string())
+    }
+    /// Value on `path`, segments are `String` for `Map` keys, `Int` for `IMap` keys and `List` indexes
+    pub fn get(&self, path: &[RpcValue]) -> Option<RpcValue> {
+        let mut node = &self.root;
+        for segment in path {
+            node = node.child(segment)?;
+        }
+        let mut s = String::new();
+        node.write(&mut s);
+        RpcValue::from_cpon(&s).ok()
+    }
+    /// Set value on `path`, missing `Map` and `IMap` key is appended, `List` can be extended by one item.
+    /// Replaced value keeps its leading comments and white space, the new one is written as single line Cpon.
+    pub fn set(&mut self, path: &[RpcValue], value: &RpcValue) -> crate::Result<()> {
+        let new_node = Node::from_rpcvalue(value)?;
+        let (last, parent_path) = match path.split_last() {
+            None => {
+                self.root = new_node;
+                return Ok(())
+            }
+            Some(p) => p,
+        };
+        let container = self.container_mut(parent_path)?;
+        let kind = container.kind;
+        if let Some(item) = container.items.iter_mut().enumerate().find(|(ix, item)| item.matches(kind, *ix, last)).map(|(_, item)| item) {
+            item.value = new_node;
+            return Ok(())
+        }
+        let key = match (kind, last.value()) {
+            (ContainerKind::List, Value::Int(i)) if *i as usize == container.items.len() => None,
+            (ContainerKind::Map, Value::String(_)) => Some(last.to_cpon()),
+            (ContainerKind::IMap, Value::Int(_)) => Some(last.to_cpon()),
+            _ => return Err(format!("Cannot set key {} in {}", last, kind.open()).into()),
+        };
+        let leading = container.next_item_leading();
+        container.items.push(Item { leading, key: key.map(|k| (k, ":".to_string())), value: new_node });
+        Ok(())
+    }
+    /// Remove value on `path`, return `true` if it was found
+    pub fn remove(&mut self, path: &[RpcValue]) -> crate::Result<bool> {
+        let (last, parent_path) = match path.split_last() {
+            None => return Err("Cannot remove document root".into()),
+            Some(p) => p,
+        };
+        let container = self.container_mut(parent_path)?;
+        let kind = container.kind;
+        match container.items.iter().enumerate().position(|(ix, item)| item.matches(kind, ix, last)) {
+            None => Ok(false),
+            Some(ix) => {
+                // trivia up to the first new line belongs to the previous item, like `, // comment`,
+                // the rest belongs to the item itself and is removed with it
+                fn split(trivia: &str) -> (&str, &str) {
+                    match trivia.find('\n') {
+                        Some(nl) => trivia.split_at(nl + 1),
+                        None => (trivia, ""),
+                    }
+                }
+                let removed = container.items.remove(ix);
+                let (removed_head, _) = split(&removed.leading);
+                match container.items.get_mut(ix) {
+                    Some(next) => next.leading = format!("{}{}", removed_head, split(&next.leading).1),
+                    None if removed.leading.contains('\n') => {
+                        container.trailing = format!("{}{}", removed_head, split(&container.trailing).1)
+                    }
+                    None => {}
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn container_mut(&mut self, path: &[RpcValue]) -> crate::Result<&mut Container> {
+        let mut node = &mut self.root;
+        for segment in path {
+            node = match node.child_mut(segment) {
+                Some(node) => node,
+                None => return Err(format!("Path {} not found", RpcValue::from(path.to_vec())).into()),
+            }
+        }
+        match &mut node.value {
+            NodeValue::Container(container) => Ok(container),
+            NodeValue::Scalar(_) => Err(format!("Path {} is not a container", RpcValue::from(path.to_vec())).into()),
+        }
+    }
+}
+
+impl Container {
+    /// Separator and indentation for item appended after the last one
+    fn next_item_leading(&self) -> String {
+        let last = match self.items.last() {
+            None => return String::new(),
+            Some(last) => last,
+        };
+        let comma = if self.items.len() == 1 || self.items[1 ..].iter().any(|item| item.leading.contains(',')) { "," } else { "" };
+        let space = match last.leading.rfind('\n') {
+            Some(ix) => {
+                let indent: String = last.leading[ix + 1 ..].chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+                format!("\n{}", indent)
+            }
+            None if self.items.len() == 1 || last.leading.ends_with(' ') => " ".to_string(),
+            None => String::new(),
+        };
+        format!("{}{}", comma, space)
+    }
+    fn write(&self, s: &mut String) {
+        s.push_str(self.kind.open());
+        for item in self.items.iter() {
+            s.push_str(&item.leading);
+            if let Some((key, separator)) = &item.key {
+                s.push_str(key);
+                s.push_str(separator);
+            }
+            item.value.write(s);
+        }
+        s.push_str(&self.trailing);
+        s.push(char::from(self.kind.close()));
+    }
+}
+
+impl Node {
+    fn from_rpcvalue(rv: &RpcValue) -> crate::Result<Node> {
+        let cpon = rv.to_cpon();
+        let mut parser = Parser { src: cpon.as_bytes(), pos: 0 };
+        Ok(parser.node()?)
+    }
+    fn child(&self, segment: &RpcValue) -> Option<&Node> {
+        match &self.value {
+            NodeValue::Container(c) => c.items.iter().enumerate().find(|(ix, item)| item.matches(c.kind, *ix, segment)).map(|(_, item)| &item.value),
+            NodeValue::Scalar(_) => None,
+        }
+    }
+    fn child_mut(&mut self, segment: &RpcValue) -> Option<&mut Node> {
+        match &mut self.value {
+            NodeValue::Container(c) => {
+                let kind = c.kind;
+                c.items.iter_mut().enumerate().find(|(ix, item)| item.matches(kind, *ix, segment)).map(|(_, item)| &mut item.value)
+            }
+            NodeValue::Scalar(_) => None,
+        }
+    }
+    fn write(&self, s: &mut String) {
+        if let Some((meta, trivia)) = &self.meta {
+            meta.write(s);
+            s.push_str(trivia);
+        }
+        match &self.value {
+            NodeValue::Scalar(text) => s.push_str(text),
+            NodeValue::Container(c) => c.write(s),
+        }
+    }
+}
+
+impl fmt::Display for CponDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = self.leading.clone();
+        self.root.write(&mut s);
+        s.push_str(&self.trailing);
+        f.write_str(&s)
+    }
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> u8 {
+        self.src.get(self.pos).copied().unwrap_or(0)
+    }
+    fn text(&self, start: usize) -> String {
+        String::from_utf8_lossy(&self.src[start .. self.pos]).to_string()
+    }
+    fn error(&self, msg: &str) -> ReadError {
+        self.error_at(self.pos, msg)
+    }
+    fn error_at(&self, pos: usize, msg: &str) -> ReadError {
+        let before = &self.src[.. pos.min(self.src.len())];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let line_start = before.iter().rposition(|b| *b == b'\n').map(|ix| ix + 1).unwrap_or(0);
+        ReadError { msg: msg.to_string(), line, col: pos - line_start + 1 }
+    }
+    /// White space, comments and separators
+    fn trivia(&mut self) -> Result<String, ReadError> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                b' ' | b'\t' | b'\r' | b'\n' | b',' | b':' => self.pos += 1,
+                b'/' => {
+                    match self.src.get(self.pos + 1) {
+                        Some(b'/') => {
+                            while self.peek() != 0 && self.peek() != b'\n' {
+                                self.pos += 1;
+                            }
+                        }
+                        Some(b'*') => {
+                            let end = self.src[self.pos + 2 ..].windows(2).position(|w| w == b"*/");
+                            match end {
+                                Some(ix) => self.pos += 2 + ix + 2,
+                                None => return Err(self.error("Unterminated comment")),
+                            }
+                        }
+                        _ => return Err(self.error("Malformed comment")),
+                    }
+                }
+                _ => break,
+            }
+        }
+        Ok(self.text(start))
+    }
+    fn node(&mut self) -> Result<Node, ReadError> {
+        let meta = if self.peek() == b'<' {
+            let meta = self.container(ContainerKind::Meta)?;
+            Some((meta, self.trivia()?))
+        } else {
+            None
+        };
+        let value = match self.peek() {
+            b'[' => NodeValue::Container(self.container(ContainerKind::List)?),
+            b'{' => NodeValue::Container(self.container(ContainerKind::Map)?),
+            b'i' => NodeValue::Container(self.container(ContainerKind::IMap)?),
+            _ => NodeValue::Scalar(self.scalar()?.0),
+        };
+        Ok(Node { meta, value })
+    }
+    fn container(&mut self, kind: ContainerKind) -> Result<Container, ReadError> {
+        let open = kind.open().as_bytes();
+        if !self.src[self.pos ..].starts_with(open) {
+            return Err(self.error(&format!("Expected '{}'", kind.open())))
+        }
+        self.pos += open.len();
+        let mut items = Vec::new();
+        loop {
+            let leading = self.trivia()?;
+            match self.peek() {
+                0 => return Err(self.error(&format!("Unexpected end of stream, expected '{}'", char::from(kind.close())))),
+                b if b == kind.close() => {
+                    self.pos += 1;
+                    return Ok(Container { kind, items, trailing: leading })
+                }
+                _ => {}
+            }
+            let key = match kind {
+                ContainerKind::List => None,
+                _ => {
+                    let (key, rv) = self.scalar()?;
+                    let valid = match kind {
+                        ContainerKind::Map => rv.is_string(),
+                        ContainerKind::IMap => rv.is_int(),
+                        _ => rv.is_string() || rv.is_int(),
+                    };
+                    if !valid {
+                        return Err(self.error(&format!("Invalid key {}", key)))
+                    }
+                    Some((key, self.trivia()?))
+                }
+            };
+            let value = self.node()?;
+            items.push(Item { leading, key, value });
+        }
+    }
+    fn scalar(&mut self) -> Result<(String, RpcValue), ReadError> {
+        let start = self.pos;
+        match self.peek() {
+            b'"' => self.quoted()?,
+            b'b' | b'x' | b'd' if self.src.get(self.pos + 1) == Some(&b'"') => {
+                self.pos += 1;
+                self.quoted()?;
+            }
+            b'0' ..= b'9' | b'+' | b'-' | b'a' ..= b'z' => {
+                while matches!(self.peek(), b'0' ..= b'9' | b'a' ..= b'z' | b'A' ..= b'Z' | b'+' | b'-' | b'.') {
+                    self.pos += 1;
+                }
+            }
+            0 => return Err(self.error("Unexpected end of stream, expected value")),
+            b => return Err(self.error(&format!("Invalid char '{}', expected value", char::from(b)))),
+        }
+        let text = self.text(start);
+        match RpcValue::from_cpon_strict(&text) {
+            Ok(rv) => Ok((text, rv)),
+            Err(err) => Err(self.error_at(start, &err.msg)),
+        }
+    }
+    fn quoted(&mut self) -> Result<(), ReadError> {
+        self.pos += 1; // eat "
+        loop {
+            match self.peek() {
+                0 => return Err(self.error("Unexpected end of stream, expected '\"'")),
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return Ok(())
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::RpcValue;
+    use crate::cpondoc::CponDocument;
+
+    const CONFIG: &str = r#"// device configuration
+<"version":1 /* format */>{
+    "device": {
+        "ip": "10.0.0.1", // static
+        "mask": 24,
+    },
+    /* users */
+    "users": ["admin" "guest"],
+    "ids": i{1:"a",2:"b"}
+}
+"#;
+
+    fn path(segments: &[&str]) -> Vec<RpcValue> {
+        segments.iter().map(|s| RpcValue::from(*s)).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let doc = CponDocument::parse(CONFIG).unwrap();
+        assert_eq!(doc.to_string(), CONFIG);
+        assert_eq!(doc.to_rpcvalue().unwrap(), RpcValue::from_cpon(CONFIG).unwrap());
+        assert_eq!(doc.get(&path(&["device", "mask"])), Some(RpcValue::from(24)));
+        assert_eq!(doc.get(&[RpcValue::from("users"), RpcValue::from(1)]), Some(RpcValue::from("guest")));
+        assert_eq!(doc.get(&[RpcValue::from("ids"), RpcValue::from(2)]), Some(RpcValue::from("b")));
+        assert_eq!(doc.get(&path(&["device", "gw"])), None);
+        assert!(CponDocument::parse("[1, 2").is_err());
+        assert!(CponDocument::parse("{1:2}").is_err());
+        assert!(CponDocument::parse("[1] x").is_err());
+        assert!(CponDocument::parse("[1 /* 2 ]").is_err());
+    }
+
+    #[test]
+    fn edit() {
+        let mut doc = CponDocument::parse(CONFIG).unwrap();
+        doc.set(&path(&["device", "ip"]), &RpcValue::from("10.0.0.2")).unwrap();
+        doc.set(&path(&["device", "gw"]), &RpcValue::from("10.0.0.254")).unwrap();
+        doc.set(&[RpcValue::from("users"), RpcValue::from(2)], &RpcValue::from("root")).unwrap();
+        doc.set(&[RpcValue::from("ids"), RpcValue::from(3)], &RpcValue::from_cpon("[1,2]").unwrap()).unwrap();
+        assert!(doc.set(&[RpcValue::from("users"), RpcValue::from(5)], &RpcValue::from(1)).is_err());
+        assert!(doc.set(&path(&["device", "ip", "foo"]), &RpcValue::from(1)).is_err());
+        assert!(doc.remove(&path(&["device", "mask"])).unwrap());
+        assert!(!doc.remove(&path(&["device", "mask"])).unwrap());
+        assert_eq!(doc.to_string(), r#"// device configuration
+<"version":1 /* format */>{
+    "device": {
+        "ip": "10.0.0.2", // static
+        "gw":"10.0.0.254",
+    },
+    /* users */
+    "users": ["admin" "guest" "root"],
+    "ids": i{1:"a",2:"b",3:[1,2]}
+}
+"#);
+        assert!(doc.remove(&path(&["device", "ip"])).unwrap());
+        assert_eq!(doc.get(&path(&["device"])).unwrap().to_cpon(), r#"{"gw":"10.0.0.254"}"#);
+        doc.set(&[], &RpcValue::from(42)).unwrap();
+        assert_eq!(doc.to_string(), "// device configuration\n42\n");
+    }
+}
@@ -1,8 +1,10 @@
 //use crate::rpcvalue::RpcValue;
 
-use std::cmp::Ordering;
-use std::fmt;
+use core::cmp::Ordering;
+use core::fmt;
+#[cfg(feature = "chrono")]
 use chrono::{FixedOffset, NaiveDateTime, Offset};
+use crate::prelude::*;
 
 /// msec: 57, tz: 7;
 /// tz is stored as signed count of quarters of hour (15 min)
@@ -33,6 +35,7 @@ impl DateTime {
     //    DateTime::from_epoch_msec(0)
     //}
     //pub fn is_valid(&self) -> bool { }
+    #[cfg(all(feature = "chrono", feature = "std"))]
     pub fn now() -> DateTime {
         let dt = chrono::offset::Local::now();
         let msec = dt.naive_utc().timestamp_millis();
@@ -40,15 +43,22 @@ impl DateTime {
         DateTime::from_epoch_msec_tz(msec, offset)
     }
 
+    #[cfg(feature = "chrono")]
     pub fn from_datetime<Tz: chrono::TimeZone>(dt: &chrono::DateTime<Tz>) -> DateTime {
         let msec = dt.naive_utc().timestamp_millis();
         let offset = dt.offset().fix().local_minus_utc();
         DateTime::from_epoch_msec_tz(msec, offset)
     }
+    #[cfg(feature = "chrono")]
     pub fn from_naive_datetime(dt: &chrono::NaiveDateTime) -> DateTime {
         let msec = dt.timestamp_millis();
         DateTime::from_epoch_msec(msec)
     }
+    /// `None` if `epoch_msec` does not fit 57 bits left for it
+    pub(crate) fn checked_from_epoch_msec_tz(epoch_msec: i64, utc_offset_sec: i32) -> Option<DateTime> {
+        epoch_msec.checked_mul(TZ_MASK + 1)?;
+        Some(DateTime::from_epoch_msec_tz(epoch_msec, utc_offset_sec))
+    }
     pub fn from_epoch_msec_tz(epoch_msec: i64, utc_offset_sec: i32) -> DateTime {
         let mut msec = epoch_msec;
         // offset in quarters of hour
@@ -62,10 +72,9 @@ impl DateTime {
     }
     pub fn from_iso_str(iso_str: &str) -> Result<DateTime, String> {
             const PATTERN: &'static str = "2020-02-03T11:59:43";
-            if iso_str.len() >= PATTERN.len() {
+            if iso_str.len() >= PATTERN.len() && iso_str.as_bytes()[10] == b'T' {
                 let s = &iso_str[..];
-                let naive_str = &s[..PATTERN.len()];
-                if let Ok(ndt) = chrono::NaiveDateTime::parse_from_str(naive_str, "%Y-%m-%dT%H:%M:%S") {
+                if let Some(naive_sec) = parse_naive_sec(&s.as_bytes()[..PATTERN.len()]) {
                     let mut msec = 0;
                     let mut offset = 0;
                     let mut rest = &s[PATTERN.len()..];
@@ -102,14 +111,14 @@ impl DateTime {
                         }
                     }
 
-                    let dt = DateTime::from_epoch_msec_tz((ndt.timestamp() - (offset as i64)) * 1000 + (msec as i64), offset);
+                    let dt = DateTime::from_epoch_msec_tz((naive_sec - (offset as i64)) * 1000 + (msec as i64), offset);
                     return Ok(dt)
                 }
             }
             return Err(format!("Invalid DateTime: '{:?}", iso_str))
     }
     pub fn epoc_msec_utc_offset(&self) -> (i64, i32) {
-        let msec= self.0 / (TZ_MASK + 1);
+        let msec= self.0.div_euclid(TZ_MASK + 1);
         let mut offset = self.0 & TZ_MASK;
         if (offset & ((TZ_MASK + 1) / 2)) != 0 {
             // sign extension
@@ -121,10 +130,44 @@ impl DateTime {
     pub fn epoch_msec(&self) -> i64 { self.epoc_msec_utc_offset().0 }
     pub fn utc_offset(&self) -> i32 { self.epoc_msec_utc_offset().1 }
 
+    /// Parse RFC 3339 date time, fraction of second is truncated to milliseconds
+    #[cfg(feature = "cbor")]
+    pub(crate) fn from_rfc3339_str(s: &str) -> Result<DateTime, String> {
+        let err = || format!("Invalid RFC 3339 date time: '{}'", s);
+        let bytes = s.as_bytes();
+        if bytes.len() < 20 || !matches!(bytes[10], b'T' | b't' | b' ') {
+            return Err(err())
+        }
+        let naive_sec = parse_naive_sec(&bytes[.. 19]).ok_or_else(err)?;
+        let mut rest = &bytes[19 ..];
+        let mut msec = 0;
+        if rest[0] == b'.' {
+            let n = rest[1 ..].iter().take_while(|b| b.is_ascii_digit()).count();
+            if n == 0 {
+                return Err(err())
+            }
+            for i in 0 .. 3 {
+                msec = msec * 10 + if i < n { (rest[1 + i] - b'0') as i64 } else { 0 };
+            }
+            rest = &rest[1 + n ..];
+        }
+        let offset = match rest {
+            b"Z" | b"z" => 0,
+            [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+                let offset = parse_digits(&[*h1, *h2]).filter(|h| *h < 24).ok_or_else(err)? * 3600
+                    + parse_digits(&[*m1, *m2]).filter(|m| *m < 60).ok_or_else(err)? * 60;
+                if *sign == b'-' { -offset } else { offset }
+            }
+            _ => return Err(err()),
+        };
+        Ok(DateTime::from_epoch_msec_tz((naive_sec - offset) * 1000 + msec, offset as i32))
+    }
+    #[cfg(feature = "chrono")]
     pub fn to_chrono_naivedatetime(&self) -> chrono::NaiveDateTime {
         let msec = self.epoch_msec();
-        chrono::NaiveDateTime::from_timestamp_opt(msec / 1000, ((msec % 1000) * 1000) as u32).unwrap_or(NaiveDateTime::default())
+        chrono::NaiveDateTime::from_timestamp_opt(msec.div_euclid(1000), (msec.rem_euclid(1000) * 1_000_000) as u32).unwrap_or(NaiveDateTime::default())
     }
+    #[cfg(feature = "chrono")]
     pub fn to_chrono_datetime(&self) -> chrono::DateTime<chrono::offset::FixedOffset> {
         let offset = match FixedOffset::east_opt(self.utc_offset()) {
             None => {FixedOffset::east_opt(0).unwrap()}
@@ -136,18 +179,7 @@ impl DateTime {
         self.to_iso_string_opt(&ToISOStringOptions::default())
     }
     pub fn to_iso_string_opt(&self, opts: &ToISOStringOptions) -> String {
-        let dt = self.to_chrono_datetime();
-        let mut s = format!("{}", dt.format("%Y-%m-%dT%H:%M:%S"));
-        let ms = self.epoch_msec() % 1000;
-        match opts.include_millis {
-            IncludeMilliseconds::Never => {}
-            IncludeMilliseconds::Always => { s.push_str(&format!(".{:03}", ms)); }
-            IncludeMilliseconds::WhenNonZero => {
-                if ms > 0 {
-                    s.push_str(&format!(".{:03}", ms));
-                }
-            }
-        }
+        let mut s = self.to_naive_string(&opts.include_millis);
         if opts.include_timezone {
             let mut offset = self.utc_offset();
             if offset == 0 {
@@ -171,6 +203,46 @@ impl DateTime {
         s
     }
 
+    /// RFC 3339 date time, `Z` is used for UTC
+    pub(crate) fn to_rfc3339_string(&self, include_millis: &IncludeMilliseconds) -> String {
+        let mut s = self.to_naive_string(include_millis);
+        let offset = self.utc_offset();
+        if offset == 0 {
+            s.push('Z');
+        } else {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let offset = offset.abs();
+            s += &format!("{}{:02}:{:02}", sign, offset / 3600, offset / 60 % 60);
+        }
+        s
+    }
+    /// Local date and time without time zone, `YYYY-MM-DDTHH:MM:SS.mmm`
+    fn to_naive_string(&self, include_millis: &IncludeMilliseconds) -> String {
+        let (msec, offset) = self.epoc_msec_utc_offset();
+        let local_msec = msec + offset as i64 * 1000;
+        let sec = local_msec.div_euclid(1000);
+        let (year, month, day) = civil_from_days(sec.div_euclid(SEC_PER_DAY));
+        let sec_of_day = sec.rem_euclid(SEC_PER_DAY);
+        // years outside 0 - 9999 need sign
+        let mut s = if (0 ..= 9999).contains(&year) {
+            format!("{:04}", year)
+        } else {
+            format!("{:+05}", year)
+        };
+        s += &format!("-{:02}-{:02}T{:02}:{:02}:{:02}", month, day, sec_of_day / 3600, sec_of_day / 60 % 60, sec_of_day % 60);
+        let ms = local_msec.rem_euclid(1000);
+        match include_millis {
+            IncludeMilliseconds::Never => {}
+            IncludeMilliseconds::Always => { s.push_str(&format!(".{:03}", ms)); }
+            IncludeMilliseconds::WhenNonZero => {
+                if ms > 0 {
+                    s.push_str(&format!(".{:03}", ms));
+                }
+            }
+        }
+        s
+    }
+
     pub fn add_days(&self, days: i64) -> Self {
         let (msec, offset) = self.epoc_msec_utc_offset();
         Self::from_epoch_msec_tz(msec + (days * 24 * 60 * 60 * 1000), offset)
@@ -217,8 +289,87 @@ impl fmt::Display for DateTime {
     }
 }
 
+#[cfg(feature = "chrono")]
 impl From<NaiveDateTime> for DateTime {
     fn from(ndt: NaiveDateTime) -> Self {
         DateTime::from_naive_datetime(&ndt)
     }
-}
\ No newline at end of file
+}
+
+const SEC_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Days since 1970-01-01 of proleptic Gregorian calendar date,
+/// see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+/// Inverse of `days_from_civil()`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+fn parse_digits(bytes: &[u8]) -> Option<i64> {
+    bytes.iter().try_fold(0, |n, b| if b.is_ascii_digit() { Some(n * 10 + (b - b'0') as i64) } else { None })
+}
+/// Seconds since epoch of `YYYY-MM-DDTHH:MM:SS` ignoring character between date and time
+fn parse_naive_sec(bytes: &[u8]) -> Option<i64> {
+    if bytes.len() != 19 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[13] != b':' || bytes[16] != b':' {
+        return None
+    }
+    let year = parse_digits(&bytes[0 .. 4])?;
+    let month = parse_digits(&bytes[5 .. 7])?;
+    let day = parse_digits(&bytes[8 .. 10])?;
+    let hour = parse_digits(&bytes[11 .. 13])?;
+    let min = parse_digits(&bytes[14 .. 16])?;
+    let sec = parse_digits(&bytes[17 .. 19])?;
+    if !(1 ..= 12).contains(&month) || hour > 23 || min > 59 || sec > 59 {
+        return None
+    }
+    let days = days_from_civil(year, month, day);
+    // day must not overflow to next month
+    if day < 1 || civil_from_days(days).2 != day {
+        return None
+    }
+    Some(days * SEC_PER_DAY + hour * 3600 + min * 60 + sec)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_iso_string() {
+        let dt = DateTime::from_epoch_msec_tz(1_700_000_000_123, -(8 * 3600 + 15 * 60));
+        assert_eq!(dt.to_iso_string(), "2023-11-14T13:58:20.123-0815");
+        assert_eq!(DateTime::from_iso_str(&dt.to_iso_string()).unwrap(), dt);
+        assert_eq!(DateTime::from_epoch_msec(-1).to_iso_string(), "1969-12-31T23:59:59.999Z");
+        assert_eq!(DateTime::from_iso_str("2024-02-29T00:00:00Z").unwrap().epoch_msec(), 1_709_164_800_000);
+        assert!(DateTime::from_iso_str("2023-02-29T00:00:00Z").is_err());
+        assert!(DateTime::from_iso_str("2023-01-01 00:00:00Z").is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_iso_string_as_chrono() {
+        let mut msec: i64 = -62_135_596_800_000;
+        while msec < 253_402_300_799_000 {
+            let dt = DateTime::from_epoch_msec_tz(msec, 5400);
+            let chrono_str = dt.to_chrono_datetime().format("%Y-%m-%dT%H:%M:%S%.3f").to_string();
+            assert_eq!(dt.to_naive_string(&IncludeMilliseconds::Always), chrono_str);
+            assert_eq!(parse_naive_sec(chrono_str[.. 19].as_bytes()), Some((msec + 5400 * 1000).div_euclid(1000)));
+            msec += 987_654_321_987;
+        }
+    }
+}
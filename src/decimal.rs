@@ -1,4 +1,5 @@
 //use crate::rpcvalue::RpcValue;
+use crate::prelude::*;
 
 /// mantisa: 56, exponent: 8;
 /// I'm storing whole Decimal in one i64 to keep size_of RpcValue == 24
@@ -36,8 +37,10 @@ impl Decimal {
         //let buff: Vec<u8> = Vec::new();
         let mut s = mantisa.to_string();
 
-        let n = s.len() as i8;
-        let dec_places = -exponent as i8;
+        // i32 arithmetic, exponent can be whole i8 range
+        let n = s.len() as i32;
+        let exponent = exponent as i32;
+        let dec_places = -exponent;
         if dec_places > 0 && dec_places < n {
             // insert decimal point
             let dot_ix = n - dec_places;
@@ -47,12 +50,12 @@ impl Decimal {
             // prepend 0.00000..
             let extra_0_cnt = dec_places - n;
             s = "0.".to_string()
-                + &*std::iter::repeat("0").take(extra_0_cnt as usize).collect::<String>()
+                + &*core::iter::repeat("0").take(extra_0_cnt as usize).collect::<String>()
                 + &*s;
         }
         else if dec_places < 0 && n + exponent <= 9 {
             // append ..000000.
-            s = s + &*std::iter::repeat("0").take(exponent as usize).collect::<String>();
+            s = s + &*core::iter::repeat("0").take(exponent as usize).collect::<String>();
             s.push('.');
         }
         else if dec_places == 0 {
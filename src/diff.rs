@@ -0,0 +1,408 @@
+use core::convert::TryFrom;
+use core::fmt;
+use alloc::sync::Arc;
+use crate::{MetaMap, RpcValue, Value};
+use crate::metamap::MetaKey;
+use crate::rpcvalue::{IMap, List, MapRemove};
+use crate::prelude::*;
+
+/// Patch operation keys, the operation itself is stored as `IMap`
+pub enum Key {Op = 1, Path, Value, Tag }
+
+const OP_ADD: &str = "add";
+const OP_REMOVE: &str = "remove";
+const OP_REPLACE: &str = "replace";
+const OP_SET_META: &str = "setMeta";
+const OP_REMOVE_META: &str = "removeMeta";
+
+/// Structural difference of two `RpcValue`s modeled after JSON Patch (RFC 6902).
+///
+/// Patch is a `List` of operations, every operation is an `IMap`:
+/// * `Key::Op` - one of `"add"`, `"remove"`, `"replace"`, `"setMeta"`, `"removeMeta"`
+/// * `Key::Path` - `List` of path segments, `String` for `Map` keys, `Int` for `IMap` keys and `List` indexes
+/// * `Key::Value` - new value for `add`, `replace` and `setMeta`
+/// * `Key::Tag` - `Int` or `String` meta tag key for `setMeta` and `removeMeta`, the path points to the value owning the meta
+///
+/// `replace` replaces the whole value including its meta.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch (RpcValue);
+
+impl Patch {
+    pub fn new() -> Self {
+        Patch(RpcValue::from(List::new()))
+    }
+    pub fn from_rpcvalue(rv: RpcValue) -> Result<Self, &'static str> {
+        if !rv.is_list() {
+            return Err("Patch must be List!");
+        }
+        for op in rv.as_list() {
+            if !op.is_imap() {
+                return Err("Patch operation must be IMap!");
+            }
+        }
+        Ok(Patch(rv))
+    }
+    pub fn as_rpcvalue(&self) -> &RpcValue {
+        &self.0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.as_list().is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.0.as_list().len()
+    }
+
+    /// Apply patch to `rv`, the value is left untouched when any operation fails.
+    pub fn apply(&self, rv: &mut RpcValue) -> crate::Result<()> {
+        let mut patched = rv.clone();
+        for op in self.0.as_list() {
+            Self::apply_op(&mut patched, op)?;
+        }
+        *rv = patched;
+        Ok(())
+    }
+
+    fn push_op(&mut self, op: &str, path: &[RpcValue], meta_key: Option<RpcValue>, value: Option<RpcValue>) {
+        let mut m = IMap::new();
+        m.insert(Key::Op as i32, RpcValue::from(op));
+        m.insert(Key::Path as i32, RpcValue::from(path.to_vec()));
+        if let Some(k) = meta_key {
+            m.insert(Key::Tag as i32, k);
+        }
+        if let Some(v) = value {
+            m.insert(Key::Value as i32, v);
+        }
+        if let Value::List(lst) = self.0.value_mut() {
+            Arc::make_mut(lst).push(RpcValue::from(m));
+        }
+    }
+
+    fn apply_op(rv: &mut RpcValue, op: &RpcValue) -> crate::Result<()> {
+        let op_name = op.get(Key::Op as i32).map(|v| v.as_str()).unwrap_or("");
+        let path = match op.get(Key::Path as i32) {
+            Some(p) if p.is_list() => p.as_list(),
+            _ => return Err(format!("Patch operation '{}' path missing", op_name).into()),
+        };
+        let value = op.get(Key::Value as i32);
+        match op_name {
+            OP_SET_META | OP_REMOVE_META => {
+                let node = node_mut(rv, path)?;
+                let key = match op.get(Key::Tag as i32) {
+                    Some(k) => k,
+                    None => return Err(format!("Patch operation '{}' meta key missing", op_name).into()),
+                };
+                let meta_key = match rpcvalue_to_meta_key(key) {
+                    Some(k) => k,
+                    None => return Err(format!("Invalid meta key: {}", key).into()),
+                };
+                if op_name == OP_SET_META {
+                    let value = match value {
+                        Some(v) => v.clone(),
+                        None => return Err(format!("Patch operation '{}' value missing", op_name).into()),
+                    };
+                    if !node.has_meta() {
+                        let v = core::mem::replace(node, RpcValue::null());
+                        *node = v.set_meta(Some(MetaMap::new()));
+                    }
+                    if let Some(mm) = node.meta_mut() {
+                        match meta_key {
+                            MetaKey::Str(s) => { mm.insert(s.as_str(), value); }
+                            MetaKey::Int(i) => { mm.insert(i, value); }
+                        }
+                    }
+                } else {
+                    let removed = match node.meta_mut() {
+                        None => None,
+                        Some(mm) => match meta_key {
+                            MetaKey::Str(s) => mm.remove(s.as_str()),
+                            MetaKey::Int(i) => mm.remove(i),
+                        },
+                    };
+                    if removed.is_none() {
+                        return Err(format!("Cannot remove meta key {}, path: {}", key, RpcValue::from(path.to_vec())).into());
+                    }
+                    if node.meta().is_empty() {
+                        node.clear_meta();
+                    }
+                }
+                Ok(())
+            }
+            OP_ADD | OP_REMOVE | OP_REPLACE => {
+                if op_name != OP_REMOVE && value.is_none() {
+                    return Err(format!("Patch operation '{}' value missing", op_name).into());
+                }
+                let (key, parent_path) = match path.split_last() {
+                    Some(kp) => kp,
+                    None => {
+                        return match (op_name, value) {
+                            (OP_REMOVE, _) => Err("Cannot remove root value".into()),
+                            (_, Some(v)) => { *rv = v.clone(); Ok(()) }
+                            _ => unreachable!(),
+                        }
+                    }
+                };
+                let parent = node_mut(rv, parent_path)?;
+                let invalid_path = || format!("Invalid path: {}", RpcValue::from(path.to_vec()));
+                let ok = match (parent.value_mut(), key.value()) {
+                    (Value::Map(map), Value::String(k)) => {
+                        let map = Arc::make_mut(map);
+                        match op_name {
+                            OP_ADD => { map.insert(k.to_string(), value.unwrap().clone()); true }
+                            OP_REMOVE => map.remove_key(k.as_str()).is_some(),
+                            _ => match map.get_mut(k.as_str()) {
+                                Some(v) => { *v = value.unwrap().clone(); true }
+                                None => false,
+                            },
+                        }
+                    }
+                    (Value::IMap(map), Value::Int(k)) => {
+                        let k = i32::try_from(*k).map_err(|_| invalid_path())?;
+                        let map = Arc::make_mut(map);
+                        match op_name {
+                            OP_ADD => { map.insert(k, value.unwrap().clone()); true }
+                            OP_REMOVE => map.remove_key(&k).is_some(),
+                            _ => match map.get_mut(&k) {
+                                Some(v) => { *v = value.unwrap().clone(); true }
+                                None => false,
+                            },
+                        }
+                    }
+                    (Value::List(lst), Value::Int(ix)) => {
+                        let ix = usize::try_from(*ix).map_err(|_| invalid_path())?;
+                        let lst = Arc::make_mut(lst);
+                        match op_name {
+                            OP_ADD if ix <= lst.len() => { lst.insert(ix, value.unwrap().clone()); true }
+                            OP_REMOVE if ix < lst.len() => { lst.remove(ix); true }
+                            OP_REPLACE if ix < lst.len() => { lst[ix] = value.unwrap().clone(); true }
+                            _ => false,
+                        }
+                    }
+                    _ => false,
+                };
+                if ok {
+                    Ok(())
+                } else {
+                    Err(format!("Cannot {} value, path: {}", op_name, RpcValue::from(path.to_vec())).into())
+                }
+            }
+            _ => Err(format!("Invalid patch operation '{}'", op_name).into()),
+        }
+    }
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Patch> for RpcValue {
+    fn from(patch: Patch) -> Self {
+        patch.0
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.to_cpon())
+    }
+}
+
+fn node_mut<'a>(rv: &'a mut RpcValue, path: &[RpcValue]) -> crate::Result<&'a mut RpcValue> {
+    let mut node = rv;
+    for (n, key) in path.iter().enumerate() {
+        let child = match (node.value_mut(), key.value()) {
+            (Value::Map(map), Value::String(k)) => Arc::make_mut(map).get_mut(k.as_str()),
+            (Value::IMap(map), Value::Int(k)) => match i32::try_from(*k) {
+                Ok(k) => Arc::make_mut(map).get_mut(&k),
+                Err(_) => None,
+            },
+            (Value::List(lst), Value::Int(ix)) => match usize::try_from(*ix) {
+                Ok(ix) => Arc::make_mut(lst).get_mut(ix),
+                Err(_) => None,
+            },
+            _ => None,
+        };
+        node = match child {
+            Some(c) => c,
+            None => return Err(format!("Invalid path: {}", RpcValue::from(path[..= n].to_vec())).into()),
+        };
+    }
+    Ok(node)
+}
+
+/// Only `String` and `Int` in `i32` range are meta keys
+fn rpcvalue_to_meta_key(key: &RpcValue) -> Option<MetaKey> {
+    match key.value() {
+        Value::String(s) => Some(MetaKey::Str(s.to_string())),
+        Value::Int(i) => i32::try_from(*i).ok().map(MetaKey::Int),
+        _ => None,
+    }
+}
+
+fn meta_key_to_rpcvalue(key: &MetaKey) -> RpcValue {
+    match key {
+        MetaKey::Int(i) => RpcValue::from(*i),
+        MetaKey::Str(s) => RpcValue::from(s),
+    }
+}
+
+fn diff_meta(patch: &mut Patch, path: &[RpcValue], a: &MetaMap, b: &MetaMap) {
+    for kv in a.0.iter() {
+        let removed = match &kv.key {
+            MetaKey::Int(i) => b.get(*i).is_none(),
+            MetaKey::Str(s) => b.get(s.as_str()).is_none(),
+        };
+        if removed {
+            patch.push_op(OP_REMOVE_META, path, Some(meta_key_to_rpcvalue(&kv.key)), None);
+        }
+    }
+    for kv in b.0.iter() {
+        let old = match &kv.key {
+            MetaKey::Int(i) => a.get(*i),
+            MetaKey::Str(s) => a.get(s.as_str()),
+        };
+        if old != Some(&kv.value) {
+            patch.push_op(OP_SET_META, path, Some(meta_key_to_rpcvalue(&kv.key)), Some(kv.value.clone()));
+        }
+    }
+}
+
+fn diff_helper(patch: &mut Patch, path: &mut Vec<RpcValue>, a: &RpcValue, b: &RpcValue) {
+    if a == b {
+        return;
+    }
+    match (a.value(), b.value()) {
+        (Value::Map(ma), Value::Map(mb)) => {
+            for k in ma.keys().filter(|k| !mb.contains_key(*k)) {
+                path.push(RpcValue::from(k));
+                patch.push_op(OP_REMOVE, path, None, None);
+                path.pop();
+            }
+            for (k, vb) in mb.iter() {
+                path.push(RpcValue::from(k));
+                match ma.get(k) {
+                    Some(va) => diff_helper(patch, path, va, vb),
+                    None => patch.push_op(OP_ADD, path, None, Some(vb.clone())),
+                }
+                path.pop();
+            }
+        }
+        (Value::IMap(ma), Value::IMap(mb)) => {
+            for k in ma.keys().filter(|k| !mb.contains_key(*k)) {
+                path.push(RpcValue::from(*k));
+                patch.push_op(OP_REMOVE, path, None, None);
+                path.pop();
+            }
+            for (k, vb) in mb.iter() {
+                path.push(RpcValue::from(*k));
+                match ma.get(k) {
+                    Some(va) => diff_helper(patch, path, va, vb),
+                    None => patch.push_op(OP_ADD, path, None, Some(vb.clone())),
+                }
+                path.pop();
+            }
+        }
+        (Value::List(la), Value::List(lb)) => {
+            for (ix, (va, vb)) in la.iter().zip(lb.iter()).enumerate() {
+                path.push(RpcValue::from(ix as i64));
+                diff_helper(patch, path, va, vb);
+                path.pop();
+            }
+            // remove from the end to keep indexes of preceding items valid
+            for ix in (lb.len() .. la.len()).rev() {
+                path.push(RpcValue::from(ix as i64));
+                patch.push_op(OP_REMOVE, path, None, None);
+                path.pop();
+            }
+            for ix in la.len() .. lb.len() {
+                path.push(RpcValue::from(ix as i64));
+                patch.push_op(OP_ADD, path, None, Some(lb[ix].clone()));
+                path.pop();
+            }
+        }
+        (va, vb) if va != vb => {
+            patch.push_op(OP_REPLACE, path, None, Some(b.clone()));
+            return;
+        }
+        _ => {}
+    }
+    diff_meta(patch, path, a.meta(), b.meta());
+}
+
+/// Create patch transforming `a` to `b`, such that `diff(a, b).apply(a) == b`
+pub fn diff(a: &RpcValue, b: &RpcValue) -> Patch {
+    let mut patch = Patch::new();
+    let mut path = Vec::new();
+    diff_helper(&mut patch, &mut path, a, b);
+    patch
+}
+
+#[cfg(test)]
+mod test {
+    use crate::RpcValue;
+    use crate::rpcvalue::{diff, Patch};
+
+    fn check_diff(cpon1: &str, cpon2: &str) -> Patch {
+        let a = RpcValue::from_cpon(cpon1).unwrap();
+        let b = RpcValue::from_cpon(cpon2).unwrap();
+        let patch = diff(&a, &b);
+        let mut c = a.clone();
+        patch.apply(&mut c).unwrap();
+        assert_eq!(c, b, "patch: {}", patch);
+        // patch must survive serialization
+        let patch2 = Patch::from_rpcvalue(RpcValue::from_chainpack(&patch.as_rpcvalue().to_chainpack()).unwrap()).unwrap();
+        let mut c = a.clone();
+        patch2.apply(&mut c).unwrap();
+        assert_eq!(c, b);
+        patch
+    }
+
+    #[test]
+    fn diff_and_apply() {
+        assert!(check_diff("1", "1").is_empty());
+        assert_eq!(check_diff("1", "2").len(), 1);
+        assert_eq!(check_diff("1", "\"foo\"").len(), 1);
+        assert_eq!(check_diff(r#"{"a":1,"b":2}"#, r#"{"a":1,"b":3,"c":4}"#).len(), 2);
+        check_diff(r#"{"a":1,"b":{"c":[1,2,3]}}"#, r#"{"b":{"c":[1,5]}}"#);
+        check_diff(r#"[1,2]"#, r#"[1,2,3,[4]]"#);
+        check_diff(r#"i{1:"foo",2:i{3:4}}"#, r#"i{2:i{3:5},7:null}"#);
+        check_diff(r#"{"a":i{1:2}}"#, r#"{"a":[1,2]}"#);
+        assert_eq!(check_diff(r#"<1:2,"foo":"bar">i{1:2}"#, r#"<1:3,"baz":"x">i{1:2}"#).len(), 3);
+        check_diff(r#"{"a":<1:2>[1,2]}"#, r#"{"a":[1,<3:4>2]}"#);
+        check_diff(r#"{"a":1}"#, r#"<8:1>{"a":1}"#);
+        check_diff(r#"<8:1>1"#, r#"2"#);
+    }
+
+    #[test]
+    fn apply_errors() {
+        let mut rv = RpcValue::from_cpon(r#"{"a":[1,2]}"#).unwrap();
+        let orig = rv.clone();
+        let patch = Patch::from_rpcvalue(RpcValue::from_cpon(r#"[i{1:"replace",2:["a",0],3:7},i{1:"remove",2:["a",5]}]"#).unwrap()).unwrap();
+        assert!(patch.apply(&mut rv).is_err());
+        assert_eq!(rv, orig);
+        let patch = Patch::from_rpcvalue(RpcValue::from_cpon(r#"[i{1:"foo",2:[]}]"#).unwrap()).unwrap();
+        assert!(patch.apply(&mut rv).is_err());
+        assert!(Patch::from_rpcvalue(RpcValue::from(1)).is_err());
+
+        // patches come over the wire, keys out of range must not hit other nodes
+        let mut rv = RpcValue::from_cpon(r#"<1:"a">i{1:"x"}"#).unwrap();
+        let orig = rv.clone();
+        for op in [
+            r#"i{1:"replace",2:[4294967297],3:7}"#,
+            r#"i{1:"remove",2:[4294967297]}"#,
+            r#"i{1:"setMeta",2:[],3:7,4:4294967297}"#,
+            r#"i{1:"setMeta",2:[],3:7,4:null}"#,
+            r#"i{1:"removeMeta",2:[],4:[1]}"#,
+            r#"i{1:"removeMeta",2:[],4:true}"#,
+        ].iter() {
+            let patch = Patch::from_rpcvalue(RpcValue::from_cpon(&format!("[{}]", op)).unwrap()).unwrap();
+            let err = patch.apply(&mut rv).unwrap_err().to_string();
+            assert!(err.contains("Invalid path") || err.contains("Invalid meta key"), "{}: {}", op, err);
+            assert_eq!(rv, orig);
+        }
+        let mut rv = RpcValue::from_cpon(r#"[[1]]"#).unwrap();
+        let patch = Patch::from_rpcvalue(RpcValue::from_cpon(r#"[i{1:"replace",2:[-1,0],3:7}]"#).unwrap()).unwrap();
+        assert!(patch.apply(&mut rv).unwrap_err().to_string().contains("Invalid path"));
+    }
+}
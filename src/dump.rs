@@ -0,0 +1,327 @@
+//! Annotated hex dump of ChainPack data
+//!
+//! ```
+//! let data = chainpack::RpcValue::from_cpon(r#"<1:2>i{1:"foo"}"#).unwrap().to_chainpack();
+//! let dump = chainpack::dump::annotate(&data).to_string();
+//! assert_eq!(dump.lines().nth(6).unwrap(), r#"000006  86 03 66 6f 6f             String len=3 "foo""#);
+//! ```
+use core::fmt::{Display, Formatter};
+use crate::chainpack::{PackingSchema, SHV_EPOCH_MSEC, datetime_from_data};
+use crate::Decimal;
+use crate::prelude::*;
+
+/// Bytes of single item shown in hex column, longer items are elided
+const MAX_HEX_BYTES: usize = 8;
+/// Longer strings and blobs are elided in description
+const MAX_TEXT_LEN: usize = 64;
+/// Deeper nesting is reported as error instead of overflowing stack
+const MAX_DEPTH: usize = 256;
+
+/// Annotate ChainPack `data`, one line per item with offset, raw bytes and meaning.
+///
+/// Invalid data is annotated up to the first error, which is followed by hex dump of the rest.
+pub fn annotate(data: &[u8]) -> Annotated<'_> {
+    Annotated { data }
+}
+
+pub struct Annotated<'a> {
+    data: &'a [u8],
+}
+
+impl Display for Annotated<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let mut dumper = Dumper { data: self.data, pos: 0, lines: Vec::new() };
+        let mut res = Ok(());
+        while res.is_ok() && dumper.pos < self.data.len() {
+            res = dumper.dump_rpcvalue(0);
+        }
+        for line in dumper.lines.iter() {
+            write_line(f, &self.data[line.offset .. line.end], line.offset, line.depth, &line.text)?;
+        }
+        if let Err(msg) = res {
+            writeln!(f, "error at offset {:06x}: {}", dumper.pos, msg)?;
+            for (n, chunk) in self.data[dumper.pos.min(self.data.len()) ..].chunks(MAX_HEX_BYTES).enumerate() {
+                write_line(f, chunk, dumper.pos + n * MAX_HEX_BYTES, 0, "?")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_line(f: &mut Formatter<'_>, bytes: &[u8], offset: usize, depth: usize, text: &str) -> core::fmt::Result {
+    let mut hex: Vec<String> = bytes.iter().take(MAX_HEX_BYTES).map(|b| format!("{:02x}", b)).collect();
+    if bytes.len() > MAX_HEX_BYTES {
+        hex[MAX_HEX_BYTES - 1] = "..".to_string();
+    }
+    writeln!(f, "{:06x}  {:<w$}  {}{}", offset, hex.join(" "), "  ".repeat(depth), text, w = MAX_HEX_BYTES * 3 - 1)
+}
+
+struct Line {
+    offset: usize,
+    end: usize,
+    depth: usize,
+    text: String,
+}
+
+struct Dumper<'a> {
+    data: &'a [u8],
+    pos: usize,
+    lines: Vec<Line>,
+}
+
+impl Dumper<'_> {
+    fn get_byte(&mut self) -> Result<u8, String> {
+        let b = *self.data.get(self.pos).ok_or("Unexpected end of data")?;
+        self.pos += 1;
+        Ok(b)
+    }
+    fn get_bytes(&mut self, len: u64) -> Result<&[u8], String> {
+        if len > (self.data.len() - self.pos) as u64 {
+            return Err(format!("Unexpected end of data, {} bytes expected, {} available", len, self.data.len() - self.pos))
+        }
+        let bytes = &self.data[self.pos .. self.pos + len as usize];
+        self.pos += len as usize;
+        Ok(bytes)
+    }
+    /// Same as `ChainPackReader::read_uint_data_helper()`, returns `(n, bitlen)`
+    fn read_uint_data_helper(&mut self) -> Result<(u128, u32), String> {
+        let head = self.get_byte()?;
+        let (bytes_to_read_cnt, mut num, bitlen) =
+            if (head & 128) == 0 { (0, (head & 127) as u128, 7) }
+            else if (head & 64) == 0 { (1, (head & 63) as u128, 6 + 8) }
+            else if (head & 32) == 0 { (2, (head & 31) as u128, 5 + 2 * 8) }
+            else if (head & 16) == 0 { (3, (head & 15) as u128, 4 + 3 * 8) }
+            else {
+                let cnt = (head & 0xf) as u32 + 4;
+                if cnt > 16 {
+                    return Err(format!("Unsupported number length: {} bytes", cnt))
+                }
+                (cnt, 0, cnt * 8)
+            };
+        for _ in 0 .. bytes_to_read_cnt {
+            num = (num << 8) + self.get_byte()? as u128;
+        }
+        Ok((num, bitlen))
+    }
+    fn read_uint_data(&mut self) -> Result<u64, String> {
+        let num = self.read_uint_data_helper()?.0;
+        if num > u64::MAX as u128 {
+            return Err(format!("UInt out of range: {}", num))
+        }
+        Ok(num as u64)
+    }
+    fn read_int_data(&mut self) -> Result<i64, String> {
+        let (num, bitlen) = self.read_uint_data_helper()?;
+        let sign_bit_mask = 1u128 << (bitlen - 1);
+        let neg = num & sign_bit_mask != 0;
+        let abs = num & !sign_bit_mask;
+        if abs > if neg { i64::MIN.unsigned_abs() as u128 } else { i64::MAX as u128 } {
+            return Err(format!("Int out of range: {}{}", if neg { "-" } else { "" }, abs))
+        }
+        Ok(if neg { (abs as i64).wrapping_neg() } else { abs as i64 })
+    }
+    fn add_line(&mut self, offset: usize, depth: usize, text: String) {
+        self.lines.push(Line { offset, end: self.pos, depth, text });
+    }
+
+    fn dump_rpcvalue(&mut self, depth: usize) -> Result<(), String> {
+        if self.data.get(self.pos) == Some(&(PackingSchema::MetaMap as u8)) {
+            self.dump_value(depth)?;
+        }
+        self.dump_value(depth)
+    }
+    fn dump_value(&mut self, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("Nesting deeper than {}", MAX_DEPTH))
+        }
+        let offset = self.pos;
+        let b = self.get_byte()?;
+        if b == PackingSchema::List as u8 || b == PackingSchema::Map as u8
+                || b == PackingSchema::IMap as u8 || b == PackingSchema::MetaMap as u8 {
+            return self.dump_container(b, offset, depth)
+        }
+        match self.describe_scalar(b) {
+            Ok(text) => {
+                self.add_line(offset, depth, text);
+                Ok(())
+            }
+            Err(err) => {
+                // rest of the item is shown in hex dump after error
+                self.pos = offset;
+                Err(err)
+            }
+        }
+    }
+    fn dump_container(&mut self, b: u8, offset: usize, depth: usize) -> Result<(), String> {
+        let (name, item_values) = match b {
+            b if b == PackingSchema::List as u8 => ("List", 1),
+            b if b == PackingSchema::Map as u8 => ("Map", 2),
+            b if b == PackingSchema::IMap as u8 => ("IMap", 2),
+            _ => ("MetaMap", 2),
+        };
+        self.add_line(offset, depth, name.to_string());
+        let mut n = 0;
+        loop {
+            let item_offset = self.pos;
+            if self.data.get(self.pos) == Some(&(PackingSchema::TERM as u8)) {
+                if n % item_values != 0 {
+                    return Err(format!("{} key without value", name))
+                }
+                self.pos += 1;
+                self.add_line(item_offset, depth, format!("TERM {}", name));
+                return Ok(())
+            }
+            self.dump_rpcvalue(depth + 1)?;
+            n += 1;
+        }
+    }
+    /// Decode scalar after packing schema byte `b`
+    fn describe_scalar(&mut self, b: u8) -> Result<String, String> {
+        let text = if b < 128 {
+            if (b & 64) == 0 {
+                format!("tiny UInt {}", b & 63)
+            } else {
+                format!("tiny Int {}", b & 63)
+            }
+        } else if b == PackingSchema::Null as u8 {
+            "Null".to_string()
+        } else if b == PackingSchema::TRUE as u8 {
+            "true".to_string()
+        } else if b == PackingSchema::FALSE as u8 {
+            "false".to_string()
+        } else if b == PackingSchema::UInt as u8 {
+            format!("UInt {}", self.read_uint_data()?)
+        } else if b == PackingSchema::Int as u8 {
+            format!("Int {}", self.read_int_data()?)
+        } else if b == PackingSchema::Double as u8 {
+            let bytes = self.get_bytes(8)?;
+            let mut buff = [0; 8];
+            buff.copy_from_slice(bytes);
+            format!("Double {:e}", f64::from_le_bytes(buff))
+        } else if b == PackingSchema::Decimal as u8 {
+            let mantissa = self.read_int_data()?;
+            let exponent = self.read_int_data()?;
+            if exponent < i8::MIN as i64 || exponent > i8::MAX as i64 {
+                return Err(format!("Decimal exponent out of range: {}", exponent))
+            }
+            format!("Decimal {} mantissa={} exponent={}", Decimal::new(mantissa, exponent as i8).to_cpon_string(), mantissa, exponent)
+        } else if b == PackingSchema::DateTime as u8 {
+            let d = self.read_int_data()?;
+            self.describe_datetime(d)?
+        } else if b == PackingSchema::String as u8 {
+            let len = self.read_uint_data()?;
+            let bytes = self.get_bytes(len)?;
+            format!("String len={} {}", len, describe_text(bytes))
+        } else if b == PackingSchema::CString as u8 {
+            let mut bytes = Vec::new();
+            loop {
+                match self.get_byte()? {
+                    0 => break,
+                    b'\\' => match self.get_byte()? {
+                        b'0' => bytes.push(0),
+                        b => bytes.push(b),
+                    },
+                    b => bytes.push(b),
+                }
+            }
+            format!("CString {}", describe_text(&bytes))
+        } else if b == PackingSchema::Blob as u8 {
+            let len = self.read_uint_data()?;
+            let bytes = self.get_bytes(len)?;
+            let hex: String = bytes.iter().take(MAX_TEXT_LEN / 2).map(|b| format!("{:02x}", b)).collect();
+            format!("Blob len={} x\"{}{}\"", len, hex, if bytes.len() > MAX_TEXT_LEN / 2 { "..." } else { "" })
+        } else if b == PackingSchema::TERM as u8 {
+            return Err("Unexpected TERM".to_string())
+        } else {
+            return Err(format!("Invalid packing schema: 0x{:02x}", b))
+        };
+        Ok(text)
+    }
+    fn describe_datetime(&self, d: i64) -> Result<String, String> {
+        let dt = datetime_from_data(d).ok_or_else(|| format!("DateTime out of range, data: {}", d))?;
+        let has_tz = d & 1 != 0;
+        let has_msec = d & 2 == 0;
+        let mut data = d >> 2;
+        let mut s = format!("DateTime d\"{}\" data={}", dt.to_iso_string(), d);
+        if has_tz {
+            let quarters = ((data & 0x7f) as i8) << 1 >> 1;
+            data >>= 7;
+            s += &format!(" tz={}*15min", quarters);
+        }
+        if has_msec {
+            s += &format!(" SHV_EPOCH_MSEC{:+}ms", data);
+        } else {
+            s += &format!(" SHV_EPOCH_MSEC{:+}s", data);
+        }
+        debug_assert_eq!(dt.epoch_msec(), SHV_EPOCH_MSEC + if has_msec { data } else { data * 1000 });
+        Ok(s)
+    }
+}
+
+fn describe_text(bytes: &[u8]) -> String {
+    match core::str::from_utf8(bytes) {
+        Ok(s) if s.chars().count() > MAX_TEXT_LEN => {
+            format!("{:?}...", s.chars().take(MAX_TEXT_LEN).collect::<String>())
+        }
+        Ok(s) => format!("{:?}", s),
+        Err(e) => format!("invalid UTF-8: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::RpcValue;
+    use crate::dump::annotate;
+
+    fn dump(cpon: &str) -> String {
+        annotate(&RpcValue::from_cpon(cpon).unwrap().to_chainpack()).to_string()
+    }
+
+    #[test]
+    fn test_annotate() {
+        assert_eq!(dump(r#"<1:2,"a":null>i{8:[-1,1000u]}"#), "\
+000000  8b                       MetaMap
+000001  41                         tiny Int 1
+000002  42                         tiny Int 2
+000003  86 01 61                   String len=1 \"a\"
+000006  80                         Null
+000007  ff                       TERM MetaMap
+000008  8a                       IMap
+000009  48                         tiny Int 8
+00000a  88                         List
+00000b  82 41                        Int -1
+00000d  81 83 e8                     UInt 1000
+000010  ff                         TERM List
+000011  ff                       TERM IMap
+");
+        assert_eq!(dump(r#"[1.23,x"0102",true]"#), "\
+000000  88                       List
+000001  8c 80 7b 42                Decimal 1.23 mantissa=123 exponent=-2
+000005  85 02 01 02                Blob len=2 x\"0102\"
+000009  fe                         true
+00000a  ff                       TERM List
+");
+        assert_eq!(annotate(&RpcValue::from(1.5).to_chainpack()).to_string(),
+                   "000000  83 00 00 00 00 00 00 ..  Double 1.5e0\n");
+        assert_eq!(annotate(&[0x8e, b'a', b'\\', b'0', 0]).to_string(), "000000  8e 61 5c 30 00           CString \"a\\0\"\n");
+        assert_eq!(dump(r#"d"2018-02-02T00:00:00.001Z""#), "000000  8d 04                    DateTime d\"2018-02-02T00:00:00.001Z\" data=4 SHV_EPOCH_MSEC+1ms\n");
+        assert_eq!(dump(r#"d"2018-02-02T01:00:01+01""#), "000000  8d 82 13                 DateTime d\"2018-02-02T01:00:01.000+01\" data=531 tz=4*15min SHV_EPOCH_MSEC+1s\n");
+    }
+
+    #[test]
+    fn test_annotate_invalid() {
+        assert_eq!(annotate(&[0x88, 0x41, 0x86, 0x05, b'a']).to_string(), "\
+000000  88                       List
+000001  41                         tiny Int 1
+error at offset 000002: Unexpected end of data, 5 bytes expected, 1 available
+000002  86 05 61                 ?
+");
+        assert_eq!(annotate(&[0x41, 0xff, 0x42]).to_string(), "\
+000000  41                       tiny Int 1
+error at offset 000001: Unexpected TERM
+000001  ff 42                    ?
+");
+        assert_eq!(annotate(&[]).to_string(), "");
+    }
+}
@@ -0,0 +1,109 @@
+//! Byte source and sink of readers and writers
+//!
+//! With `std` feature they are implemented for every `std::io::Read` and `std::io::Write`,
+//! without it for `&[u8]` and `Vec<u8>` only, implement them for UART or DMA buffers on embedded targets.
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+
+/// Error of `ByteSource` or `ByteSink`, it is `std::io::Error` with `std` feature
+#[cfg(feature = "std")]
+pub type IoError = std::io::Error;
+
+/// Error of `ByteSource` or `ByteSink`, it is `std::io::Error` with `std` feature
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub struct IoError {
+    msg: String,
+}
+#[cfg(not(feature = "std"))]
+impl IoError {
+    pub fn new(msg: &str) -> Self {
+        IoError { msg: msg.to_string() }
+    }
+}
+#[cfg(not(feature = "std"))]
+impl core::fmt::Display for IoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+#[cfg(not(feature = "std"))]
+impl core::error::Error for IoError {}
+
+#[cfg(feature = "msgpack")]
+pub(crate) fn invalid_data(msg: &str) -> IoError {
+    #[cfg(feature = "std")]
+    return std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    #[cfg(not(feature = "std"))]
+    return IoError::new(msg);
+}
+
+/// Readers never read ahead, they ask for one byte at a time, so that the source is left right after the value read.
+/// Wrap unbuffered sources, like `File` or `TcpStream`, in `BufReader`.
+pub trait ByteSource {
+    /// Read up to `buf.len()` bytes, 0 is returned at the end of data
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, IoError>;
+}
+
+/// Writers buffer their output, so a sink gets chunks instead of single bytes and does not need own buffering.
+/// Whole value is written to the sink when `Writer` method returns.
+pub trait ByteSink {
+    /// Write whole `buf`
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<(), IoError>;
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read + ?Sized> ByteSource for R {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        self.read(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + ?Sized> ByteSink for W {
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<(), IoError> {
+        self.write_all(buf)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl ByteSource for &[u8] {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        let n = buf.len().min(self.len());
+        let (data, rest) = self.split_at(n);
+        buf[.. n].copy_from_slice(data);
+        *self = rest;
+        Ok(n)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<R: ByteSource + ?Sized> ByteSource for &mut R {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        (**self).read_bytes(buf)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl ByteSink for Vec<u8> {
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<(), IoError> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<W: ByteSink + ?Sized> ByteSink for &mut W {
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<(), IoError> {
+        (**self).write_bytes(buf)
+    }
+}
+
+/// Sink discarding everything, writers count written bytes themselves
+pub(crate) struct NullSink;
+
+impl ByteSink for NullSink {
+    fn write_bytes(&mut self, _buf: &[u8]) -> Result<(), IoError> {
+        Ok(())
+    }
+}
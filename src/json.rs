@@ -0,0 +1,808 @@
+use crate::prelude::*;
+use crate::io::{ByteSink, ByteSource};
+use crate::{RpcValue, MetaMap, Value, Decimal, DateTime};
+use crate::datetime::{IncludeMilliseconds, ToISOStringOptions};
+use crate::writer::{WriteResult, Writer, ByteWriter};
+use crate::metamap::MetaKey;
+use crate::reader::{Reader, ByteReader, ReadError};
+use crate::rpcvalue::{Map, IMap};
+
+/// How `JsonWriter` writes blobs in plain mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JsonBlobFormat {
+    /// standard base64 with padding
+    Base64,
+    /// lower case hex dump
+    Hex,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonWriterOptions {
+    /// lossy plain JSON without `$` wrappers, see `JsonWriter`
+    pub plain: bool,
+    /// blob format in plain mode, lossless mode always writes hex
+    pub blob_format: JsonBlobFormat,
+    /// in plain mode, value with meta is written as `{"<meta_key>":{...},"value":...}`,
+    /// meta is dropped when `None`
+    pub meta_key: Option<String>,
+}
+
+impl Default for JsonWriterOptions {
+    fn default() -> Self {
+        JsonWriterOptions {
+            plain: false,
+            blob_format: JsonBlobFormat::Base64,
+            meta_key: None,
+        }
+    }
+}
+
+/// Lossless JSON writer, `JsonReader` reads the output back to identical `RpcValue`.
+///
+/// Types JSON has are written as they are, the rest is wrapped in a single key object:
+///
+/// | RpcValue            | JSON                                           |
+/// |---------------------|------------------------------------------------|
+/// | `null`, `true`      | `null`, `true`                                 |
+/// | `Int`               | `-123`                                         |
+/// | `Double`            | `1.5`, `1e-7`, always with `.` or exponent     |
+/// | `Double` NaN, ±inf  | `{"$double":"NaN"}`, `"inf"`, `"-inf"`         |
+/// | `UInt`              | `{"$uint":123}`                                |
+/// | `Decimal`           | `{"$decimal":"123e-2"}`, mantissa and exponent |
+/// | `DateTime`          | `{"$datetime":"2021-11-08T01:02:03.456+01"}`   |
+/// | `Blob`              | `{"$blob":"0aff"}`, hex                        |
+/// | `String`, `List`    | `"foo"`, `[1,2]`                               |
+/// | `Map`               | `{"a":1}`, key `$a` is written as `$$a`        |
+/// | `IMap`              | `{"$imap":{"1":"a"}}`                          |
+/// | meta                | `{"$meta":[[1,2],["foo",3]],"$value":...}`     |
+///
+/// Meta keys are kept in order as `[key, value]` pairs, `Int` key is a JSON number, `String` key a JSON string.
+///
+/// Plain mode, see `JsonWriterOptions::plain`, writes JSON which can be consumed directly by JavaScript,
+/// but cannot be read back to the same `RpcValue`:
+/// `UInt` and `Decimal` are JSON numbers, NaN and ±inf `null`, `DateTime` is RFC 3339 string with milliseconds,
+/// `Blob` is base64 or hex string, `IMap` keys are strings and meta is dropped or written under `meta_key`.
+pub struct JsonWriter<'a, W>
+    where W: ByteSink
+{
+    byte_writer: ByteWriter<'a, W>,
+    options: JsonWriterOptions,
+}
+
+impl<'a, W> JsonWriter<'a, W>
+    where W: ByteSink
+{
+    pub fn new(write: &'a mut W) -> Self {
+        Self::with_options(write, JsonWriterOptions::default())
+    }
+    pub fn with_options(write: &'a mut W, options: JsonWriterOptions) -> Self {
+        JsonWriter { byte_writer: ByteWriter::new(write), options }
+    }
+    pub fn options(&self) -> &JsonWriterOptions {
+        &self.options
+    }
+    pub fn set_options(&mut self, options: JsonWriterOptions) {
+        self.options = options;
+    }
+
+    fn write_byte(&mut self, b: u8) -> WriteResult {
+        self.byte_writer.write_byte(b)
+    }
+    fn write_bytes(&mut self, b: &[u8]) -> WriteResult {
+        self.byte_writer.write_bytes(b)
+    }
+    fn write_wrapped(&mut self, key: &str, write_fn: impl FnOnce(&mut Self) -> WriteResult) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'{')?;
+        self.write_string(key)?;
+        self.write_byte(b':')?;
+        write_fn(self)?;
+        self.write_byte(b'}')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_string(&mut self, s: &str) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'"')?;
+        for c in s.chars() {
+            match c {
+                '"' => { self.write_bytes(b"\\\"")?; }
+                '\\' => { self.write_bytes(b"\\\\")?; }
+                '\n' => { self.write_bytes(b"\\n")?; }
+                '\r' => { self.write_bytes(b"\\r")?; }
+                '\t' => { self.write_bytes(b"\\t")?; }
+                '\u{8}' => { self.write_bytes(b"\\b")?; }
+                '\u{c}' => { self.write_bytes(b"\\f")?; }
+                _ if c.is_ascii_control() => { self.write_bytes(format!("\\u{:04x}", c as u32).as_bytes())?; }
+                _ => {
+                    let mut b = [0; 4];
+                    self.write_bytes(c.encode_utf8(&mut b).as_bytes())?;
+                }
+            }
+        }
+        self.write_byte(b'"')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_double(&mut self, n: f64) -> WriteResult {
+        if self.options.plain && !n.is_finite() {
+            self.write_bytes(b"null")
+        } else if n.is_nan() {
+            self.write_wrapped("$double", |wr| wr.write_string("NaN"))
+        } else if n.is_infinite() {
+            self.write_wrapped("$double", |wr| wr.write_string(if n > 0. { "inf" } else { "-inf" }))
+        } else {
+            // debug format always contains decimal point or exponent, so it is not confused with Int
+            self.write_bytes(format!("{:?}", n).as_bytes())
+        }
+    }
+    fn write_blob(&mut self, bytes: &[u8]) -> WriteResult {
+        let hex = || bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        if !self.options.plain {
+            return self.write_wrapped("$blob", |wr| wr.write_string(&hex()))
+        }
+        match self.options.blob_format {
+            JsonBlobFormat::Base64 => self.write_string(&encode_base64(bytes)),
+            JsonBlobFormat::Hex => self.write_string(&hex()),
+        }
+    }
+    fn write_decimal(&mut self, decimal: &Decimal) -> WriteResult {
+        let (mantissa, exponent) = decimal.decode();
+        if !self.options.plain {
+            return self.write_wrapped("$decimal", |wr| wr.write_string(&format!("{}e{}", mantissa, exponent)))
+        }
+        // exact decimal notation, JSON does not allow trailing decimal point of Cpon
+        let mut s = mantissa.unsigned_abs().to_string();
+        if mantissa != 0 && exponent > 0 {
+            s += &"0".repeat(exponent as usize);
+        } else if exponent < 0 {
+            let dec_places = -exponent as usize;
+            if s.len() <= dec_places {
+                s = "0".repeat(dec_places + 1 - s.len()) + &s;
+            }
+            s.insert(s.len() - dec_places, '.');
+        }
+        if mantissa < 0 {
+            s.insert(0, '-');
+        }
+        self.write_bytes(s.as_bytes())
+    }
+    fn write_datetime(&mut self, dt: &DateTime) -> WriteResult {
+        if self.options.plain {
+            let s = dt.to_rfc3339_string(&IncludeMilliseconds::Always);
+            return self.write_string(&s)
+        }
+        let s = dt.to_iso_string_opt(&ToISOStringOptions {
+            include_millis: IncludeMilliseconds::WhenNonZero,
+            include_timezone: true
+        });
+        self.write_wrapped("$datetime", |wr| wr.write_string(&s))
+    }
+    fn write_list(&mut self, lst: &[RpcValue]) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'[')?;
+        for (n, v) in lst.iter().enumerate() {
+            if n > 0 {
+                self.write_byte(b',')?;
+            }
+            self.do_write(v)?;
+        }
+        self.write_byte(b']')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_map(&mut self, map: &Map) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'{')?;
+        for (n, (k, v)) in map.iter().enumerate() {
+            if n > 0 {
+                self.write_byte(b',')?;
+            }
+            if k.starts_with('$') && !self.options.plain {
+                self.write_string(&format!("${}", k))?;
+            } else {
+                self.write_string(k)?;
+            }
+            self.write_byte(b':')?;
+            self.do_write(v)?;
+        }
+        self.write_byte(b'}')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_imap(&mut self, map: &IMap) -> WriteResult {
+        if self.options.plain {
+            return self.write_imap_items(map)
+        }
+        self.write_wrapped("$imap", |wr| wr.write_imap_items(map))
+    }
+    fn write_imap_items(&mut self, map: &IMap) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'{')?;
+        for (n, (k, v)) in map.iter().enumerate() {
+            if n > 0 {
+                self.write_byte(b',')?;
+            }
+            self.write_string(&k.to_string())?;
+            self.write_byte(b':')?;
+            self.do_write(v)?;
+        }
+        self.write_byte(b'}')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_plain_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'{')?;
+        for (n, kv) in map.0.iter().enumerate() {
+            if n > 0 {
+                self.write_byte(b',')?;
+            }
+            match &kv.key {
+                MetaKey::Int(i) => self.write_string(&i.to_string())?,
+                MetaKey::Str(s) => self.write_string(s)?,
+            };
+            self.write_byte(b':')?;
+            self.do_write(&kv.value)?;
+        }
+        self.write_byte(b'}')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0 .. 4 {
+            if i <= chunk.len() {
+                s.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+impl<'a, W> JsonWriter<'a, W>
+    where W: ByteSink
+{
+    fn do_write(&mut self, val: &RpcValue) -> WriteResult
+    {
+        let mm = val.meta();
+        if mm.is_empty() || (self.options.plain && self.options.meta_key.is_none()) {
+            return self.do_write_value(val.value())
+        }
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'{')?;
+        if let Some(meta_key) = self.options.meta_key.clone().filter(|_| self.options.plain) {
+            self.write_string(&meta_key)?;
+            self.write_byte(b':')?;
+            self.write_plain_meta(mm)?;
+            self.write_byte(b',')?;
+            self.write_string("value")?;
+            self.write_byte(b':')?;
+            self.do_write_value(val.value())?;
+            self.write_byte(b'}')?;
+            return Ok(self.byte_writer.count() - cnt)
+        }
+        self.write_string("$meta")?;
+        self.write_byte(b':')?;
+        self.do_write_meta(mm)?;
+        self.write_byte(b',')?;
+        self.write_string("$value")?;
+        self.write_byte(b':')?;
+        self.do_write_value(val.value())?;
+        self.write_byte(b'}')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn do_write_meta(&mut self, map: &MetaMap) -> WriteResult
+    {
+        let cnt = self.byte_writer.count();
+        self.write_byte(b'[')?;
+        for (n, kv) in map.0.iter().enumerate() {
+            if n > 0 {
+                self.write_byte(b',')?;
+            }
+            self.write_byte(b'[')?;
+            match &kv.key {
+                MetaKey::Int(i) => self.write_bytes(i.to_string().as_bytes())?,
+                MetaKey::Str(s) => self.write_string(s)?,
+            };
+            self.write_byte(b',')?;
+            self.do_write(&kv.value)?;
+            self.write_byte(b']')?;
+        }
+        self.write_byte(b']')?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn do_write_value(&mut self, val: &Value) -> WriteResult
+    {
+        match val {
+            Value::Null => self.write_bytes(b"null"),
+            Value::Bool(b) => self.write_bytes(if *b { b"true" } else { b"false" }),
+            Value::Int(n) => self.write_bytes(n.to_string().as_bytes()),
+            Value::UInt(n) if self.options.plain => self.write_bytes(n.to_string().as_bytes()),
+            Value::UInt(n) => self.write_wrapped("$uint", |wr| wr.write_bytes(n.to_string().as_bytes())),
+            Value::String(s) => self.write_string(s),
+            Value::Blob(b) => self.write_blob(b),
+            Value::Double(n) => self.write_double(*n),
+            Value::Decimal(d) => self.write_decimal(d),
+            Value::DateTime(d) => self.write_datetime(d),
+            Value::List(lst) => self.write_list(lst),
+            Value::Map(map) => self.write_map(map),
+            Value::IMap(map) => self.write_imap(map),
+        }
+    }
+}
+
+impl<'a, W> Writer for JsonWriter<'a, W>
+    where W: ByteSink
+{
+    fn write(&mut self, val: &RpcValue) -> WriteResult {
+        let res = self.do_write(val);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let res = self.do_write_meta(map);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_value(&mut self, val: &Value) -> WriteResult {
+        let res = self.do_write_value(val);
+        self.byte_writer.flush_result(res)
+    }
+}
+
+/// Reader of the lossless JSON mapping written by `JsonWriter`.
+///
+/// Any standard JSON is accepted as long as its object keys do not start with `$`,
+/// numbers with decimal point or exponent are read as `Double`, the other ones as `Int`.
+///
+/// Plain mode, see `set_plain()`, reads any JSON to `Map`, `List`, `String`, `Int`, `Double`, `Bool` and `Null`.
+pub struct JsonReader<'a, R>
+    where R: ByteSource
+{
+    byte_reader: ByteReader<'a, R>,
+    /// `try_read_meta()` consumed `{` and the first key of an object which is not a meta wrapper,
+    /// `None` key for an empty object
+    first_key: Option<Option<String>>,
+    /// `try_read_meta()` consumed `{"$meta":[...],"$value":`, closing `}` follows the value
+    in_meta_wrapper: bool,
+    plain: bool,
+}
+
+impl<'a, R> JsonReader<'a, R>
+    where R: ByteSource
+{
+    pub fn new(read: &'a mut R) -> Self {
+        JsonReader { byte_reader: ByteReader::new(read), first_key: None, in_meta_wrapper: false, plain: false }
+    }
+    /// Plain mode does not interpret `$` wrappers and it is forgiving, it accepts `//` and `/* */` comments,
+    /// trailing commas and unescaped control characters in strings, integers out of `Int` range are read as `Double`.
+    pub fn set_plain(&mut self, plain: bool) {
+        self.plain = plain;
+    }
+
+    /// Check that only white space follows the value
+    pub(crate) fn expect_end(&mut self) -> Result<(), ReadError> {
+        self.skip_white()?;
+        if !self.byte_reader.is_eof()? {
+            return Err(self.make_error("Unexpected input after value"))
+        }
+        Ok(())
+    }
+
+    fn peek_byte(&mut self) -> u8 {
+        self.byte_reader.peek_byte()
+    }
+    fn get_byte(&mut self) -> Result<u8, ReadError> {
+        self.byte_reader.get_byte()
+    }
+    fn make_error(&self, msg: &str) -> ReadError {
+        self.byte_reader.make_error(msg)
+    }
+
+    fn skip_white(&mut self) -> Result<(), ReadError> {
+        loop {
+            match self.peek_byte() {
+                b' ' | b'\t' | b'\r' | b'\n' => { self.get_byte()?; }
+                b'/' if self.plain => self.skip_comment()?,
+                _ => return Ok(()),
+            }
+        }
+    }
+    fn skip_comment(&mut self) -> Result<(), ReadError> {
+        self.get_byte()?; // eat '/'
+        match self.get_byte()? {
+            b'/' => {
+                while !matches!(self.peek_byte(), b'\n' | 0) {
+                    self.get_byte()?;
+                }
+            }
+            b'*' => {
+                let mut prev = 0;
+                loop {
+                    let b = self.get_byte()?;
+                    if prev == b'*' && b == b'/' {
+                        break;
+                    }
+                    prev = b;
+                }
+            }
+            _ => return Err(self.make_error("Malformed comment")),
+        }
+        Ok(())
+    }
+    fn expect(&mut self, c: u8, what: &str) -> Result<(), ReadError> {
+        self.skip_white()?;
+        if self.peek_byte() != c {
+            return Err(self.make_error(&format!("Expected '{}' {}", char::from(c), what)))
+        }
+        self.get_byte()?;
+        Ok(())
+    }
+    /// Read `,` and return `false` or read `end` and return `true`
+    fn read_item_separator(&mut self, end: u8, item_name: &str) -> Result<bool, ReadError> {
+        self.skip_white()?;
+        let b = self.get_byte()?;
+        if b == end {
+            Ok(true)
+        } else if b == b',' {
+            if self.plain {
+                // trailing comma
+                self.skip_white()?;
+                if self.peek_byte() == end {
+                    self.get_byte()?;
+                    return Ok(true)
+                }
+            }
+            Ok(false)
+        } else {
+            Err(self.make_error(&format!("Expected ',' or '{}' after {}", char::from(end), item_name)))
+        }
+    }
+    fn read_str(&mut self) -> Result<String, ReadError> {
+        self.skip_white()?;
+        if self.peek_byte() != b'"' {
+            return Err(self.make_error("Expected string"))
+        }
+        self.get_byte()?;
+        let mut buff: Vec<u8> = Vec::new();
+        loop {
+            let b = self.get_byte()?;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let b = self.get_byte()?;
+                    match b {
+                        b'"' | b'\\' | b'/' => buff.push(b),
+                        b'b' => buff.push(8),
+                        b'f' => buff.push(12),
+                        b'n' => buff.push(b'\n'),
+                        b'r' => buff.push(b'\r'),
+                        b't' => buff.push(b'\t'),
+                        b'u' => {
+                            let c = self.byte_reader.read_unicode_escape()?;
+                            let mut b = [0; 4];
+                            buff.extend_from_slice(c.encode_utf8(&mut b).as_bytes());
+                        }
+                        _ => return Err(self.make_error(&format!("Invalid escape sequence '\\{}'", char::from(b)))),
+                    }
+                }
+                0 ..= 0x1f if !self.plain => return Err(self.make_error("Unescaped control character in string")),
+                _ => buff.push(b),
+            }
+        }
+        String::from_utf8(buff).map_err(|e| self.make_error(&e.to_string()))
+    }
+    fn read_number(&mut self) -> Result<Value, ReadError> {
+        let mut s = String::new();
+        while let b'0' ..= b'9' | b'-' | b'+' | b'.' | b'e' | b'E' = self.peek_byte() {
+            s.push(char::from(self.get_byte()?));
+        }
+        // `+` only in exponent, plain mode accepts leading `+` too
+        let sign_ok = |(ix, c): (usize, char)| c != '+' || (ix == 0 && self.plain) || s[.. ix].ends_with(['e', 'E'].as_ref());
+        if !s.char_indices().all(sign_ok) {
+            return Err(self.make_error(&format!("Invalid number: {}", s)))
+        }
+        if s.contains(['.', 'e', 'E'].as_ref()) {
+            return s.parse::<f64>()
+                .map(Value::from)
+                .map_err(|_| self.make_error(&format!("Invalid number: {}", s)))
+        }
+        if let Ok(n) = s.parse::<i64>() {
+            return Ok(Value::from(n))
+        }
+        // too big for Int
+        if self.plain {
+            return s.parse::<f64>()
+                .map(Value::from)
+                .map_err(|_| self.make_error(&format!("Invalid number: {}", s)))
+        }
+        s.parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| self.make_error(&format!("Invalid number: {}", s)))
+    }
+    fn read_token(&mut self, token: &str) -> Result<(), ReadError> {
+        for c in token.as_bytes() {
+            if self.get_byte()? != *c {
+                return Err(self.make_error(&format!("Invalid token, expected '{}'", token)))
+            }
+        }
+        Ok(())
+    }
+    fn read_list(&mut self) -> Result<Value, ReadError> {
+        self.get_byte()?; // eat '['
+        let mut lst = Vec::new();
+        self.skip_white()?;
+        if self.peek_byte() == b']' {
+            self.get_byte()?;
+            return Ok(Value::from(lst))
+        }
+        loop {
+            lst.push(self.read()?);
+            if self.read_item_separator(b']', "list item")? {
+                break;
+            }
+        }
+        Ok(Value::from(lst))
+    }
+    /// Read first key of an object after `{`, `None` when the object is empty
+    fn read_first_key(&mut self) -> Result<Option<String>, ReadError> {
+        self.skip_white()?;
+        if self.peek_byte() == b'}' {
+            self.get_byte()?;
+            return Ok(None)
+        }
+        Ok(Some(self.read_str()?))
+    }
+    fn read_object(&mut self, first_key: Option<String>) -> Result<Value, ReadError> {
+        let first_key = match first_key {
+            None => return Ok(Value::from(Map::new())),
+            Some(key) => key,
+        };
+        if first_key.starts_with('$') && !first_key.starts_with("$$") && !self.plain {
+            let v = self.read_wrapped(&first_key)?;
+            self.expect(b'}', &format!("after {} wrapper", first_key))?;
+            return Ok(v)
+        }
+        let mut map = Map::new();
+        let mut key = first_key;
+        loop {
+            if !self.plain && key.starts_with("$$") {
+                key.remove(0);
+            } else if !self.plain && key.starts_with('$') {
+                return Err(self.make_error(&format!("Unexpected wrapper key: {}", key)))
+            }
+            self.expect(b':', "after map key")?;
+            let val = self.read()?;
+            map.insert(key, val);
+            if self.read_item_separator(b'}', "map item")? {
+                break;
+            }
+            key = self.read_str()?;
+        }
+        Ok(Value::from(map))
+    }
+    fn read_wrapped(&mut self, key: &str) -> Result<Value, ReadError> {
+        if key == "$meta" {
+            // meta is not wanted by caller
+            self.read_meta_wrapper()?;
+            return self.read_value()
+        }
+        self.expect(b':', "after wrapper key")?;
+        if key == "$imap" {
+            return self.read_imap()
+        }
+        self.skip_white()?;
+        let v = if self.peek_byte() == b'"' {
+            Value::from(self.read_str()?)
+        } else {
+            self.read_number()?
+        };
+        let wrapped = match (key, v) {
+            ("$uint", Value::Int(n)) if n >= 0 => Some(Value::from(n as u64)),
+            ("$uint", v @ Value::UInt(_)) => Some(v),
+            ("$double", Value::String(s)) => match s.as_str() {
+                "NaN" => Some(Value::from(f64::NAN)),
+                "inf" => Some(Value::from(f64::INFINITY)),
+                "-inf" => Some(Value::from(f64::NEG_INFINITY)),
+                _ => None,
+            },
+            ("$decimal", Value::String(s)) => s.split_once('e')
+                .and_then(|(mantissa, exponent)| Some(Decimal::new(mantissa.parse().ok()?, exponent.parse().ok()?)))
+                .map(Value::from),
+            ("$datetime", Value::String(s)) => DateTime::from_iso_str(&s).ok().map(Value::from),
+            ("$blob", Value::String(s)) => decode_hex(&s).map(Value::from),
+            ("$uint", _) | ("$double", _) | ("$decimal", _) | ("$datetime", _) | ("$blob", _) => None,
+            _ => return Err(self.make_error(&format!("Unknown wrapper key: {}", key))),
+        };
+        wrapped.ok_or_else(|| self.make_error(&format!("Invalid {} value", key)))
+    }
+    fn read_imap(&mut self) -> Result<Value, ReadError> {
+        self.expect(b'{', "in $imap wrapper")?;
+        let mut map = IMap::new();
+        let mut key = self.read_first_key()?;
+        while let Some(k) = key {
+            let k: i32 = k.parse().map_err(|_| self.make_error(&format!("Invalid IMap key: {}", k)))?;
+            self.expect(b':', "after IMap key")?;
+            let val = self.read()?;
+            map.insert(k, val);
+            key = if self.read_item_separator(b'}', "IMap item")? { None } else { Some(self.read_str()?) };
+        }
+        Ok(Value::from(map))
+    }
+    /// Read `:[[key,value],...],"$value":` following `{"$meta"`
+    fn read_meta_wrapper(&mut self) -> Result<MetaMap, ReadError> {
+        self.expect(b':', "after $meta key")?;
+        self.expect(b'[', "in $meta wrapper")?;
+        let mut map = MetaMap::new();
+        self.skip_white()?;
+        if self.peek_byte() == b']' {
+            self.get_byte()?;
+        } else {
+            loop {
+                self.expect(b'[', "in meta item")?;
+                self.skip_white()?;
+                let key = if self.peek_byte() == b'"' {
+                    MetaKey::Str(self.read_str()?)
+                } else {
+                    match self.read_number()? {
+                        Value::Int(n) if n >= i32::MIN as i64 && n <= i32::MAX as i64 => MetaKey::Int(n as i32),
+                        _ => return Err(self.make_error("Meta key must be Int or String")),
+                    }
+                };
+                self.expect(b',', "after meta key")?;
+                let val = self.read()?;
+                match key {
+                    MetaKey::Int(i) => map.insert(i, val),
+                    MetaKey::Str(s) => map.insert(s.as_str(), val),
+                };
+                self.expect(b']', "after meta item")?;
+                if self.read_item_separator(b']', "meta item")? {
+                    break;
+                }
+            }
+        }
+        self.expect(b',', "after $meta")?;
+        if self.read_str()? != "$value" {
+            return Err(self.make_error("Expected $value key after $meta"))
+        }
+        self.expect(b':', "after $value key")?;
+        Ok(map)
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes().chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => Some((char::from(*hi).to_digit(16)? * 16 + char::from(*lo).to_digit(16)?) as u8),
+            _ => None,
+        })
+        .collect()
+}
+
+impl<'a, R> Reader for JsonReader<'a, R>
+    where R: ByteSource
+{
+    fn try_read_meta(&mut self) -> Result<Option<MetaMap>, ReadError> {
+        self.skip_white()?;
+        if self.plain || self.peek_byte() != b'{' {
+            return Ok(None)
+        }
+        self.get_byte()?;
+        let key = self.read_first_key()?;
+        if key.as_deref() == Some("$meta") {
+            let map = self.read_meta_wrapper()?;
+            self.in_meta_wrapper = true;
+            return Ok(Some(map))
+        }
+        self.first_key = Some(key);
+        Ok(None)
+    }
+    fn read_value(&mut self) -> Result<Value, ReadError> {
+        let in_meta_wrapper = core::mem::take(&mut self.in_meta_wrapper);
+        let v = match self.first_key.take() {
+            Some(key) => self.read_object(key)?,
+            None => {
+                self.skip_white()?;
+                let b = self.peek_byte();
+                match &b {
+                    b'0' ..= b'9' | b'-' => self.read_number(),
+                    b'+' if self.plain => self.read_number(),
+                    b'"' => self.read_str().map(Value::from),
+                    b'[' => self.read_list(),
+                    b'{' => {
+                        self.get_byte()?;
+                        let key = self.read_first_key()?;
+                        self.read_object(key)
+                    }
+                    b't' => self.read_token("true").map(|_| Value::from(true)),
+                    b'f' => self.read_token("false").map(|_| Value::from(false)),
+                    b'n' => self.read_token("null").map(|_| Value::Null),
+                    0 => Err(self.make_error("Unexpected end of stream, expected value")),
+                    _ => Err(self.make_error(&format!("Invalid char '{}', code: {}, expected value", char::from(b), b))),
+                }?
+            }
+        };
+        if in_meta_wrapper {
+            self.expect(b'}', "after $value")?;
+        }
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::RpcValue;
+    use crate::json::{JsonBlobFormat, JsonWriterOptions};
+
+    fn json(cpon: &str) -> String {
+        RpcValue::from_cpon(cpon).unwrap().to_json().unwrap()
+    }
+    fn cpon(json: &str) -> String {
+        RpcValue::from_json(json).unwrap().to_cpon()
+    }
+
+    #[test]
+    fn test_write() {
+        assert_eq!(json(r#"[null,true,-1,2u,"a\"\n\u0001"]"#), r#"[null,true,-1,{"$uint":2},"a\"\n\u0001"]"#);
+        assert_eq!(json("[1.25,100.]"), r#"[{"$decimal":"125e-2"},{"$decimal":"100e0"}]"#);
+        assert_eq!(RpcValue::from(vec![RpcValue::from(1.5), RpcValue::from(-0.5e-6)]).to_json().unwrap(), "[1.5,-5e-7]");
+        assert_eq!(json(r#"[x"0061",d"2021-11-08T01:02:03.456+01"]"#), r#"[{"$blob":"0061"},{"$datetime":"2021-11-08T01:02:03.456+01"}]"#);
+        assert_eq!(json(r#"{"$a":1,"b":i{1:2}}"#), r#"{"$$a":1,"b":{"$imap":{"1":2}}}"#);
+        assert_eq!(json(r#"<1:2,"foo":<3:4>5>[<6:7>{}]"#), r#"{"$meta":[[1,2],["foo",{"$meta":[[3,4]],"$value":5}]],"$value":[{"$meta":[[6,7]],"$value":{}}]}"#);
+        assert_eq!(RpcValue::from(f64::NEG_INFINITY).to_json().unwrap(), r#"{"$double":"-inf"}"#);
+    }
+
+    #[test]
+    fn test_read() {
+        assert_eq!(cpon(" [ 1 , -2.5 , 1E3, \"\\u00e9\\/\" , {} , [ ] , { \"a\" : null } ] "), r#"[1,-2.5e0,1e3,"é/",{},[],{"a":null}]"#);
+        assert_eq!(cpon("18446744073709551615"), "18446744073709551615u");
+        assert_eq!(cpon("1e+2"), "1e2");
+        assert_eq!(cpon(r#"{ "$meta" : [ [1, 2] ], "$value" : { "$$a" : { "$imap" : { "-1" : 2 } } } }"#), r#"<1:2>{"$a":i{-1:2}}"#);
+        assert_eq!(cpon(r#"{"$meta":[],"$value":1}"#), "1");
+        assert_eq!(cpon(r#"{"$decimal":"-5e-10"}"#), RpcValue::from(crate::Decimal::new(-5, -10)).to_cpon());
+        for json in ["{\"$foo\":1}", "{\"a\":1,\"$uint\":1}", "{\"$uint\":-1}", "{\"$blob\":\"0\"}", "{\"$meta\":[[1.5,1]],\"$value\":1}",
+                "{\"$meta\":[]}", "{\"$uint\":1,\"a\":2}", "[1 2]", "\"\n\"", "01x", "+1", "[-1,+2.5]", "-+1", "1.5+2"].iter() {
+            assert!(RpcValue::from_json(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for cpon in [r#"<1:2,"a":"b">{"$$":<"x":1u>[1,2u,1.23,d"2021-11-08T01:02:03Z",x"00ff",i{-1:null,2:{}}]}"#,
+                r#""\u0000\t\\""#].iter() {
+            let rv = RpcValue::from_cpon(cpon).unwrap();
+            assert_eq!(RpcValue::from_json(&rv.to_json().unwrap()).unwrap(), rv, "{}", cpon);
+        }
+        for n in [-3.5e-1, 1e-323, -0.0, f64::MAX].iter() {
+            let rv = RpcValue::from(*n);
+            assert_eq!(RpcValue::from_json(&rv.to_json().unwrap()).unwrap(), rv);
+        }
+        let rv = RpcValue::from(f64::NAN);
+        assert!(RpcValue::from_json(&rv.to_json().unwrap()).unwrap().as_f64().is_nan());
+    }
+
+    #[test]
+    fn test_plain_write() {
+        let plain = |cpon: &str, options: &JsonWriterOptions| RpcValue::from_cpon(cpon).unwrap().to_json_with_options(options).unwrap();
+        let opts = JsonWriterOptions { plain: true, ..JsonWriterOptions::default() };
+        assert_eq!(plain(r#"[1,2u,1.25,-0.05,100.,0.,-1e-3,{"$a":i{1:"x"}}]"#, &opts), r#"[1,2,1.25,-0.05,100,0,-0.001,{"$a":{"1":"x"}}]"#);
+        assert_eq!(plain(r#"[d"2021-11-08T01:02:03.456+01",d"2021-11-08T01:02:03Z"]"#, &opts), r#"["2021-11-08T01:02:03.456+01:00","2021-11-08T01:02:03.000Z"]"#);
+        assert_eq!(plain(r#"[x"",x"66",x"666f",x"666f6f",x"666f6f62"]"#, &opts), r#"["","Zg==","Zm8=","Zm9v","Zm9vYg=="]"#);
+        assert_eq!(RpcValue::from(vec![RpcValue::from(f64::NAN), RpcValue::from(0.5)]).to_json_with_options(&opts).unwrap(), "[null,0.5]");
+        assert_eq!(plain(r#"<1:2,"a":<3:4>5>{"b":x"ff"}"#, &opts), r#"{"b":"/w=="}"#);
+        let opts = JsonWriterOptions { plain: true, blob_format: JsonBlobFormat::Hex, meta_key: Some("_meta".into()) };
+        assert_eq!(plain(r#"<1:2,"a":<3:4>5>{"b":x"ff"}"#, &opts), r#"{"_meta":{"1":2,"a":{"_meta":{"3":4},"value":5}},"value":{"b":"ff"}}"#);
+    }
+
+    #[test]
+    fn test_plain_read() {
+        let plain = |json: &str| RpcValue::from_json_plain(json).unwrap().to_cpon();
+        assert_eq!(plain(r#"{"$meta":[[1,2]],"$value":{"$uint":1}}"#), r#"{"$meta":[[1,2]],"$value":{"$uint":1}}"#);
+        assert_eq!(plain("// comment\n[1, /* two */ 2.5, 18446744073709551616, {\"a\": [],},]\n"), r#"[1,2.5e0,1.8446744073709552e19,{"a":[]}]"#);
+        assert_eq!(plain("\"a\tb\""), r#""a\tb""#);
+        assert_eq!(plain("[+1,+2.5,1e+2]"), "[1,2.5e0,1e2]");
+        assert!(RpcValue::from_json_plain("[1,,]").is_err());
+        assert!(RpcValue::from_json_plain("[1] /").is_err());
+    }
+}
@@ -1,14 +1,23 @@
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+extern crate alloc;
+
 pub use datetime::DateTime;
 pub use decimal::Decimal;
-pub use metamap::MetaMap;
-pub use reader::{Reader, ReadError, ReadResult};
+pub use metamap::{MetaMap, MetaKey};
+pub use reader::{Diagnostic, Reader, ReadError, ReadResult, Values};
 pub use rpcmessage::{RpcMessage, RpcMessageMetaTags};
 pub use rpcvalue::{Blob, List, Map, RpcValue};
 pub use rpcvalue::Value;
 pub use writer::{Writer, WriteResult};
+pub use crate::io::{ByteSink, ByteSource, IoError};
 
-pub use crate::chainpack::{ChainPackReader, ChainPackWriter};
-pub use crate::cpon::{CponReader, CponWriter};
+pub use crate::chainpack::{ChainPackReader, ChainPackWriter, ChainPackWriterOptions, is_canonical};
+pub use crate::cpon::{BlobFormat, CponReader, CponWriter, CponWriterOptions, UnicodeEscape};
+pub use crate::json::{JsonBlobFormat, JsonReader, JsonWriter, JsonWriterOptions};
+#[cfg(feature = "cbor")]
+pub use crate::cbor::{CborReader, CborWriter, CBOR_META_TAG};
+#[cfg(feature = "msgpack")]
+pub use crate::msgpack::{MsgPackReader, MsgPackWriter, MSGPACK_DECIMAL_EXT, MSGPACK_META_EXT};
 
 mod datetime;
 mod decimal;
@@ -18,11 +27,40 @@ pub mod rpcvalue;
 pub mod rpctype;
 pub mod rpcframe;
 pub mod rpcmessage;
+pub mod visit;
+pub mod cpondoc;
+pub mod dump;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
 
+mod io;
 mod reader;
 mod writer;
 mod cpon;
 mod chainpack;
+mod json;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod diff;
+mod merge;
+mod logdisplay;
+
+#[doc(hidden)]
+pub use alloc::vec as __alloc_vec;
+
+pub type Error = Box<dyn core::error::Error + Send + Sync>;
+pub type Result<T> = core::result::Result<T, Error>;
 
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
-pub type Result<T> = std::result::Result<T, Error>;
+/// `alloc` counterparts of `std` prelude, so that modules compile without `std`
+mod prelude {
+    pub(crate) use alloc::boxed::Box;
+    pub(crate) use alloc::format;
+    pub(crate) use alloc::string::{String, ToString};
+    // std prelude has its own vec! macro
+    #[allow(unused_imports)]
+    pub(crate) use alloc::vec;
+    pub(crate) use alloc::vec::Vec;
+}
+use crate::prelude::*;
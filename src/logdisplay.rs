@@ -0,0 +1,391 @@
+use core::fmt;
+use core::convert::TryFrom;
+use alloc::sync::Arc;
+use alloc::borrow::Cow;
+use crate::{CponWriter, MetaMap, RpcValue, Value};
+use crate::metamap::MetaKey;
+use crate::rpcmessage::{Key, Tag};
+use crate::writer::Writer;
+use crate::prelude::*;
+
+const REDACTED: &str = "***";
+
+/// Limits applied by [`LogDisplay`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LogLimits {
+    /// containers nested deeper are shown as `[...]`, `{...}`, `i{...}` or `<...>`
+    pub max_depth: usize,
+    /// max number of `List`, `Map`, `IMap` and `MetaMap` items shown
+    pub max_items: usize,
+    /// max number of `Blob` bytes shown
+    pub max_blob: usize,
+    /// max number of `String` chars shown
+    pub max_str: usize,
+}
+
+impl Default for LogLimits {
+    fn default() -> Self {
+        LogLimits {
+            max_depth: 3,
+            max_items: 10,
+            max_blob: 32,
+            max_str: 120,
+        }
+    }
+}
+
+impl LogLimits {
+    /// Nothing is truncated
+    pub fn unlimited() -> Self {
+        LogLimits {
+            max_depth: usize::MAX,
+            max_items: usize::MAX,
+            max_blob: usize::MAX,
+            max_str: usize::MAX,
+        }
+    }
+}
+
+/// Secrets replaced by `"***"` when `RpcMessage` is logged.
+///
+/// Paths are `List`s of segments as in [`crate::rpcvalue::Patch`], `String` for `Map` keys,
+/// `Int` for `IMap` keys and `List` indexes, starting at the message root.
+/// `Redaction::default()` hides the `login` method password and `Tag::AccessGrant`,
+/// `Redaction::new()` hides nothing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redaction {
+    tags: Vec<i32>,
+    paths: Vec<(Option<String>, Vec<RpcValue>)>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Redaction::new()
+            .tag(Tag::AccessGrant as i32)
+            .method_path("login", &[RpcValue::from(Key::Params as i32), RpcValue::from("login"), RpcValue::from("password")])
+    }
+}
+
+impl Redaction {
+    pub fn new() -> Self {
+        Redaction { tags: Vec::new(), paths: Vec::new() }
+    }
+    /// Redact message meta tag
+    pub fn tag(mut self, tag: i32) -> Self {
+        self.tags.push(tag);
+        self
+    }
+    /// Redact value on `path` in every message
+    pub fn path(mut self, path: &[RpcValue]) -> Self {
+        self.paths.push((None, path.to_vec()));
+        self
+    }
+    /// Redact value on `path` in messages with `Tag::Method` equal to `method`
+    pub fn method_path(mut self, method: &str, path: &[RpcValue]) -> Self {
+        self.paths.push((Some(method.to_string()), path.to_vec()));
+        self
+    }
+
+    /// `Redaction::default()` built once and borrowed by every `RpcMessage` `Display`
+    #[cfg(feature = "std")]
+    pub(crate) fn shared_default() -> Cow<'static, Redaction> {
+        static DEFAULT: std::sync::OnceLock<Redaction> = std::sync::OnceLock::new();
+        Cow::Borrowed(DEFAULT.get_or_init(Redaction::default))
+    }
+    /// No `OnceLock` without `std`, default is built for every use
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn shared_default() -> Cow<'static, Redaction> {
+        Cow::Owned(Redaction::default())
+    }
+
+    /// Return copy of `rv` with secrets replaced, `rv` itself is not touched.
+    /// Containers are shared with `rv`, only those on redacted paths are copied.
+    pub fn apply(&self, rv: &RpcValue) -> RpcValue {
+        let mut rv = rv.clone();
+        if self.tags.iter().any(|tag| rv.meta().get(*tag).is_some()) {
+            let mm = self.apply_meta(rv.meta());
+            rv = rv.set_meta(Some(mm));
+        }
+        let method = rv.meta().get(Tag::Method as i32).map(|m| m.as_str().to_string());
+        for (rule_method, path) in self.paths.iter() {
+            if rule_method.is_some() && rule_method != &method {
+                continue;
+            }
+            if path_exists(&rv, path) {
+                redact_path(&mut rv, path);
+            }
+        }
+        rv
+    }
+    /// Return copy of message meta with redacted tags replaced
+    pub fn apply_meta(&self, mm: &MetaMap) -> MetaMap {
+        let mut mm = mm.clone();
+        for tag in self.tags.iter() {
+            if let Some(v) = mm.get_mut(*tag) {
+                *v = RpcValue::from(REDACTED);
+            }
+        }
+        mm
+    }
+}
+
+
+fn path_exists(rv: &RpcValue, path: &[RpcValue]) -> bool {
+    let (seg, rest) = match path.split_first() {
+        None => return true,
+        Some(p) => p,
+    };
+    let child = match (rv.value(), seg.value()) {
+        (Value::Map(map), Value::String(key)) => map.get(key.as_str()),
+        (Value::IMap(map), Value::Int(key)) => match i32::try_from(*key) {
+            Ok(key) => map.get(&key),
+            Err(_) => None,
+        },
+        (Value::List(lst), Value::Int(ix)) => match usize::try_from(*ix) {
+            Ok(ix) => lst.get(ix),
+            Err(_) => None,
+        },
+        _ => None,
+    };
+    match child {
+        Some(child) => path_exists(child, rest),
+        None => false,
+    }
+}
+
+fn redact_path(rv: &mut RpcValue, path: &[RpcValue]) {
+    let (seg, rest) = match path.split_first() {
+        None => {
+            *rv = RpcValue::from(REDACTED);
+            return
+        }
+        Some(p) => p,
+    };
+    let child = match (rv.value_mut(), seg.value()) {
+        (Value::Map(map), Value::String(key)) => Arc::make_mut(map).get_mut(key.as_str()),
+        (Value::IMap(map), Value::Int(key)) => match i32::try_from(*key) {
+            Ok(key) => Arc::make_mut(map).get_mut(&key),
+            Err(_) => None,
+        },
+        (Value::List(lst), Value::Int(ix)) => match usize::try_from(*ix) {
+            Ok(ix) => Arc::make_mut(lst).get_mut(ix),
+            Err(_) => None,
+        },
+        _ => None,
+    };
+    if let Some(child) = child {
+        redact_path(child, rest);
+    }
+}
+
+/// Truncating Cpon `Display` of `RpcValue` intended for logging.
+///
+/// Elided parts are replaced by `...`, truncated blobs are followed by their size in a Cpon comment.
+/// ```
+/// use chainpack::RpcValue;
+/// let rv = RpcValue::from_cpon(r#"{"a":[1,2,3,4],"b":"foobar"}"#).unwrap();
+/// assert_eq!(rv.log_display().max_items(2).max_str(3).to_string(), r#"{"a":[1,2,...],"b":"foo..."}"#);
+/// ```
+pub struct LogDisplay<'a> {
+    value: &'a RpcValue,
+    limits: LogLimits,
+    redaction: Option<Cow<'a, Redaction>>,
+}
+
+impl<'a> LogDisplay<'a> {
+    pub fn new(value: &'a RpcValue) -> Self {
+        LogDisplay { value, limits: LogLimits::default(), redaction: None }
+    }
+    /// Replace secrets according to `redaction`
+    pub fn redact(mut self, redaction: &'a Redaction) -> Self {
+        self.redaction = Some(Cow::Borrowed(redaction));
+        self
+    }
+    /// Replace secrets according to `Redaction::default()`
+    pub(crate) fn redact_default(mut self) -> Self {
+        self.redaction = Some(Redaction::shared_default());
+        self
+    }
+    /// Do not replace secrets
+    pub fn no_redact(mut self) -> Self {
+        self.redaction = None;
+        self
+    }
+    pub fn limits(mut self, limits: LogLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    pub fn max_depth(mut self, n: usize) -> Self {
+        self.limits.max_depth = n;
+        self
+    }
+    pub fn max_items(mut self, n: usize) -> Self {
+        self.limits.max_items = n;
+        self
+    }
+    pub fn max_blob(mut self, n: usize) -> Self {
+        self.limits.max_blob = n;
+        self
+    }
+    pub fn max_str(mut self, n: usize) -> Self {
+        self.limits.max_str = n;
+        self
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, rv: &RpcValue, depth: usize) -> fmt::Result {
+        let mm = rv.meta();
+        if !mm.is_empty() {
+            self.write_meta(f, mm, depth)?;
+        }
+        self.write_value(f, rv.value(), depth)
+    }
+    fn write_meta(&self, f: &mut fmt::Formatter<'_>, mm: &MetaMap, depth: usize) -> fmt::Result {
+        let items = mm.0.iter().map(|kv| {
+            let key = match &kv.key {
+                MetaKey::Int(i) => i.to_string(),
+                MetaKey::Str(s) => to_cpon(&Value::from(s)),
+            };
+            (Some(key), &kv.value)
+        });
+        self.write_container(f, "<", ">", mm.0.len(), items, depth)
+    }
+    fn write_value(&self, f: &mut fmt::Formatter<'_>, val: &Value, depth: usize) -> fmt::Result {
+        match val {
+            Value::String(s) => {
+                match s.char_indices().nth(self.limits.max_str) {
+                    None => f.write_str(&to_cpon(val)),
+                    Some((ix, _)) => {
+                        let mut cpon = to_cpon(&Value::from(&s[.. ix]));
+                        cpon.pop();
+                        write!(f, "{}...\"", cpon)
+                    }
+                }
+            }
+            Value::Blob(b) => {
+                if b.len() > self.limits.max_blob {
+                    let mut cpon = to_cpon(&Value::from(&b[.. self.limits.max_blob]));
+                    cpon.pop();
+                    write!(f, "{}...\"/*{} bytes*/", cpon, b.len())
+                } else {
+                    f.write_str(&to_cpon(val))
+                }
+            }
+            Value::List(lst) => {
+                let items = lst.iter().map(|v| (None, v));
+                self.write_container(f, "[", "]", lst.len(), items, depth)
+            }
+            Value::Map(map) => {
+                let items = map.iter().map(|(k, v)| (Some(to_cpon(&Value::from(k))), v));
+                self.write_container(f, "{", "}", map.len(), items, depth)
+            }
+            Value::IMap(map) => {
+                let items = map.iter().map(|(k, v)| (Some(k.to_string()), v));
+                self.write_container(f, "i{", "}", map.len(), items, depth)
+            }
+            _ => f.write_str(&to_cpon(val)),
+        }
+    }
+    fn write_container<'b>(&self, f: &mut fmt::Formatter<'_>, open: &str, close: &str, len: usize,
+                           items: impl Iterator<Item = (Option<String>, &'b RpcValue)>, depth: usize) -> fmt::Result {
+        f.write_str(open)?;
+        if len > 0 && depth >= self.limits.max_depth {
+            f.write_str("...")?;
+            return f.write_str(close)
+        }
+        for (n, (key, val)) in items.enumerate() {
+            if n > 0 {
+                f.write_str(",")?;
+            }
+            if n == self.limits.max_items {
+                f.write_str("...")?;
+                break;
+            }
+            if let Some(key) = key {
+                write!(f, "{}:", key)?;
+            }
+            self.write(f, val, depth + 1)?;
+        }
+        f.write_str(close)
+    }
+}
+
+impl fmt::Display for LogDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.redaction {
+            Some(redaction) => self.write(f, &redaction.apply(self.value), 0),
+            None => self.write(f, self.value, 0),
+        }
+    }
+}
+
+fn to_cpon(val: &Value) -> String {
+    let mut buff: Vec<u8> = Vec::new();
+    let mut wr = CponWriter::new(&mut buff);
+    match wr.write_value(val) {
+        Ok(_) => String::from_utf8(buff).unwrap_or_default(),
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{RpcMessage, RpcMessageMetaTags, RpcValue};
+    use crate::rpcframe::{Protocol, RpcFrame};
+    use crate::rpcmessage::Tag;
+    use crate::rpcvalue::Redaction;
+
+    fn log(cpon: &str) -> RpcValue {
+        RpcValue::from_cpon(cpon).unwrap()
+    }
+
+    #[test]
+    fn log_display() {
+        let rv = log(r#"<1:2,"foo":"bar">{"a":[1,[2,[3,[4]]]],"b":i{1:2},"c":[]}"#);
+        assert_eq!(rv.log_display().max_depth(5).to_string(), rv.to_cpon());
+        assert_eq!(rv.log_display().to_string(), r#"<1:2,"foo":"bar">{"a":[1,[2,[...]]],"b":i{1:2},"c":[]}"#);
+        assert_eq!(rv.log_display().max_depth(2).to_string(), r#"<1:2,"foo":"bar">{"a":[1,[...]],"b":i{1:2},"c":[]}"#);
+        assert_eq!(rv.log_display().max_depth(0).to_string(), r#"<...>{...}"#);
+        assert_eq!(rv.log_display().max_items(1).to_string(), r#"<1:2,...>{"a":[1,...],...}"#);
+        assert_eq!(rv.log_display().max_items(0).to_string(), r#"<...>{...}"#);
+    }
+
+    #[test]
+    fn log_display_truncate() {
+        let rv = log(r#"["ab\"cd", "řžč", b"\00\01\02\03"]"#);
+        assert_eq!(rv.log_display().max_str(3).max_blob(2).to_string(), r#"["ab\"...","řžč",b"\00\01..."/*4 bytes*/]"#);
+        assert_eq!(rv.log_display().max_str(0).max_blob(0).to_string(), r#"["...","...",b"..."/*4 bytes*/]"#);
+    }
+
+    #[test]
+    fn log_display_frame() {
+        let msg = RpcMessage::create_request_with_id(1, "shv/test", "setBlob", Some(RpcValue::from(vec![0u8; 1000])));
+        let frame = RpcFrame::from_rpcmessage(Protocol::ChainPack, &msg).unwrap();
+        assert_eq!(format!("{:#}", frame), format!(r#"{{proto:ChainPack, message:<1:1,8:1,9:"shv/test",10:"setBlob">i{{1:b"{}..."/*1000 bytes*/}}}}"#, "\\00".repeat(32)));
+    }
+
+    #[test]
+    fn redaction() {
+        let params = log(r#"{"login":{"password":"secret","user":"root"},"options":{}}"#);
+        let mut msg = RpcMessage::create_request_with_id(1, "", "login", Some(params));
+        msg.set_tag(Tag::AccessGrant as i32, Some(RpcValue::from("token")));
+        assert_eq!(msg.to_string(), r#"<1:1,8:1,10:"login",14:"***">i{1:{"login":{"password":"***","user":"root"},"options":{}}}"#);
+        assert_eq!(msg.to_cpon(), r#"<1:1,8:1,10:"login",14:"token">i{1:{"login":{"password":"secret","user":"root"},"options":{}}}"#);
+        assert_eq!(msg.log_display().no_redact().to_string(), msg.to_cpon());
+        let frame = RpcFrame::from_rpcmessage(Protocol::Cpon, &msg).unwrap();
+        assert_eq!(format!("{}", frame), r#"{proto:Cpon, meta:<1:1,8:1,10:"login",14:"***">, data len: 63}"#);
+        assert_eq!(format!("{:#}", frame), format!("{{proto:Cpon, message:{}}}", msg));
+
+        let redaction = Redaction::new().path(&[RpcValue::from(1), RpcValue::from("options")]);
+        assert_eq!(msg.log_display().redact(&redaction).to_string(), r#"<1:1,8:1,10:"login",14:"token">i{1:{"login":{"password":"secret","user":"root"},"options":"***"}}"#);
+        // out of range key matches nothing
+        let redaction = Redaction::new().path(&[RpcValue::from((1i64 << 32) + 1)]);
+        assert_eq!(msg.log_display().redact(&redaction).to_string(), msg.to_cpon());
+        let msg = RpcMessage::create_request_with_id(1, "", "ls", Some(log(r#"{"login":{"password":"secret"}}"#)));
+        assert_eq!(msg.to_string(), msg.to_cpon());
+        // nothing to redact, nothing copied
+        let rv = msg.as_rpcvalue();
+        assert!(Redaction::default().apply(rv).is_same(rv));
+        #[cfg(feature = "std")]
+        assert!(matches!(rv.log_display().redact_default().redaction, Some(alloc::borrow::Cow::Borrowed(_))));
+    }
+}
@@ -0,0 +1,218 @@
+use alloc::sync::Arc;
+use crate::{MetaMap, RpcValue, Value};
+use crate::metamap::MetaKey;
+use crate::rpcvalue::MapRemove;
+use crate::prelude::*;
+
+/// How to merge two lists
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ListMerge {
+    /// other list replaces the original one
+    Replace,
+    /// items of other list are appended to the original one
+    Append,
+    /// items on the same index are merged, extra items of other list are appended
+    MergeByIndex,
+}
+
+/// What to do when merged values have different types, `Null` never conflicts
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TypeConflict {
+    /// other value replaces the original one
+    Override,
+    /// merge fails with error
+    Error,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MergePolicy {
+    pub lists: ListMerge,
+    /// `null` in other `Map`, `IMap` or `MetaMap` removes the key instead of setting it to `null`
+    pub null_deletes: bool,
+    pub type_conflict: TypeConflict,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy {
+            lists: ListMerge::Replace,
+            null_deletes: false,
+            type_conflict: TypeConflict::Override,
+        }
+    }
+}
+
+fn merge_meta(dst: &mut RpcValue, src: &MetaMap, policy: &MergePolicy, path: &mut Vec<RpcValue>) -> crate::Result<()> {
+    if src.is_empty() {
+        return Ok(())
+    }
+    if !dst.has_meta() {
+        let v = core::mem::replace(dst, RpcValue::null());
+        *dst = v.set_meta(Some(MetaMap::new()));
+    }
+    if let Some(mm) = dst.meta_mut() {
+        for kv in src.0.iter() {
+            if kv.value.is_null() && policy.null_deletes {
+                match &kv.key {
+                    MetaKey::Int(i) => mm.remove(*i),
+                    MetaKey::Str(s) => mm.remove(s.as_str()),
+                };
+                continue;
+            }
+            let (dst_val, key) = match &kv.key {
+                MetaKey::Int(i) => (mm.get_mut(*i), RpcValue::from(*i)),
+                MetaKey::Str(s) => (mm.get_mut(s.as_str()), RpcValue::from(s)),
+            };
+            match dst_val {
+                Some(v) => {
+                    path.push(key);
+                    merge_helper(v, &kv.value, policy, path)?;
+                    path.pop();
+                }
+                None => {
+                    match &kv.key {
+                        MetaKey::Int(i) => mm.insert(*i, kv.value.clone()),
+                        MetaKey::Str(s) => mm.insert(s.as_str(), kv.value.clone()),
+                    };
+                }
+            }
+        }
+    }
+    if dst.meta().is_empty() {
+        dst.clear_meta();
+    }
+    Ok(())
+}
+
+fn merge_helper(dst: &mut RpcValue, src: &RpcValue, policy: &MergePolicy, path: &mut Vec<RpcValue>) -> crate::Result<()> {
+    match (dst.value_mut(), src.value()) {
+        (Value::Map(dm), Value::Map(sm)) => {
+            let dm = Arc::make_mut(dm);
+            for (k, v) in sm.iter() {
+                if v.is_null() && policy.null_deletes {
+                    dm.remove_key(k.as_str());
+                    continue;
+                }
+                match dm.get_mut(k) {
+                    Some(dv) => {
+                        path.push(RpcValue::from(k));
+                        merge_helper(dv, v, policy, path)?;
+                        path.pop();
+                    }
+                    None => { dm.insert(k.clone(), v.clone()); }
+                }
+            }
+        }
+        (Value::IMap(dm), Value::IMap(sm)) => {
+            let dm = Arc::make_mut(dm);
+            for (k, v) in sm.iter() {
+                if v.is_null() && policy.null_deletes {
+                    dm.remove_key(k);
+                    continue;
+                }
+                match dm.get_mut(k) {
+                    Some(dv) => {
+                        path.push(RpcValue::from(*k));
+                        merge_helper(dv, v, policy, path)?;
+                        path.pop();
+                    }
+                    None => { dm.insert(*k, v.clone()); }
+                }
+            }
+        }
+        (Value::List(dl), Value::List(sl)) => {
+            match policy.lists {
+                ListMerge::Replace => {
+                    *dl = sl.clone();
+                }
+                ListMerge::Append => {
+                    Arc::make_mut(dl).extend(sl.iter().cloned());
+                }
+                ListMerge::MergeByIndex => {
+                    let dl = Arc::make_mut(dl);
+                    for (ix, v) in sl.iter().enumerate() {
+                        match dl.get_mut(ix) {
+                            Some(dv) => {
+                                path.push(RpcValue::from(ix as i64));
+                                merge_helper(dv, v, policy, path)?;
+                                path.pop();
+                            }
+                            None => dl.push(v.clone()),
+                        }
+                    }
+                }
+            }
+        }
+        (dv, sv) => {
+            let conflict = !matches!(dv, Value::Null) && !matches!(sv, Value::Null) && dv.type_name() != sv.type_name();
+            if conflict && policy.type_conflict == TypeConflict::Error {
+                return Err(format!("Merge type conflict {} vs {}, path: {}", dv.type_name(), sv.type_name(), RpcValue::from(path.clone())).into())
+            }
+            *dv = sv.clone();
+        }
+    }
+    merge_meta(dst, src.meta(), policy, path)
+}
+
+impl RpcValue {
+    /// Recursively merge `other` into `self`.
+    ///
+    /// `Map`s and `IMap`s are merged key by key, `MetaMap`s tag by tag, lists according to `policy.lists`,
+    /// any other value is replaced by `other`.
+    /// When merge fails on type conflict, `self` is left untouched.
+    pub fn merge(&mut self, other: &RpcValue, policy: MergePolicy) -> crate::Result<()> {
+        let mut merged = self.clone();
+        let mut path = Vec::new();
+        merge_helper(&mut merged, other, &policy, &mut path)?;
+        *self = merged;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::RpcValue;
+    use crate::rpcvalue::{ListMerge, MergePolicy, TypeConflict};
+
+    fn merge(cpon1: &str, cpon2: &str, policy: MergePolicy) -> String {
+        let mut rv = RpcValue::from_cpon(cpon1).unwrap();
+        rv.merge(&RpcValue::from_cpon(cpon2).unwrap(), policy).unwrap();
+        rv.to_cpon()
+    }
+
+    #[test]
+    fn merge_maps() {
+        let p = MergePolicy::default();
+        assert_eq!(merge("1", "2", p), "2");
+        assert_eq!(merge(r#"{"a":1,"b":{"c":2,"d":3}}"#, r#"{"b":{"c":4},"e":5}"#, p), r#"{"a":1,"b":{"c":4,"d":3},"e":5}"#);
+        assert_eq!(merge(r#"i{1:i{2:3}}"#, r#"i{1:i{4:5},6:7}"#, p), r#"i{1:i{2:3,4:5},6:7}"#);
+        assert_eq!(merge(r#"{"a":1,"b":2}"#, r#"{"a":null}"#, p), r#"{"a":null,"b":2}"#);
+        let p = MergePolicy { null_deletes: true, ..MergePolicy::default() };
+        assert_eq!(merge(r#"{"a":1,"b":2}"#, r#"{"a":null}"#, p), r#"{"b":2}"#);
+        assert_eq!(merge(r#"<1:2,3:4>i{1:2}"#, r#"<1:null,5:6>i{1:3}"#, p), r#"<3:4,5:6>i{1:3}"#);
+        assert_eq!(merge(r#"<1:2>i{}"#, r#"<1:null>i{}"#, p), r#"i{}"#);
+    }
+
+    #[test]
+    fn merge_lists() {
+        let p = MergePolicy::default();
+        assert_eq!(merge(r#"{"a":[1,2,3]}"#, r#"{"a":[4]}"#, p), r#"{"a":[4]}"#);
+        let p = MergePolicy { lists: ListMerge::Append, ..MergePolicy::default() };
+        assert_eq!(merge(r#"{"a":[1,2,3]}"#, r#"{"a":[4]}"#, p), r#"{"a":[1,2,3,4]}"#);
+        let p = MergePolicy { lists: ListMerge::MergeByIndex, ..MergePolicy::default() };
+        assert_eq!(merge(r#"[{"a":1},2]"#, r#"[{"b":3},4,5]"#, p), r#"[{"a":1,"b":3},4,5]"#);
+    }
+
+    #[test]
+    fn merge_conflicts() {
+        let p = MergePolicy::default();
+        assert_eq!(merge(r#"{"a":{"b":1}}"#, r#"{"a":[1]}"#, p), r#"{"a":[1]}"#);
+        let p = MergePolicy { type_conflict: TypeConflict::Error, ..MergePolicy::default() };
+        assert_eq!(merge(r#"{"a":null}"#, r#"{"a":[1]}"#, p), r#"{"a":[1]}"#);
+        let mut rv = RpcValue::from_cpon(r#"{"a":{"b":1}}"#).unwrap();
+        assert!(rv.merge(&RpcValue::from_cpon(r#"{"a":{"b":"foo"}}"#).unwrap(), p).is_err());
+        let mut rv = RpcValue::from_cpon(r#"<1:2>{"a":1,"b":{"c":2},"d":3}"#).unwrap();
+        assert!(rv.merge(&RpcValue::from_cpon(r#"<1:3>{"a":4,"b":{"c":"foo"},"d":5}"#).unwrap(), p).is_err());
+        assert_eq!(rv.to_cpon(), r#"<1:2>{"a":1,"b":{"c":2},"d":3}"#);
+    }
+}
@@ -1,11 +1,9 @@
 use crate::rpcvalue::RpcValue;
-use std::fmt;
-use lazy_static::lazy_static;
+use core::fmt;
 use crate::{CponWriter, Writer};
+use crate::prelude::*;
 
-lazy_static! {
-    static ref NULL_RPCVALUE_REF: RpcValue = RpcValue::null();
-}
+static NULL_RPCVALUE_REF: RpcValue = RpcValue::null();
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum MetaKey {
@@ -61,7 +59,7 @@ pub struct MetaMap(pub(crate) Vec<MetaKeyVal>);
 
 impl MetaMap {
 
-    pub fn new() -> MetaMap {
+    pub const fn new() -> MetaMap {
         MetaMap(Vec::new())
     }
     pub fn is_empty(&self) -> bool {
@@ -99,6 +97,14 @@ impl MetaMap {
             None => None,
         }
     }
+    pub fn get_mut<I>(&mut self, key: I) -> Option<&mut RpcValue>
+        where I: GetIndex
+    {
+        match self.find(&key) {
+            Some(ix) => Some(&mut self.0[ix].value),
+            None => None,
+        }
+    }
     pub fn get_or_null<I>(&self, key: I) -> &RpcValue
         where I: GetIndex
     {
@@ -209,8 +215,9 @@ impl fmt::Display for MetaMap {
         let mut buff: Vec<u8> = Vec::new();
         let mut wr = CponWriter::new(&mut buff);
         let res = wr.write_meta(self);
-        if let Err(e) = res {
-            log::warn!("to_cpon write with error: {}", e);
+        if let Err(_err) = res {
+            #[cfg(feature = "log")]
+            log::warn!("to_cpon write with error: {}", _err);
             return write!(fmt, "<invalid>")
         }
         match String::from_utf8(buff) {
@@ -224,7 +231,7 @@ impl fmt::Display for MetaMap {
 mod test {
     use crate::metamap::MetaMap;
     use crate::rpcvalue::RpcValue;
-    use std::collections::BTreeMap;
+    use crate::rpcvalue::{Map, IMap};
 
     #[test]
     fn metamap_insert() {
@@ -246,7 +253,7 @@ mod test {
         mm.insert("list", RpcValue::from(v1));
         assert_eq!(mm.get_or_null("list").as_list(), &v2);
 
-        let mut v1: BTreeMap<i32, RpcValue> = BTreeMap::new();
+        let mut v1: IMap = IMap::new();
         v1.insert(1, RpcValue::from("foo"));
         v1.insert(2, RpcValue::from("bar"));
         v1.insert(3, RpcValue::from("baz"));
@@ -254,7 +261,7 @@ mod test {
         mm.insert("imap", RpcValue::from(v1));
         assert_eq!(mm.get_or_null("imap").as_imap(), &v2);
 
-        let mut v1: BTreeMap<String, RpcValue> = BTreeMap::new();
+        let mut v1: Map = Map::new();
         v1.insert("a".to_string(), RpcValue::from("foo"));
         v1.insert("b".to_string(), RpcValue::from("bar"));
         v1.insert("c".to_string(), RpcValue::from("baz"));
@@ -1,5 +1,6 @@
 use crate::RpcValue;
 use crate::rpcvalue::{List};
+use crate::prelude::*;
 
 #[derive(Copy, Clone, Debug)]
 pub enum Signature {
@@ -0,0 +1,508 @@
+use crate::prelude::*;
+use crate::io::{ByteSink, ByteSource, invalid_data};
+use crate::{RpcValue, MetaMap, Value, Decimal, DateTime};
+use crate::writer::{WriteResult, Writer, ByteWriter};
+use crate::metamap::MetaKey;
+use crate::reader::{Reader, ByteReader, ReadError};
+use crate::rpcvalue::{Map, IMap};
+
+/// MessagePack extension type of `Decimal`, data is big endian 64-bit mantissa followed by 8-bit exponent
+pub const MSGPACK_DECIMAL_EXT: i8 = 1;
+/// MessagePack extension type of value with meta, data is MessagePack map of meta with integer and string keys
+/// followed by the value
+pub const MSGPACK_META_EXT: i8 = 2;
+
+const TIMESTAMP_EXT: i8 = -1;
+
+/// MessagePack writer
+///
+/// | RpcValue          | MessagePack                                              |
+/// |-------------------|----------------------------------------------------------|
+/// | `Int`             | positive or negative fixint, int 8 - int 64              |
+/// | `UInt`            | uint 8 - uint 64                                         |
+/// | `Double`          | float 64                                                 |
+/// | `Decimal`         | ext `MSGPACK_DECIMAL_EXT`                                |
+/// | `DateTime`        | timestamp ext -1, UTC offset is not kept                 |
+/// | `String`, `Blob`  | str, bin                                                 |
+/// | `List`            | array                                                    |
+/// | `Map`, `IMap`     | map with string keys, map with integer keys              |
+/// | meta              | ext `MSGPACK_META_EXT`                                   |
+///
+/// Empty `IMap` is read back as empty `Map`.
+pub struct MsgPackWriter<'a, W>
+    where W: ByteSink
+{
+    byte_writer: ByteWriter<'a, W>,
+}
+
+impl<'a, W> MsgPackWriter<'a, W>
+    where W: ByteSink
+{
+    pub fn new(write: &'a mut W) -> Self {
+        MsgPackWriter { byte_writer: ByteWriter::new(write) }
+    }
+
+    fn write_byte(&mut self, b: u8) -> WriteResult {
+        self.byte_writer.write_byte(b)
+    }
+    fn write_bytes(&mut self, b: &[u8]) -> WriteResult {
+        self.byte_writer.write_bytes(b)
+    }
+    /// Write `fix_marker | len` for short length or marker of 8, 16 or 32-bit length
+    fn write_len(&mut self, len: usize, fix: Option<(u8, usize)>, markers: [Option<u8>; 3]) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        match (fix, markers) {
+            (Some((marker, max)), _) if len <= max => { self.write_byte(marker | len as u8)?; }
+            (_, [Some(marker), _, _]) if len <= 0xff => {
+                self.write_byte(marker)?;
+                self.write_byte(len as u8)?;
+            }
+            (_, [_, Some(marker), _]) if len <= 0xffff => {
+                self.write_byte(marker)?;
+                self.write_bytes(&(len as u16).to_be_bytes())?;
+            }
+            (_, [_, _, Some(marker)]) if len <= 0xffff_ffff => {
+                self.write_byte(marker)?;
+                self.write_bytes(&(len as u32).to_be_bytes())?;
+            }
+            _ => return Err(invalid_data(&format!("MessagePack length too big: {}", len))),
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_int(&mut self, n: i64) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        if (-32 ..= 127).contains(&n) {
+            self.write_byte(n as u8)?;
+        } else if n >= i8::MIN as i64 && n <= i8::MAX as i64 {
+            self.write_byte(0xd0)?;
+            self.write_byte(n as u8)?;
+        } else if n >= i16::MIN as i64 && n <= i16::MAX as i64 {
+            self.write_byte(0xd1)?;
+            self.write_bytes(&(n as i16).to_be_bytes())?;
+        } else if n >= i32::MIN as i64 && n <= i32::MAX as i64 {
+            self.write_byte(0xd2)?;
+            self.write_bytes(&(n as i32).to_be_bytes())?;
+        } else {
+            self.write_byte(0xd3)?;
+            self.write_bytes(&n.to_be_bytes())?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    /// `UInt` is always written as uint 8 - 64, which keeps it apart from `Int`
+    fn write_uint(&mut self, n: u64) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        if n <= 0xff {
+            self.write_byte(0xcc)?;
+            self.write_byte(n as u8)?;
+        } else if n <= 0xffff {
+            self.write_byte(0xcd)?;
+            self.write_bytes(&(n as u16).to_be_bytes())?;
+        } else if n <= 0xffff_ffff {
+            self.write_byte(0xce)?;
+            self.write_bytes(&(n as u32).to_be_bytes())?;
+        } else {
+            self.write_byte(0xcf)?;
+            self.write_bytes(&n.to_be_bytes())?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_double(&mut self, n: f64) -> WriteResult {
+        let cnt = self.write_byte(0xcb)?;
+        Ok(cnt + self.write_bytes(&n.to_be_bytes())?)
+    }
+    fn write_string(&mut self, s: &str) -> WriteResult {
+        let cnt = self.write_len(s.len(), Some((0xa0, 31)), [Some(0xd9), Some(0xda), Some(0xdb)])?;
+        Ok(cnt + self.write_bytes(s.as_bytes())?)
+    }
+    fn write_blob(&mut self, b: &[u8]) -> WriteResult {
+        let cnt = self.write_len(b.len(), None, [Some(0xc4), Some(0xc5), Some(0xc6)])?;
+        Ok(cnt + self.write_bytes(b)?)
+    }
+    fn write_ext(&mut self, ext_type: i8, data: &[u8]) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        match data.len() {
+            1 => { self.write_byte(0xd4)?; }
+            2 => { self.write_byte(0xd5)?; }
+            4 => { self.write_byte(0xd6)?; }
+            8 => { self.write_byte(0xd7)?; }
+            16 => { self.write_byte(0xd8)?; }
+            len => { self.write_len(len, None, [Some(0xc7), Some(0xc8), Some(0xc9)])?; }
+        }
+        self.write_byte(ext_type as u8)?;
+        self.write_bytes(data)?;
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_decimal(&mut self, decimal: &Decimal) -> WriteResult {
+        let (mantissa, exponent) = decimal.decode();
+        let mut data = mantissa.to_be_bytes().to_vec();
+        data.push(exponent as u8);
+        self.write_ext(MSGPACK_DECIMAL_EXT, &data)
+    }
+    fn write_datetime(&mut self, dt: &DateTime) -> WriteResult {
+        let msec = dt.epoch_msec();
+        let sec = msec.div_euclid(1000);
+        let nsec = (msec.rem_euclid(1000) * 1_000_000) as u64;
+        if sec >> 34 == 0 {
+            if nsec == 0 && sec >> 32 == 0 {
+                self.write_ext(TIMESTAMP_EXT, &(sec as u32).to_be_bytes())
+            } else {
+                self.write_ext(TIMESTAMP_EXT, &(nsec << 34 | sec as u64).to_be_bytes())
+            }
+        } else {
+            let mut data = (nsec as u32).to_be_bytes().to_vec();
+            data.extend_from_slice(&sec.to_be_bytes());
+            self.write_ext(TIMESTAMP_EXT, &data)
+        }
+    }
+    fn write_list(&mut self, lst: &[RpcValue]) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_len(lst.len(), Some((0x90, 15)), [None, Some(0xdc), Some(0xdd)])?;
+        for v in lst {
+            self.do_write(v)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_map(&mut self, map: &Map) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_len(map.len(), Some((0x80, 15)), [None, Some(0xde), Some(0xdf)])?;
+        for (k, v) in map.iter() {
+            self.write_string(k)?;
+            self.do_write(v)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn write_imap(&mut self, map: &IMap) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_len(map.len(), Some((0x80, 15)), [None, Some(0xde), Some(0xdf)])?;
+        for (k, v) in map.iter() {
+            self.write_int(*k as i64)?;
+            self.do_write(v)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+}
+
+impl<'a, W> MsgPackWriter<'a, W>
+    where W: ByteSink
+{
+    fn do_write(&mut self, val: &RpcValue) -> WriteResult {
+        let mm = val.meta();
+        if mm.is_empty() {
+            return self.do_write_value(val.value())
+        }
+        // extension data length has to be known up front
+        let mut data: Vec<u8> = Vec::new();
+        let mut wr = MsgPackWriter::new(&mut data);
+        wr.write_meta(mm)?;
+        wr.write_value(val.value())?;
+        self.write_ext(MSGPACK_META_EXT, &data)
+    }
+    fn do_write_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let cnt = self.byte_writer.count();
+        self.write_len(map.len(), Some((0x80, 15)), [None, Some(0xde), Some(0xdf)])?;
+        for kv in map.0.iter() {
+            match &kv.key {
+                MetaKey::Int(i) => self.write_int(*i as i64)?,
+                MetaKey::Str(s) => self.write_string(s)?,
+            };
+            self.do_write(&kv.value)?;
+        }
+        Ok(self.byte_writer.count() - cnt)
+    }
+    fn do_write_value(&mut self, val: &Value) -> WriteResult {
+        match val {
+            Value::Null => self.write_byte(0xc0),
+            Value::Bool(b) => self.write_byte(if *b { 0xc3 } else { 0xc2 }),
+            Value::Int(n) => self.write_int(*n),
+            Value::UInt(n) => self.write_uint(*n),
+            Value::String(s) => self.write_string(s),
+            Value::Blob(b) => self.write_blob(b),
+            Value::Double(n) => self.write_double(*n),
+            Value::Decimal(d) => self.write_decimal(d),
+            Value::DateTime(d) => self.write_datetime(d),
+            Value::List(lst) => self.write_list(lst),
+            Value::Map(map) => self.write_map(map),
+            Value::IMap(map) => self.write_imap(map),
+        }
+    }
+}
+
+impl<'a, W> Writer for MsgPackWriter<'a, W>
+    where W: ByteSink
+{
+    fn write(&mut self, val: &RpcValue) -> WriteResult {
+        let res = self.do_write(val);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_meta(&mut self, map: &MetaMap) -> WriteResult {
+        let res = self.do_write_meta(map);
+        self.byte_writer.flush_result(res)
+    }
+    fn write_value(&mut self, val: &Value) -> WriteResult {
+        let res = self.do_write_value(val);
+        self.byte_writer.flush_result(res)
+    }
+}
+
+/// MessagePack reader, see `MsgPackWriter` for the mapping.
+///
+/// Positive fixint and int 8 - 64 are read as `Int`, uint 8 - 64 as `UInt`, float 32 is accepted.
+pub struct MsgPackReader<'a, R>
+    where R: ByteSource
+{
+    byte_reader: ByteReader<'a, R>,
+    /// `(type, data length)` of extension consumed by `try_read_meta()` which is not `MSGPACK_META_EXT`
+    pending_ext: Option<(i8, usize)>,
+    /// value of `MSGPACK_META_EXT` read by `try_read_meta()`
+    pending_value: Option<Value>,
+}
+
+impl<'a, R> MsgPackReader<'a, R>
+    where R: ByteSource
+{
+    pub fn new(read: &'a mut R) -> Self {
+        MsgPackReader { byte_reader: ByteReader::new(read), pending_ext: None, pending_value: None }
+    }
+
+    fn peek_byte(&mut self) -> u8 {
+        self.byte_reader.peek_byte()
+    }
+    fn get_byte(&mut self) -> Result<u8, ReadError> {
+        self.byte_reader.get_byte()
+    }
+    fn make_error(&self, msg: &str) -> ReadError {
+        self.byte_reader.make_error(msg)
+    }
+
+    fn read_be(&mut self, len: usize) -> Result<u64, ReadError> {
+        let mut n = 0;
+        for _ in 0 .. len {
+            n = n << 8 | self.get_byte()? as u64;
+        }
+        Ok(n)
+    }
+    fn read_data(&mut self, len: usize) -> Result<Vec<u8>, ReadError> {
+        self.byte_reader.get_bytes(len)
+    }
+    fn read_list(&mut self, len: usize) -> Result<Value, ReadError> {
+        let mut lst = Vec::new();
+        for _ in 0 .. len {
+            lst.push(self.read()?);
+        }
+        Ok(Value::from(lst))
+    }
+    fn read_map(&mut self, len: usize) -> Result<Value, ReadError> {
+        let mut map = Map::new();
+        let mut imap = IMap::new();
+        for _ in 0 .. len {
+            let key = self.read_value()?;
+            let val = self.read()?;
+            match key {
+                Value::String(s) if imap.is_empty() => { map.insert(s.to_string(), val); }
+                Value::Int(i) if map.is_empty() && i >= i32::MIN as i64 && i <= i32::MAX as i64 => { imap.insert(i as i32, val); }
+                _ => return Err(self.make_error(&format!("Map keys must be all String or all Int, got: {}", key.type_name()))),
+            }
+        }
+        if imap.is_empty() {
+            Ok(Value::from(map))
+        } else {
+            Ok(Value::from(imap))
+        }
+    }
+    /// Read extension header, return `(type, data length)` or `None` when the next item is not extension
+    fn read_ext_header(&mut self) -> Result<Option<(i8, usize)>, ReadError> {
+        let len = match self.peek_byte() {
+            // fixext 1, 2, 4, 8, 16
+            0xd4 ..= 0xd8 => 1 << (self.get_byte()? - 0xd4),
+            0xc7 => { self.get_byte()?; self.read_be(1)? as usize }
+            0xc8 => { self.get_byte()?; self.read_be(2)? as usize }
+            0xc9 => { self.get_byte()?; self.read_be(4)? as usize }
+            _ => return Ok(None),
+        };
+        Ok(Some((self.get_byte()? as i8, len)))
+    }
+    fn read_ext(&mut self, ext_type: i8, len: usize) -> Result<Value, ReadError> {
+        let data = self.read_data(len)?;
+        match ext_type {
+            TIMESTAMP_EXT => {
+                let (sec, nsec) = match data.len() {
+                    4 => (u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as i64, 0),
+                    8 => {
+                        let n = u64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
+                        ((n & 0x3_ffff_ffff) as i64, (n >> 34) as i64)
+                    }
+                    12 => {
+                        let nsec = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as i64;
+                        (i64::from_be_bytes([data[4], data[5], data[6], data[7], data[8], data[9], data[10], data[11]]), nsec)
+                    }
+                    len => return Err(self.make_error(&format!("Invalid timestamp length: {}", len))),
+                };
+                sec.checked_mul(1000)
+                    .and_then(|msec| msec.checked_add(nsec / 1_000_000))
+                    .and_then(|msec| DateTime::checked_from_epoch_msec_tz(msec, 0))
+                    .map(Value::from)
+                    .ok_or_else(|| self.make_error("DateTime out of range"))
+            }
+            MSGPACK_DECIMAL_EXT if data.len() == 9 => {
+                let mantissa = i64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
+                Ok(Value::from(Decimal::new(mantissa, data[8] as i8)))
+            }
+            MSGPACK_DECIMAL_EXT => Err(self.make_error(&format!("Invalid Decimal length: {}", data.len()))),
+            MSGPACK_META_EXT => {
+                // meta is not wanted by caller
+                Ok(self.read_meta_ext(data)?.1)
+            }
+            _ => Err(self.make_error(&format!("Unsupported extension type: {}", ext_type))),
+        }
+    }
+    fn read_meta_ext(&self, data: Vec<u8>) -> Result<(MetaMap, Value), ReadError> {
+        let mut buff = &data[..];
+        let mut rd = MsgPackReader::new(&mut buff);
+        let res = rd.read_meta().and_then(|map| Ok((map, rd.read_value()?)));
+        match res {
+            Ok(res) if rd.byte_reader.is_eof()? => Ok(res),
+            Ok(_) => Err(self.make_error("Unexpected data after value in meta extension")),
+            Err(e) => Err(self.make_error(&format!("Invalid meta extension: {}", e.msg))),
+        }
+    }
+    fn read_meta(&mut self) -> Result<MetaMap, ReadError> {
+        let len = match self.get_byte()? {
+            b @ 0x80 ..= 0x8f => (b & 0x0f) as usize,
+            0xde => self.read_be(2)? as usize,
+            0xdf => self.read_be(4)? as usize,
+            _ => return Err(self.make_error("MetaMap must be map")),
+        };
+        let mut map = MetaMap::new();
+        for _ in 0 .. len {
+            let key = self.read_value()?;
+            let val = self.read()?;
+            match key {
+                Value::Int(i) if i >= i32::MIN as i64 && i <= i32::MAX as i64 => { map.insert(i as i32, val); }
+                Value::String(s) => { map.insert(s.as_str(), val); }
+                _ => return Err(self.make_error(&format!("MetaMap key must be int or string, got: {}", key.type_name()))),
+            }
+        }
+        Ok(map)
+    }
+    fn read_string(&mut self, len: usize) -> Result<Value, ReadError> {
+        let data = self.read_data(len)?;
+        String::from_utf8(data).map(Value::from).map_err(|e| self.make_error(&e.to_string()))
+    }
+}
+
+impl<'a, R> Reader for MsgPackReader<'a, R>
+    where R: ByteSource
+{
+    fn try_read_meta(&mut self) -> Result<Option<MetaMap>, ReadError> {
+        if self.pending_ext.is_some() {
+            return Ok(None)
+        }
+        match self.read_ext_header()? {
+            Some((MSGPACK_META_EXT, len)) => {
+                let data = self.read_data(len)?;
+                let (map, val) = self.read_meta_ext(data)?;
+                self.pending_value = Some(val);
+                Ok(Some(map))
+            }
+            ext => {
+                self.pending_ext = ext;
+                Ok(None)
+            }
+        }
+    }
+    fn read_value(&mut self) -> Result<Value, ReadError> {
+        if let Some(val) = self.pending_value.take() {
+            return Ok(val)
+        }
+        if let Some((ext_type, len)) = self.pending_ext.take() {
+            return self.read_ext(ext_type, len)
+        }
+        if let Some((ext_type, len)) = self.read_ext_header()? {
+            return self.read_ext(ext_type, len)
+        }
+        let b = self.get_byte()?;
+        match b {
+            0x00 ..= 0x7f => Ok(Value::from(b as i64)),
+            0xe0 ..= 0xff => Ok(Value::from(b as i8 as i64)),
+            0x80 ..= 0x8f => self.read_map((b & 0x0f) as usize),
+            0x90 ..= 0x9f => self.read_list((b & 0x0f) as usize),
+            0xa0 ..= 0xbf => self.read_string((b & 0x1f) as usize),
+            0xc0 => Ok(Value::Null),
+            0xc2 => Ok(Value::from(false)),
+            0xc3 => Ok(Value::from(true)),
+            0xc4 => { let len = self.read_be(1)? as usize; Ok(Value::from(self.read_data(len)?)) }
+            0xc5 => { let len = self.read_be(2)? as usize; Ok(Value::from(self.read_data(len)?)) }
+            0xc6 => { let len = self.read_be(4)? as usize; Ok(Value::from(self.read_data(len)?)) }
+            0xca => Ok(Value::from(f32::from_bits(self.read_be(4)? as u32) as f64)),
+            0xcb => Ok(Value::from(f64::from_bits(self.read_be(8)?))),
+            0xcc => Ok(Value::from(self.read_be(1)?)),
+            0xcd => Ok(Value::from(self.read_be(2)?)),
+            0xce => Ok(Value::from(self.read_be(4)?)),
+            0xcf => Ok(Value::from(self.read_be(8)?)),
+            0xd0 => Ok(Value::from(self.read_be(1)? as i8 as i64)),
+            0xd1 => Ok(Value::from(self.read_be(2)? as i16 as i64)),
+            0xd2 => Ok(Value::from(self.read_be(4)? as i32 as i64)),
+            0xd3 => Ok(Value::from(self.read_be(8)? as i64)),
+            0xd9 => { let len = self.read_be(1)? as usize; self.read_string(len) }
+            0xda => { let len = self.read_be(2)? as usize; self.read_string(len) }
+            0xdb => { let len = self.read_be(4)? as usize; self.read_string(len) }
+            0xdc => { let len = self.read_be(2)? as usize; self.read_list(len) }
+            0xdd => { let len = self.read_be(4)? as usize; self.read_list(len) }
+            0xde => { let len = self.read_be(2)? as usize; self.read_map(len) }
+            0xdf => { let len = self.read_be(4)? as usize; self.read_map(len) }
+            _ => Err(self.make_error(&format!("Invalid MessagePack format byte: 0x{:02x}", b))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use crate::{DateTime, RpcValue};
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    fn unhex(s: &str) -> Vec<u8> {
+        (0 .. s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i .. i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_write() {
+        let msgpack = |cpon: &str| hex(&RpcValue::from_cpon(cpon).unwrap().to_msgpack().unwrap());
+        assert_eq!(msgpack("[0,127,-32,128,-33,0u,65536u,null,true,false]"), "9a007fe0d10080d0dfcc00ce00010000c0c3c2");
+        assert_eq!(msgpack(r#"["a",x"0102",{"b":1},i{-2:3}]"#), "94a161c4020102 81a16201 81fe03".replace(' ', ""));
+        assert_eq!(msgpack("1.23"), "c709010000000000000 07bfe".replace(' ', ""));
+        assert_eq!(msgpack(r#"d"2013-03-21T20:04:00Z""#), "d6ff514b67b0");
+        assert_eq!(msgpack(r#"d"2013-03-21T20:04:00.500Z""#), "d7ff77359400514b67b0");
+        assert_eq!(msgpack(r#"d"1969-12-31T23:59:59Z""#), "c70cff00000000ffffffffffffffff");
+        assert_eq!(msgpack(r#"<1:2,"a":3>4"#), "c70702 820102a16103 04".replace(' ', ""));
+    }
+
+    #[test]
+    fn test_read() {
+        let cpon = |data: &str| RpcValue::from_msgpack(&unhex(data)).unwrap_or_else(|e| panic!("{}: {}", data, e)).to_cpon();
+        assert_eq!(cpon("ca3fc00000"), "1.5e0");
+        assert_eq!(cpon("d3ffffffffffffffff"), "-1");
+        assert_eq!(cpon("cfffffffffffffffff"), "18446744073709551615u");
+        assert_eq!(cpon("dc0002a0da0000"), r#"["",""]"#);
+        assert_eq!(RpcValue::from_msgpack(&unhex("d7ff0000000100000000")).unwrap(), RpcValue::from(DateTime::from_epoch_msec((1 << 32) * 1000)));
+        for data in ["c1", "d4ff00", "c70102", "d40100", "9201", "82a16101 01a161",
+                "c70cff 00000000 7fffffffffffffff", "c70cff 00000000 0100000000000000"].iter() {
+            assert!(RpcValue::from_msgpack(&unhex(&data.replace(' ', ""))).is_err(), "{}", data);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for cpon in [r#"<1:2,"a":<3:4>[]>{"$":[1,2u,-3,3.5e-1,1.23,-5e-10,d"2021-11-08T01:02:03.456Z",d"1969-12-31T23:59:59.999Z",x"00ff",i{-1:null,2:{}}]}"#,
+                "9223372036854775808u", r#"[d"2600-01-01T00:00:00Z",d"1600-01-01T00:00:00.001Z"]"#, r#""\u0000ř€""#].iter() {
+            let rv = RpcValue::from_cpon(cpon).unwrap();
+            assert_eq!(RpcValue::from_msgpack(&rv.to_msgpack().unwrap()).unwrap(), rv, "{}", cpon);
+        }
+        let rv = RpcValue::from(i64::MIN);
+        assert_eq!(RpcValue::from_msgpack(&rv.to_msgpack().unwrap()).unwrap(), rv);
+        let rv = RpcValue::from("x".repeat(70000));
+        assert_eq!(RpcValue::from_msgpack(&rv.to_msgpack().unwrap()).unwrap(), rv);
+    }
+}
@@ -1,22 +1,78 @@
-use std::fmt::{Display, Formatter};
-use std::io::Read;
+use core::fmt::{Display, Formatter};
+use crate::prelude::*;
+use crate::io::ByteSource;
 use crate::{MetaMap, RpcValue};
 use crate::rpcvalue::Value;
 
 #[derive(Debug)]
 pub struct ReadError {
     pub msg: String,
+    /// 1-based line of the next unread byte
     pub line: usize,
+    /// 1-based byte column of the next unread byte
     pub col: usize,
 }
 
+impl ReadError {
+    /// Render error with the offending source line, use when the source text is available
+    pub fn diagnostic<'a>(&'a self, source: &'a str) -> Diagnostic<'a> {
+        Diagnostic { error: self, source, file_name: None }
+    }
+}
+
 impl Display for ReadError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "ReadError: {}, line: {}, col: {}", self.msg, self.line, self.col)
     }
 }
 
-impl std::error::Error for ReadError {}
+impl core::error::Error for ReadError {}
+
+/// `ReadError` rendered with source line and caret
+/// ```text
+/// error: Expected ',' or ']' after list item
+///  --> config.cpon:2:5
+///   |
+/// 2 | [1 2]
+///   |    ^
+/// ```
+pub struct Diagnostic<'a> {
+    error: &'a ReadError,
+    source: &'a str,
+    file_name: Option<&'a str>,
+}
+
+impl<'a> Diagnostic<'a> {
+    pub fn file_name(mut self, file_name: &'a str) -> Self {
+        self.file_name = Some(file_name);
+        self
+    }
+}
+
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let line_no = self.error.line;
+        writeln!(f, "error: {}", self.error.msg)?;
+        match self.file_name {
+            Some(file_name) => writeln!(f, " --> {}:{}:{}", file_name, line_no, self.error.col)?,
+            None => writeln!(f, " --> {}:{}", line_no, self.error.col)?,
+        }
+        let line = match self.source.split('\n').nth(line_no.wrapping_sub(1)) {
+            Some(line) => line.trim_end_matches('\r'),
+            None => return Ok(()),
+        };
+        let margin = " ".repeat(line_no.to_string().len());
+        writeln!(f, "{} |", margin)?;
+        writeln!(f, "{} | {}", line_no, line)?;
+        // keep tabs so that caret is aligned with the source line, count chars not bytes
+        let mut prefix_len = self.error.col.saturating_sub(1).min(line.len());
+        while !line.is_char_boundary(prefix_len) {
+            prefix_len -= 1;
+        }
+        let padding: String = line[.. prefix_len].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        write!(f, "{} | {}^", margin, padding)
+    }
+}
 
 pub(crate) struct ByteReader<'a, R>
 {
@@ -27,14 +83,14 @@ pub(crate) struct ByteReader<'a, R>
 }
 
 impl<'a, R> ByteReader<'a, R>
-where R: Read
+where R: ByteSource
 {
     pub(crate) fn new(read: &'a mut R) -> ByteReader<'a, R> {
         ByteReader {
             read,
             peeked: None,
-            line: 0,
-            col: 0,
+            line: 1,
+            col: 1,
         }
     }
 
@@ -43,7 +99,7 @@ where R: Read
             return b
         }
         let mut arr: [u8; 1] = [0];
-        let r = self.read.read(&mut arr);
+        let r = self.read.read_bytes(&mut arr);
         match r {
             Ok(n) => {
                 if n == 0 {
@@ -62,7 +118,7 @@ where R: Read
             ret_b = b;
         } else {
             let mut arr: [u8; 1] = [0];
-            let r = self.read.read(&mut arr);
+            let r = self.read.read_bytes(&mut arr);
             match r {
                 Ok(n) => {
                     if n == 0 {
@@ -75,13 +131,90 @@ where R: Read
         }
         if ret_b == b'\n' {
             self.line += 1;
-            self.col = 0;
+            self.col = 1;
         } else {
             self.col += 1;
         }
         Ok(ret_b)
     }
 
+    /// Read exactly `n` bytes, memory is not reserved up front, so bogus length in malformed input cannot exhaust it
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    pub(crate) fn get_bytes(&mut self, n: usize) -> Result<Vec<u8>, ReadError> {
+        let mut buff = Vec::new();
+        for _ in 0 .. n {
+            buff.push(self.get_byte()?);
+        }
+        Ok(buff)
+    }
+    /// Return `true` when there are no more bytes, unlike `peek_byte()` it distinguishes zero byte from end of stream
+    pub(crate) fn is_eof(&mut self) -> Result<bool, ReadError> {
+        if self.peeked.is_some() {
+            return Ok(false)
+        }
+        let mut arr: [u8; 1] = [0];
+        match self.read.read_bytes(&mut arr) {
+            Ok(0) => Ok(true),
+            Ok(_) => {
+                self.peeked = Some(arr[0]);
+                Ok(false)
+            }
+            Err(e) => Err(self.make_error(&e.to_string())),
+        }
+    }
+
+    /// Read `XXXX`, `XXXX\uXXXX` surrogate pair or `{X...}` following `\u`
+    pub(crate) fn read_unicode_escape(&mut self) -> Result<char, ReadError> {
+        if self.peek_byte() == b'{' {
+            self.get_byte()?;
+            let mut code: u32 = 0;
+            let mut n = 0;
+            loop {
+                let b = self.get_byte()?;
+                if b == b'}' && n > 0 {
+                    break;
+                }
+                if n == 6 {
+                    return Err(self.make_error("Invalid unicode escape, too many digits"))
+                }
+                code = code * 16 + self.decode_hex(b)? as u32;
+                n += 1;
+            }
+            return char::from_u32(code).ok_or_else(|| self.make_error(&format!("Invalid unicode code point: {:x}", code)))
+        }
+        let hi = self.read_utf16_unit()?;
+        let code = match hi {
+            0xD800 ..= 0xDBFF => {
+                if self.get_byte()? != b'\\' || self.get_byte()? != b'u' {
+                    return Err(self.make_error("Unpaired high surrogate in unicode escape"))
+                }
+                let lo = self.read_utf16_unit()?;
+                if !(0xDC00 ..= 0xDFFF).contains(&lo) {
+                    return Err(self.make_error("Invalid low surrogate in unicode escape"))
+                }
+                0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+            }
+            0xDC00 ..= 0xDFFF => return Err(self.make_error("Unpaired low surrogate in unicode escape")),
+            _ => hi,
+        };
+        char::from_u32(code).ok_or_else(|| self.make_error(&format!("Invalid unicode code point: {:x}", code)))
+    }
+    fn read_utf16_unit(&mut self) -> Result<u32, ReadError> {
+        let mut unit: u32 = 0;
+        for _ in 0 .. 4 {
+            let b = self.get_byte()?;
+            unit = unit * 16 + self.decode_hex(b)? as u32;
+        }
+        Ok(unit)
+    }
+    pub(crate) fn decode_hex(&self, b: u8) -> Result<u8, ReadError> {
+        match b {
+            b'A' ..= b'F' => Ok(b - b'A' + 10),
+            b'a' ..= b'f' => Ok(b - b'a' + 10),
+            b'0' ..= b'9' => Ok(b - b'0'),
+            c => Err(self.make_error(&format!("Illegal hex encoding character: {}", c))),
+        }
+    }
     pub(crate) fn make_error(&self, msg: &str) -> ReadError {
         ReadError { msg: msg.to_string(), line: self.line, col: self.col }
     }
@@ -100,3 +233,40 @@ pub trait Reader {
     fn try_read_meta(&mut self) -> Result<Option<MetaMap>, ReadError>;
     fn read_value(&mut self) -> Result<Value, ReadError>;
 }
+
+/// Iterator over back-to-back top level values, see `CponReader::values()` and `ChainPackReader::values()`.
+///
+/// Iteration ends when the stream ends between values, end of stream inside a value is reported as error.
+/// Nothing is read after an error.
+pub struct Values<'r, T> {
+    reader: &'r mut T,
+    at_end: fn(&mut T) -> Result<bool, ReadError>,
+    read_next: fn(&mut T) -> ReadResult,
+    done: bool,
+}
+
+impl<'r, T> Values<'r, T> {
+    pub(crate) fn new(reader: &'r mut T, at_end: fn(&mut T) -> Result<bool, ReadError>, read_next: fn(&mut T) -> ReadResult) -> Self {
+        Values { reader, at_end, read_next, done: false }
+    }
+}
+
+impl<T> Iterator for Values<'_, T> {
+    type Item = ReadResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        let res = match (self.at_end)(self.reader) {
+            Ok(true) => {
+                self.done = true;
+                return None
+            }
+            Ok(false) => (self.read_next)(self.reader),
+            Err(err) => Err(err),
+        };
+        self.done = res.is_err();
+        Some(res)
+    }
+}
@@ -1,10 +1,10 @@
-use std::fmt;
-use std::io::{Cursor, BufReader};
+use core::fmt;
 // use tracing::{instrument};
-use bytes::Buf;
+use crate::prelude::*;
 use crate::{ChainPackReader, ChainPackWriter, CponReader, CponWriter, MetaMap, RpcMessage, RpcMessageMetaTags, RpcValue};
 use crate::writer::Writer;
 use crate::reader::Reader;
+use crate::rpcvalue::Redaction;
 
 #[derive(Clone, Debug)]
 pub struct RpcFrame {
@@ -46,7 +46,7 @@ impl RpcFrame {
         Ok(RpcFrame { protocol, meta, data })
     }
     pub fn to_rpcmesage(&self) -> crate::Result<RpcMessage> {
-        let mut buff = BufReader::new(&*self.data);
+        let mut buff = &*self.data;
         let value;
         match &self.protocol {
             Protocol::ChainPack => {
@@ -60,6 +60,14 @@ impl RpcFrame {
         }
         RpcMessage::new(self.meta.clone(), value).map_err(|err| err.into())
     }
+    fn data_to_rpcvalue(&self) -> crate::Result<RpcValue> {
+        let mut buff = &*self.data;
+        let value = match &self.protocol {
+            Protocol::ChainPack => ChainPackReader::new(&mut buff).read()?,
+            Protocol::Cpon => CponReader::new(&mut buff).read()?,
+        };
+        Ok(value)
+    }
 
     /// The message has already been validated with `check`.
     pub fn parse(buff: &[u8]) -> crate::Result<Option<(usize, RpcFrame)>> {
@@ -70,29 +78,40 @@ impl RpcFrame {
             return Ok(None)
         }
         // debug!("parse pos1: {}", buff.position());
-        let mut buff_cursor = Cursor::new(buff);
-        let mut cpk_rd = ChainPackReader::new(&mut buff_cursor);
+        // position is the length of consumed part of the slice
+        let mut rest = buff;
+        let mut cpk_rd = ChainPackReader::new(&mut rest);
         let msg_len = cpk_rd.read_uint_data()? as usize;
-        let header_len = buff_cursor.position() as usize;
-        let frame_len = header_len + msg_len;
+        let header_len = buff_len - rest.len();
+        if msg_len == 0 {
+            return Err("Empty frame, protocol expected!".into())
+        }
+        let frame_len = match header_len.checked_add(msg_len) {
+            Some(frame_len) => frame_len,
+            None => return Err(format!("Invalid frame length: {}!", msg_len).into()),
+        };
         if buff_len < frame_len {
             return Ok(None)
         }
-        let proto = buff_cursor.get_u8();
+        let proto = rest[0];
+        rest = &rest[1 ..];
         let protocol;
         let meta;
         if proto == Protocol::ChainPack as u8 {
             protocol = Protocol::ChainPack;
-            let mut rd = ChainPackReader::new(&mut buff_cursor);
-            meta = rd.try_read_meta()?.unwrap();
+            let mut rd = ChainPackReader::new(&mut rest);
+            meta = rd.try_read_meta()?.ok_or("Frame meta expected!")?;
         } else if proto == Protocol::Cpon as u8 {
             protocol = Protocol::Cpon;
-            let mut rd = CponReader::new(&mut buff_cursor);
-            meta = rd.try_read_meta()?.unwrap();
+            let mut rd = CponReader::new(&mut rest);
+            meta = rd.try_read_meta()?.ok_or("Frame meta expected!")?;
         } else {
             return Err(format!("Invalid protocol: {}!", proto).into())
         }
-        let pos = buff_cursor.position() as usize;
+        let pos = buff_len - rest.len();
+        if pos > frame_len {
+            return Err("Frame meta exceeds frame length!".into())
+        }
         // debug!("parse pos2: {}", pos);
         // debug!("parse data len: {}", (frame_len - pos));
         let data: Vec<u8> = buff[pos .. frame_len].into();
@@ -100,9 +119,22 @@ impl RpcFrame {
     }
 }
 
+/// `{:#}` shows meta and truncated body using default `LogLimits`, `{}` shows body length only.
+/// Secrets are redacted by `Redaction::default()` in both modes.
 impl fmt::Display for RpcFrame {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "{{proto:{}, meta:{}, data len: {}}}", self.protocol, self.meta, self.data.len())
+        let redaction = Redaction::shared_default();
+        if fmt.alternate() {
+            match self.data_to_rpcvalue() {
+                Ok(data) => {
+                    let msg = data.set_meta(Some(self.meta.clone()));
+                    write!(fmt, "{{proto:{}, message:{}}}", self.protocol, msg.log_display().redact(&redaction))
+                }
+                Err(err) => write!(fmt, "{{proto:{}, meta:{}, data len: {}, error: {}}}", self.protocol, redaction.apply_meta(&self.meta), self.data.len(), err),
+            }
+        } else {
+            write!(fmt, "{{proto:{}, meta:{}, data len: {}}}", self.protocol, redaction.apply_meta(&self.meta), self.data.len())
+        }
     }
 }
 
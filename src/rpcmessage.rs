@@ -1,12 +1,18 @@
 use crate::{RpcValue, rpctype, Value};
 use crate::metamap::*;
 // use std::collections::BTreeMap;
-use crate::rpcvalue::{IMap, List};
+use crate::rpcvalue::{IMap, List, LogDisplay, LogLimits, MapRemove};
 // use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicI64, Ordering};
-use std::fmt;
+use core::sync::atomic::Ordering;
+use core::fmt;
+use alloc::sync::Arc;
+use crate::prelude::*;
 
-static G_RPC_REQUEST_COUNT: AtomicI64 = AtomicI64::new(0);
+#[cfg(target_has_atomic = "64")]
+static G_RPC_REQUEST_COUNT: core::sync::atomic::AtomicI64 = core::sync::atomic::AtomicI64::new(0);
+// 32 bit targets like Cortex-M have no 64 bit atomics
+#[cfg(not(target_has_atomic = "64"))]
+static G_RPC_REQUEST_COUNT: core::sync::atomic::AtomicI32 = core::sync::atomic::AtomicI32::new(0);
 
 pub type RqId = i64;
 pub type CliId = i32;
@@ -63,9 +69,13 @@ impl RpcMessage {
     pub fn to_cpon(&self) -> String {
         self.0.to_cpon()
     }
+    /// Truncating Cpon representation for logging with secrets redacted by `Redaction::default()`
+    pub fn log_display(&self) -> LogDisplay<'_> {
+        self.0.log_display().redact_default()
+    }
 
     pub fn next_request_id() -> RqId {
-        let old_id = G_RPC_REQUEST_COUNT.fetch_add(1, Ordering::SeqCst);
+        let old_id = G_RPC_REQUEST_COUNT.fetch_add(1, Ordering::SeqCst) as RqId;
         old_id + 1
     }
 
@@ -117,9 +127,10 @@ impl RpcMessage {
     }
     fn set_key(&mut self, key: Key, rv: Option<RpcValue>) -> &mut Self {
         if let Value::IMap(m) = self.0.value_mut() {
+            let m = Arc::make_mut(m);
             match rv {
                 Some(rv) => m.insert(key as i32, rv),
-                None => m.remove(&(key as i32)),
+                None => m.remove_key(&(key as i32)),
             };
             self
         } else {
@@ -354,9 +365,10 @@ impl RpcError {
     }
 }
 
+/// Full Cpon with secrets redacted by `Redaction::default()`, use `to_cpon()` to get them
 impl fmt::Display for RpcMessage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.as_rpcvalue().to_cpon())
+        write!(f, "{}", self.log_display().limits(LogLimits::unlimited()))
     }
 }
 /*
@@ -1,53 +1,173 @@
-use std::collections::BTreeMap;
-use std::fmt;
+#[cfg(not(feature = "preserve_order"))]
+use alloc::collections::BTreeMap;
+use core::fmt;
+use alloc::sync::Arc;
 
-use lazy_static::lazy_static;
-
-use crate::{datetime, DateTime, Decimal};
+use crate::prelude::*;
+use crate::{datetime, Decimal};
 use crate::decimal;
 use crate::metamap::MetaMap;
 use crate::reader::Reader;
 use crate::{CponReader, ReadResult};
 use crate::writer::Writer;
-use crate::CponWriter;
-use crate::chainpack::ChainPackWriter;
+use crate::{CponWriter, CponWriterOptions};
+use crate::chainpack::{ChainPackWriter, ChainPackWriterOptions};
+use crate::{JsonReader, JsonWriter, JsonWriterOptions};
+#[cfg(feature = "cbor")]
+use crate::{CborReader, CborWriter};
+#[cfg(feature = "msgpack")]
+use crate::{MsgPackReader, MsgPackWriter};
 use crate::chainpack::ChainPackReader;
-use std::convert::From;
+use crate::chainpack;
+use crate::io::NullSink;
+
+pub use crate::diff::{diff, Patch};
+pub use crate::merge::{ListMerge, MergePolicy, TypeConflict};
+pub use crate::logdisplay::{LogDisplay, LogLimits, Redaction};
 
 // see https://github.com/rhysd/tinyjson/blob/master/src/json_value.rs
 
 const EMPTY_STR_REF: &str = "";
 const EMPTY_BYTES_REF: &[u8] = EMPTY_STR_REF.as_bytes();
-lazy_static! {
-    static ref EMPTY_LIST_REF: Vec<RpcValue> = {
-        let v = Vec::new();
-        v
-    };
-    static ref EMPTY_MAP_REF: Map = {
-        let m = BTreeMap::new();
-        m
-    };
-    static ref EMPTY_IMAP_REF: IMap = {
-        let m = BTreeMap::new();
-        m
-    };
-    static ref EMPTY_METAMAP_REF: MetaMap = MetaMap::new();
-}
-
-#[macro_export(local_inner_macros)]
+static EMPTY_LIST_REF: Vec<RpcValue> = Vec::new();
+static EMPTY_METAMAP_REF: MetaMap = MetaMap::new();
+#[cfg(not(feature = "preserve_order"))]
+static EMPTY_MAP_REF: Map = Map::new();
+#[cfg(not(feature = "preserve_order"))]
+static EMPTY_IMAP_REF: IMap = IMap::new();
+// IndexMap::new() is not const because of its random hasher
+#[cfg(feature = "preserve_order")]
+static EMPTY_MAP_REF: std::sync::LazyLock<Map> = std::sync::LazyLock::new(Map::new);
+#[cfg(feature = "preserve_order")]
+static EMPTY_IMAP_REF: std::sync::LazyLock<IMap> = std::sync::LazyLock::new(IMap::new);
+
+#[macro_export]
 macro_rules! make_map {
 	($( $key: expr => $val: expr ),*) => {{
-		 let mut map = rpcvalue::Map::new();
-		 $( map.insert($key.to_string(), RpcValue::from($val)); )*
+		 let mut map = $crate::rpcvalue::Map::new();
+		 $( map.insert($key.to_string(), $crate::RpcValue::from($val)); )*
 		 map
 	}}
 }
 
+/// Construct `RpcValue` using Cpon like syntax
+///
+/// ```
+/// use chainpack::{rpcvalue, RpcValue};
+/// let path = "foo/bar";
+/// let rv = rpcvalue!(<1: 1, 9: path, 10: "ls">i{1: [1, "a", true, null, b"blob", d"2021-11-08T01:02:03Z"]});
+/// assert_eq!(rv, RpcValue::from_cpon(r#"<1:1,9:"foo/bar",10:"ls">i{1:[1,"a",true,null,b"blob",d"2021-11-08T01:02:03Z"]}"#).unwrap());
+/// ```
+///
+/// * `<key: value, ...>` meta, `i{key: value, ...}` IMap, `{key: value, ...}` Map, `[value, ...]` List
+/// * `null`, `d"..."` DateTime, the `d "..."` form has to be used in Rust 2021 edition, invalid DateTime string panics
+/// * any other Rust expression is converted using `RpcValue::from()`, so `1.5` is `Double` and `1u32` is `UInt`
+/// * value with meta nested in meta has to be enclosed in parentheses, `<1: (<2: 3>4)>5`
+#[macro_export]
+macro_rules! rpcvalue {
+	// Containers are parsed item by item as `@item kind [entries] (key prefix) (item meta) rest`
+	// List, single token items are taken 8 at once, so long lists do not hit the recursion limit
+	(@item list [$($elems:expr,)*] () ()) => { $crate::RpcValue::from($crate::__alloc_vec![$($elems,)*] as $crate::List) };
+	(@item list [$($elems:tt)*] () () $v1:tt, $v2:tt, $v3:tt, $v4:tt, $v5:tt, $v6:tt, $v7:tt, $v8:tt, $($rest:tt)*) => {
+		$crate::rpcvalue!(@item list [$($elems)*
+			$crate::rpcvalue!($v1), $crate::rpcvalue!($v2), $crate::rpcvalue!($v3), $crate::rpcvalue!($v4),
+			$crate::rpcvalue!($v5), $crate::rpcvalue!($v6), $crate::rpcvalue!($v7), $crate::rpcvalue!($v8),] () () $($rest)*)
+	};
+	// Map
+	(@item map [$($k:expr => $v:expr,)*] () ()) => {{
+		let mut map = $crate::rpcvalue::Map::new();
+		$( map.insert($k, $v); )*
+		$crate::RpcValue::from(map)
+	}};
+	(@item map [$($entries:tt)*] () () $key:tt : $($rest:tt)+) => { $crate::rpcvalue!(@item map [$($entries)*] (($key).to_string() =>) () $($rest)+) };
+	// IMap
+	(@item imap [$($k:expr => $v:expr,)*] () ()) => {{
+		let mut map = $crate::rpcvalue::IMap::new();
+		$( map.insert($k, $v); )*
+		$crate::RpcValue::from(map)
+	}};
+	(@item imap [$($entries:tt)*] () () - $key:tt : $($rest:tt)+) => { $crate::rpcvalue!(@item imap [$($entries)*] ((-$key) as i32 =>) () $($rest)+) };
+	(@item imap [$($entries:tt)*] () () $key:tt : $($rest:tt)+) => { $crate::rpcvalue!(@item imap [$($entries)*] (($key) as i32 =>) () $($rest)+) };
+	// Item value terminated by comma or end of input is appended as `key prefix rpcvalue!(meta value),`
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) < $($rest:tt)*) => { $crate::rpcvalue!(@item_meta $kind [$($entries)*] ($($pre)*) ($($meta)* <) $($rest)*) };
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) null $(, $($rest:tt)*)?) => {
+		$crate::rpcvalue!(@item $kind [$($entries)* $($pre)* $crate::rpcvalue!($($meta)* null),] () () $($($rest)*)?)
+	};
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) [$($t:tt)*] $(, $($rest:tt)*)?) => {
+		$crate::rpcvalue!(@item $kind [$($entries)* $($pre)* $crate::rpcvalue!($($meta)* [$($t)*]),] () () $($($rest)*)?)
+	};
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) {$($t:tt)*} $(, $($rest:tt)*)?) => {
+		$crate::rpcvalue!(@item $kind [$($entries)* $($pre)* $crate::rpcvalue!($($meta)* {$($t)*}),] () () $($($rest)*)?)
+	};
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) i {$($t:tt)*} $(, $($rest:tt)*)?) => {
+		$crate::rpcvalue!(@item $kind [$($entries)* $($pre)* $crate::rpcvalue!($($meta)* i {$($t)*}),] () () $($($rest)*)?)
+	};
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) d $s:literal $(, $($rest:tt)*)?) => {
+		$crate::rpcvalue!(@item $kind [$($entries)* $($pre)* $crate::rpcvalue!($($meta)* d $s),] () () $($($rest)*)?)
+	};
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) ($($t:tt)*) $(, $($rest:tt)*)?) => {
+		$crate::rpcvalue!(@item $kind [$($entries)* $($pre)* $crate::rpcvalue!($($meta)* ($($t)*)),] () () $($($rest)*)?)
+	};
+	(@item $kind:ident [$($entries:tt)*] ($($pre:tt)*) ($($meta:tt)*) $e:expr $(, $($rest:tt)*)?) => {
+		$crate::rpcvalue!(@item $kind [$($entries)* $($pre)* $crate::rpcvalue!($($meta)* $e),] () () $($($rest)*)?)
+	};
+	// Item meta is collected up to the closing `>` as one group, it is parsed by the MetaMap rules
+	(@item_meta $kind:ident $entries:tt $pre:tt ($($meta:tt)*) > $($rest:tt)*) => { $crate::rpcvalue!(@item $kind $entries $pre ($($meta)* >) $($rest)*) };
+	(@item_meta $kind:ident $entries:tt $pre:tt ($($meta:tt)*) $t:tt $($rest:tt)*) => { $crate::rpcvalue!(@item_meta $kind $entries $pre ($($meta)* $t) $($rest)*) };
+	// MetaMap
+	(@meta [$($k:expr => $v:expr,)*] > $($value:tt)+) => {{
+		let mut meta = $crate::MetaMap::new();
+		$( meta.insert($k, $v); )*
+		$crate::rpcvalue!($($value)+).set_meta(Some(meta))
+	}};
+	(@meta [$($entries:tt)*] $key:tt : $val:tt , $($rest:tt)*) => { $crate::rpcvalue!(@meta [$($entries)* $key => $crate::rpcvalue!($val),] $($rest)*) };
+	(@meta [$($entries:tt)*] $key:tt : $val:tt > $($rest:tt)*) => { $crate::rpcvalue!(@meta [$($entries)* $key => $crate::rpcvalue!($val),] > $($rest)*) };
+	(@meta [$($entries:tt)*] $key:tt : $($rest:tt)*) => { $crate::rpcvalue!(@meta_val [$($entries)*] ($key) () $($rest)*) };
+	(@meta_val [$($entries:tt)*] ($key:tt) ($($cur:tt)+) , $($rest:tt)*) => { $crate::rpcvalue!(@meta [$($entries)* $key => $crate::rpcvalue!($($cur)+),] $($rest)*) };
+	(@meta_val [$($entries:tt)*] ($key:tt) ($($cur:tt)+) > $($rest:tt)*) => { $crate::rpcvalue!(@meta [$($entries)* $key => $crate::rpcvalue!($($cur)+),] > $($rest)*) };
+	(@meta_val [$($entries:tt)*] ($key:tt) ($($cur:tt)*) $t:tt $($rest:tt)*) => { $crate::rpcvalue!(@meta_val [$($entries)*] ($key) ($($cur)* $t) $($rest)*) };
+	// values
+	(null) => { $crate::RpcValue::null() };
+	(< $($rest:tt)+) => { $crate::rpcvalue!(@meta [] $($rest)+) };
+	([ $($t:tt)* ]) => { $crate::rpcvalue!(@item list [] () () $($t)*) };
+	({ $($t:tt)* }) => { $crate::rpcvalue!(@item map [] () () $($t)*) };
+	(i { $($t:tt)* }) => { $crate::rpcvalue!(@item imap [] () () $($t)*) };
+	(d $s:literal) => { $crate::RpcValue::from($crate::DateTime::from_iso_str($s).unwrap()) };
+	(( $($t:tt)+ )) => { $crate::rpcvalue!($($t)+) };
+	($e:expr) => { $crate::RpcValue::from($e) };
+}
+
 pub type Blob = Vec<u8>;
 pub type List = Vec<RpcValue>;
+#[cfg(not(feature = "preserve_order"))]
 pub type Map = BTreeMap<String, RpcValue>;
+#[cfg(not(feature = "preserve_order"))]
 pub type IMap = BTreeMap<i32, RpcValue>;
+/// Keys are kept in insertion order, so Cpon written by human keeps its key order when read and written back
+#[cfg(feature = "preserve_order")]
+pub type Map = indexmap::IndexMap<String, RpcValue>;
+#[cfg(feature = "preserve_order")]
+pub type IMap = indexmap::IndexMap<i32, RpcValue>;
+
+/// Remove map key without changing order of remaining keys
+pub(crate) trait MapRemove<K: ?Sized> {
+	fn remove_key(&mut self, key: &K) -> Option<RpcValue>;
+}
+impl MapRemove<str> for Map {
+	#[cfg(not(feature = "preserve_order"))]
+	fn remove_key(&mut self, key: &str) -> Option<RpcValue> { self.remove(key) }
+	#[cfg(feature = "preserve_order")]
+	fn remove_key(&mut self, key: &str) -> Option<RpcValue> { self.shift_remove(key) }
+}
+impl MapRemove<i32> for IMap {
+	#[cfg(not(feature = "preserve_order"))]
+	fn remove_key(&mut self, key: &i32) -> Option<RpcValue> { self.remove(key) }
+	#[cfg(feature = "preserve_order")]
+	fn remove_key(&mut self, key: &i32) -> Option<RpcValue> { self.shift_remove(key) }
+}
 
+/// Heap allocated values are shared using `Arc`, so `clone()` is cheap.
+/// Use `Arc::make_mut()` to modify them, data are copied only when shared.
 #[allow(non_snake_case)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
@@ -58,11 +178,11 @@ pub enum Value {
 	Bool(bool),
 	DateTime(datetime::DateTime),
 	Decimal(decimal::Decimal),
-	String(Box<String>),
-	Blob(Box<Blob>),
-	List(Box<List>),
-	Map(Box<Map>),
-	IMap(Box<IMap>),
+	String(Arc<String>),
+	Blob(Arc<Blob>),
+	List(Arc<List>),
+	Map(Arc<Map>),
+	IMap(Arc<IMap>),
 }
 
 impl Value {
@@ -102,11 +222,12 @@ impl Value {
 
 impl From<()> for Value { fn from(_: ()) -> Self { Value::Null }}
 impl From<bool> for Value { fn from(val: bool) -> Self { Value::Bool(val) }}
-impl From<&str> for Value { fn from(val: &str) -> Self { Value::String(Box::new(val.to_string())) }}
-impl From<String> for Value { fn from(val: String) -> Self { Value::String(Box::new(val)) }}
-impl From<&String> for Value { fn from(val: &String) -> Self { Value::String(Box::new(val.clone())) }}
-impl From<Vec<u8>> for Value { fn from(val: Vec<u8>) -> Self { Value::Blob(Box::new(val)) }}
-impl From<&[u8]> for Value { fn from(val: &[u8]) -> Self { Value::Blob(Box::new(val.to_vec())) }}
+impl From<&str> for Value { fn from(val: &str) -> Self { Value::String(Arc::new(val.to_string())) }}
+impl From<String> for Value { fn from(val: String) -> Self { Value::String(Arc::new(val)) }}
+impl From<&String> for Value { fn from(val: &String) -> Self { Value::String(Arc::new(val.clone())) }}
+impl From<Vec<u8>> for Value { fn from(val: Vec<u8>) -> Self { Value::Blob(Arc::new(val)) }}
+impl From<&[u8]> for Value { fn from(val: &[u8]) -> Self { Value::Blob(Arc::new(val.to_vec())) }}
+impl<const N: usize> From<&[u8; N]> for Value { fn from(val: &[u8; N]) -> Self { Value::Blob(Arc::new(val.to_vec())) }}
 impl From<i32> for Value { fn from(val: i32) -> Self { Value::Int(val.into()) }}
 impl From<i64> for Value { fn from(val: i64) -> Self { Value::Int(val) }}
 impl From<isize> for Value { fn from(val: isize) -> Self { Value::Int(val as i64) }}
@@ -115,11 +236,13 @@ impl From<u64> for Value { fn from(val: u64) -> Self { Value::UInt(val) }}
 impl From<usize> for Value { fn from(val: usize) -> Self { Value::UInt(val as u64) }}
 impl From<f64> for Value { fn from(val: f64) -> Self { Value::Double(val as f64) }}
 impl From<Decimal> for Value { fn from(val: Decimal) -> Self { Value::Decimal(val) }}
-impl From<List> for Value { fn from(val: List) -> Self { Value::List(Box::new(val)) }}
-impl From<Map> for Value { fn from(val: Map) -> Self { Value::Map(Box::new(val)) }}
-impl From<IMap> for Value { fn from(val: IMap) -> Self { Value::IMap(Box::new(val)) }}
+impl From<List> for Value { fn from(val: List) -> Self { Value::List(Arc::new(val)) }}
+impl From<Map> for Value { fn from(val: Map) -> Self { Value::Map(Arc::new(val)) }}
+impl From<IMap> for Value { fn from(val: IMap) -> Self { Value::IMap(Arc::new(val)) }}
 impl From<datetime::DateTime> for Value { fn from(val: datetime::DateTime) -> Self { Value::DateTime(val) }}
-impl From<chrono::NaiveDateTime> for Value { fn from(val: chrono::NaiveDateTime) -> Self { Value::DateTime(DateTime::from_naive_datetime(&val)) }}
+#[cfg(feature = "chrono")]
+impl From<chrono::NaiveDateTime> for Value { fn from(val: chrono::NaiveDateTime) -> Self { Value::DateTime(datetime::DateTime::from_naive_datetime(&val)) }}
+#[cfg(feature = "chrono")]
 impl<Tz: chrono::TimeZone> From<chrono::DateTime<Tz>> for Value { fn from(item: chrono::DateTime<Tz>) -> Self { Value::DateTime(datetime::DateTime::from_datetime(&item)) }}
 
 // cannot use generic implementation
@@ -133,6 +256,7 @@ impl From<String> for RpcValue { fn from(val: String) -> Self { RpcValue { meta:
 impl From<&String> for RpcValue { fn from(val: &String) -> Self { RpcValue { meta: None, value: val.into() }}}
 impl From<Vec<u8>> for RpcValue { fn from(val: Vec<u8>) -> Self { RpcValue { meta: None, value: val.into() }}}
 impl From<&[u8]> for RpcValue { fn from(val: &[u8]) -> Self { RpcValue { meta: None, value: val.into() }}}
+impl<const N: usize> From<&[u8; N]> for RpcValue { fn from(val: &[u8; N]) -> Self { RpcValue { meta: None, value: val.into() }}}
 impl From<i32> for RpcValue { fn from(val: i32) -> Self { RpcValue { meta: None, value: val.into() }}}
 impl From<i64> for RpcValue { fn from(val: i64) -> Self { RpcValue { meta: None, value: val.into() }}}
 impl From<isize> for RpcValue { fn from(val: isize) -> Self { RpcValue { meta: None, value: val.into() }}}
@@ -145,7 +269,9 @@ impl From<List> for RpcValue { fn from(val: List) -> Self { RpcValue { meta: Non
 impl From<Map> for RpcValue { fn from(val: Map) -> Self { RpcValue { meta: None, value: val.into() }}}
 impl From<IMap> for RpcValue { fn from(val: IMap) -> Self { RpcValue { meta: None, value: val.into() }}}
 impl From<datetime::DateTime> for RpcValue { fn from(val: datetime::DateTime) -> Self { RpcValue { meta: None, value: val.into() }}}
+#[cfg(feature = "chrono")]
 impl From<chrono::NaiveDateTime> for RpcValue { fn from(val: chrono::NaiveDateTime) -> Self { RpcValue { meta: None, value: val.into() }}}
+#[cfg(feature = "chrono")]
 impl<Tz: chrono::TimeZone> From<chrono::DateTime<Tz>> for RpcValue {
 	fn from(val: chrono::DateTime<Tz>) -> Self {
 		RpcValue {
@@ -195,12 +321,12 @@ impl GetIndex for usize {
 
 #[derive(PartialEq, Clone)]
 pub struct RpcValue {
-	meta: Option<Box<MetaMap>>,
+	meta: Option<Arc<MetaMap>>,
 	value: Value
 }
 
 impl RpcValue {
-	pub fn null() -> RpcValue {
+	pub const fn null() -> RpcValue {
 		RpcValue {
 			meta: None,
 			value: Value::Null,
@@ -210,7 +336,7 @@ impl RpcValue {
 		RpcValue {
 			meta: match m {
 				None => None,
-				Some(mm) => Some(Box::new(mm)),
+				Some(mm) => Some(Arc::new(mm)),
 			},
 			value: v,
 		}
@@ -248,7 +374,7 @@ impl RpcValue {
 	pub fn set_meta(mut self, meta: Option<MetaMap>) -> Self {
 		match meta {
 			None => { self.meta = None }
-			Some(mm) => { self.meta = Some(Box::new(mm)) }
+			Some(mm) => { self.meta = Some(Arc::new(mm)) }
 		}
 		self
 	}
@@ -266,13 +392,34 @@ impl RpcValue {
 	}
 	pub fn meta_mut(&mut self) -> Option<&mut MetaMap> {
 		match &mut self.meta {
-			Some(mm) => Some(mm.as_mut()),
+			Some(mm) => Some(Arc::make_mut(mm)),
 			_ => None,
 		}
 	}
 	pub fn clear_meta(&mut self) {
 		self.meta = None;
 	}
+	/// Shared meta, unlike `meta_mut()` it is not copied
+	pub(crate) fn meta_arc_mut(&mut self) -> Option<&mut Arc<MetaMap>> {
+		self.meta.as_mut()
+	}
+	/// Same meta and containers `Arc`s and equal scalars, O(1) check if value was changed by copy-on-write
+	pub(crate) fn is_same(&self, other: &RpcValue) -> bool {
+		let same_meta = match (&self.meta, &other.meta) {
+			(None, None) => true,
+			(Some(a), Some(b)) => Arc::ptr_eq(a, b),
+			_ => false,
+		};
+		same_meta && match (&self.value, &other.value) {
+			(Value::String(a), Value::String(b)) => Arc::ptr_eq(a, b),
+			(Value::Blob(a), Value::Blob(b)) => Arc::ptr_eq(a, b),
+			(Value::List(a), Value::List(b)) => Arc::ptr_eq(a, b),
+			(Value::Map(a), Value::Map(b)) => Arc::ptr_eq(a, b),
+			(Value::IMap(a), Value::IMap(b)) => Arc::ptr_eq(a, b),
+			(Value::Double(a), Value::Double(b)) => a.to_bits() == b.to_bits(),
+			(a, b) => a == b,
+		}
+	}
 
 	pub fn value(&self) -> &Value {
 		&self.value
@@ -383,7 +530,7 @@ impl RpcValue {
 			_ => &EMPTY_MAP_REF,
 		}
 	}
-	pub fn as_imap(&self) -> &BTreeMap<i32, RpcValue> {
+	pub fn as_imap(&self) -> &IMap {
 		match &self.value {
 			Value::IMap(b) => &b,
 			_ => &EMPTY_IMAP_REF,
@@ -425,6 +572,39 @@ impl RpcValue {
 			Err(err) => { Err(err.into()) }
 		}
 	}
+	pub fn to_cpon_with_options(&self, options: &CponWriterOptions) -> crate::Result<String> {
+		let mut buff: Vec<u8> = Vec::new();
+		let mut wr = CponWriter::with_options(&mut buff, options.clone());
+		wr.write(self)?;
+		Ok(String::from_utf8(buff)?)
+	}
+	/// Lossless JSON, see `JsonWriter` for the mapping of types JSON does not have
+	pub fn to_json(&self) -> crate::Result<String> {
+		let mut buff: Vec<u8> = Vec::new();
+		let mut wr = JsonWriter::new(&mut buff);
+		wr.write(self)?;
+		Ok(String::from_utf8(buff)?)
+	}
+	pub fn to_json_with_options(&self, options: &JsonWriterOptions) -> crate::Result<String> {
+		let mut buff: Vec<u8> = Vec::new();
+		let mut wr = JsonWriter::with_options(&mut buff, options.clone());
+		wr.write(self)?;
+		Ok(String::from_utf8(buff)?)
+	}
+	#[cfg(feature = "cbor")]
+	pub fn to_cbor(&self) -> crate::Result<Vec<u8>> {
+		let mut buff: Vec<u8> = Vec::new();
+		let mut wr = CborWriter::new(&mut buff);
+		wr.write(self)?;
+		Ok(buff)
+	}
+	#[cfg(feature = "msgpack")]
+	pub fn to_msgpack(&self) -> crate::Result<Vec<u8>> {
+		let mut buff: Vec<u8> = Vec::new();
+		let mut wr = MsgPackWriter::new(&mut buff);
+		wr.write(self)?;
+		Ok(buff)
+	}
 	pub fn to_chainpack(&self) -> Vec<u8> {
 		let mut buff: Vec<u8> = Vec::new();
 		let mut wr = ChainPackWriter::new(&mut buff);
@@ -434,12 +614,85 @@ impl RpcValue {
 			Err(_) => Vec::new(),
 		}
 	}
+	pub fn to_chainpack_with_options(&self, options: &ChainPackWriterOptions) -> crate::Result<Vec<u8>> {
+		let mut buff: Vec<u8> = Vec::new();
+		let mut wr = ChainPackWriter::with_options(&mut buff, options.clone());
+		wr.write(self)?;
+		Ok(buff)
+	}
+	/// ChainPack in canonical encoding, equal values give equal bytes, so they can be hashed or signed,
+	/// see `ChainPackWriterOptions::canonical`
+	pub fn to_canonical_chainpack(&self) -> crate::Result<Vec<u8>> {
+		self.to_chainpack_with_options(&ChainPackWriterOptions { canonical: true, ..Default::default() })
+	}
+	/// Feed canonical ChainPack encoding to `state`, values equal in canonical encoding give equal hashes
+	/// no matter of `Map` insertion order or `MetaMap` key order
+	pub fn hash_canonical<H: core::hash::Hasher>(&self, state: &mut H) -> crate::Result<()> {
+		state.write(&self.to_canonical_chainpack()?);
+		Ok(())
+	}
+	/// Sign canonical ChainPack encoding of value with `sign` function, for example HMAC or Ed25519 signer
+	pub fn sign_canonical<S, F: FnOnce(&[u8]) -> S>(&self, sign: F) -> crate::Result<S> {
+		Ok(sign(&self.to_canonical_chainpack()?))
+	}
+
+	/// Size of ChainPack encoded value, same as `to_chainpack().len()` but without serialization
+	pub fn chainpack_len(&self) -> usize {
+		chainpack::rpcvalue_len(self)
+	}
+	/// Size of Cpon encoded value, same as `to_cpon_indented(indent).len()` but without output buffer
+	pub fn cpon_len(&self, indent: &str) -> usize {
+		let mut sink = NullSink;
+		let mut wr = CponWriter::new(&mut sink);
+		wr.set_indent(indent.as_bytes());
+		wr.write(self).unwrap_or(0)
+	}
+	/// Truncating Cpon representation for logging, see [`LogDisplay`]
+	pub fn log_display(&self) -> LogDisplay<'_> {
+		LogDisplay::new(self)
+	}
 
 	pub fn from_cpon(s: &str) -> ReadResult {
 		let mut buff = s.as_bytes();
 		let mut rd = CponReader::new(&mut buff);
 		rd.read()
 	}
+	/// Parse Cpon in strict mode, see `CponReader::set_strict()`
+	pub fn from_cpon_strict(s: &str) -> ReadResult {
+		let mut buff = s.as_bytes();
+		let mut rd = CponReader::new(&mut buff);
+		rd.set_strict(true);
+		rd.read()
+	}
+	/// Parse JSON written by `to_json()`, nothing but white space may follow the value
+	pub fn from_json(s: &str) -> ReadResult {
+		let mut buff = s.as_bytes();
+		let mut rd = JsonReader::new(&mut buff);
+		let rv = rd.read()?;
+		rd.expect_end()?;
+		Ok(rv)
+	}
+	/// Parse any JSON in forgiving plain mode, see `JsonReader::set_plain()`
+	pub fn from_json_plain(s: &str) -> ReadResult {
+		let mut buff = s.as_bytes();
+		let mut rd = JsonReader::new(&mut buff);
+		rd.set_plain(true);
+		let rv = rd.read()?;
+		rd.expect_end()?;
+		Ok(rv)
+	}
+	#[cfg(feature = "cbor")]
+	pub fn from_cbor(b: &[u8]) -> ReadResult {
+		let mut buff = b;
+		let mut rd = CborReader::new(&mut buff);
+		rd.read()
+	}
+	#[cfg(feature = "msgpack")]
+	pub fn from_msgpack(b: &[u8]) -> ReadResult {
+		let mut buff = b;
+		let mut rd = MsgPackReader::new(&mut buff);
+		rd.read()
+	}
 	pub fn from_chainpack(b: &[u8]) -> ReadResult {
 		let mut buff = b;
 		let mut rd = ChainPackReader::new(&mut buff);
@@ -462,15 +715,16 @@ impl fmt::Display for RpcValue {
 
 #[cfg(test)]
 mod test {
-	use std::collections::BTreeMap;
 	use std::mem::size_of;
+	use std::sync::Arc;
 
+	#[cfg(feature = "chrono")]
 	use chrono::Offset;
 
 	use crate::{DateTime};
 	use crate::Decimal;
 	use crate::metamap::MetaMap;
-	use crate::rpcvalue::{RpcValue, Value, Map};
+	use crate::rpcvalue::{RpcValue, Value, Map, IMap};
 
 	macro_rules! show_size {
 		(header) => (
@@ -487,12 +741,31 @@ mod test {
 		show_size!(header);
 		show_size!(usize);
 		show_size!(MetaMap);
-		show_size!(Box<MetaMap>);
+		show_size!(Arc<MetaMap>);
 		show_size!(Option<MetaMap>);
-		show_size!(Option<Box<MetaMap>>);
+		show_size!(Option<Arc<MetaMap>>);
 		show_size!(Value);
 		show_size!(Option<Value>);
 		show_size!(RpcValue);
+		assert_eq!(size_of::<RpcValue>(), 24);
+	}
+
+	#[test]
+	fn rpcval_shared_clone()
+	{
+		let mut rv1 = RpcValue::from_cpon(r#"<1:2>{"foo":[1,2,3]}"#).unwrap();
+		let rv2 = rv1.clone();
+		match (rv1.value(), rv2.value()) {
+			(Value::Map(m1), Value::Map(m2)) => assert!(Arc::ptr_eq(m1, m2)),
+			_ => panic!("Map expected"),
+		}
+		if let Value::Map(m) = rv1.value_mut() {
+			Arc::make_mut(m).insert("bar".to_string(), RpcValue::from(4));
+		}
+		rv1.meta_mut().unwrap().insert(3, RpcValue::from(5));
+		assert_eq!(rv1.get("bar"), Some(&RpcValue::from(4)));
+		assert_eq!(rv1.meta().get(3), Some(&RpcValue::from(5)));
+		assert_eq!(rv2.to_cpon(), r#"<1:2>{"foo":[1,2,3]}"#);
 	}
 
 	#[test]
@@ -509,7 +782,7 @@ mod test {
 		let rv = RpcValue::from(12.3);
 		assert_eq!(rv.as_f64(), 12.3);
 
-		let dt = DateTime::now();
+		let dt = DateTime::from_epoch_msec_tz(1_700_000_000_123, 3600);
 		let rv = RpcValue::from(dt.clone());
 		assert_eq!(rv.as_datetime(), dt);
 
@@ -517,26 +790,33 @@ mod test {
 		let rv = RpcValue::from(dc.clone());
 		assert_eq!(rv.as_decimal(), dc);
 
-		let dt = chrono::offset::Utc::now();
-		let rv = RpcValue::from(dt.clone());
-		assert_eq!(rv.as_datetime().epoch_msec(), dt.timestamp_millis());
+		#[cfg(feature = "chrono")]
+		{
+			let dt = DateTime::now();
+			let rv = RpcValue::from(dt.clone());
+			assert_eq!(rv.as_datetime(), dt);
 
-		let dt = chrono::offset::Local::now();
-		let rv = RpcValue::from(dt.clone());
-		assert_eq!(rv.as_datetime().epoch_msec() + rv.as_datetime().utc_offset() as i64 * 1000
-				   , dt.timestamp_millis() + dt.offset().fix().local_minus_utc() as i64 * 1000);
+			let dt = chrono::offset::Utc::now();
+			let rv = RpcValue::from(dt.clone());
+			assert_eq!(rv.as_datetime().epoch_msec(), dt.timestamp_millis());
+
+			let dt = chrono::offset::Local::now();
+			let rv = RpcValue::from(dt.clone());
+			assert_eq!(rv.as_datetime().epoch_msec() + rv.as_datetime().utc_offset() as i64 * 1000
+					   , dt.timestamp_millis() + dt.offset().fix().local_minus_utc() as i64 * 1000);
+		}
 
 		let vec1 = vec![RpcValue::from(123), RpcValue::from("foo")];
 		let rv = RpcValue::from(vec1.clone());
 		assert_eq!(rv.as_list(), &vec1);
 
-		let mut m: Map = BTreeMap::new();
+		let mut m: Map = Map::new();
 		m.insert("foo".to_string(), RpcValue::from(123));
 		m.insert("bar".to_string(), RpcValue::from("foo"));
 		let rv = RpcValue::from(m.clone());
 		assert_eq!(rv.as_map(), &m);
 
-		let mut m: BTreeMap<i32, RpcValue> = BTreeMap::new();
+		let mut m: IMap = IMap::new();
 		m.insert(1, RpcValue::from(123));
 		m.insert(2, RpcValue::from("foo"));
 		let rv = RpcValue::from(m.clone());
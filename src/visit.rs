@@ -0,0 +1,386 @@
+use core::fmt;
+use alloc::sync::Arc;
+use crate::{Blob, DateTime, Decimal, List, Map, MetaMap, RpcValue, Value};
+use crate::metamap::MetaKey;
+use crate::rpcvalue::IMap;
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    /// `List` index
+    Index(usize),
+    /// `Map` key
+    Key(String),
+    /// `IMap` key
+    IKey(i32),
+    /// `MetaMap` key
+    Meta(MetaKey),
+}
+
+/// Path from the walked root to the visited value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Path(Vec<PathSegment>);
+
+impl Path {
+    pub fn new() -> Self {
+        Path(Vec::new())
+    }
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn last(&self) -> Option<&PathSegment> {
+        self.0.last()
+    }
+    fn push(&mut self, seg: PathSegment) {
+        self.0.push(seg);
+    }
+    fn pop(&mut self) {
+        self.0.pop();
+    }
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Index(ix) => write!(f, "{}", ix),
+            PathSegment::Key(k) => write!(f, "{}", k),
+            PathSegment::IKey(k) => write!(f, "{}", k),
+            PathSegment::Meta(MetaKey::Int(k)) => write!(f, "<{}>", k),
+            PathSegment::Meta(MetaKey::Str(k)) => write!(f, "<{}>", k),
+        }
+    }
+}
+
+/// Path segments separated by `/`, meta keys are enclosed in `<>`, for example `foo/1/<8>`
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (n, seg) in self.0.iter().enumerate() {
+            if n > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{}", seg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every method has default implementation which recurses into children using
+/// corresponding `walk_*` function, so only methods of interest have to be overridden.
+/// Overridden container method can call `walk_*` function to continue recursion.
+pub trait Visitor {
+    fn visit_rpcvalue(&mut self, path: &mut Path, rv: &RpcValue) { walk_rpcvalue(self, path, rv) }
+    fn visit_meta(&mut self, path: &mut Path, mm: &MetaMap) { walk_meta(self, path, mm) }
+    fn visit_meta_entry(&mut self, path: &mut Path, _key: &MetaKey, val: &RpcValue) { self.visit_rpcvalue(path, val) }
+    fn visit_value(&mut self, path: &mut Path, val: &Value) { walk_value(self, path, val) }
+
+    fn visit_null(&mut self, _path: &mut Path) {}
+    fn visit_int(&mut self, _path: &mut Path, _val: i64) {}
+    fn visit_uint(&mut self, _path: &mut Path, _val: u64) {}
+    fn visit_double(&mut self, _path: &mut Path, _val: f64) {}
+    fn visit_bool(&mut self, _path: &mut Path, _val: bool) {}
+    fn visit_datetime(&mut self, _path: &mut Path, _val: &DateTime) {}
+    fn visit_decimal(&mut self, _path: &mut Path, _val: &Decimal) {}
+    fn visit_string(&mut self, _path: &mut Path, _val: &str) {}
+    fn visit_blob(&mut self, _path: &mut Path, _val: &[u8]) {}
+    fn visit_list(&mut self, path: &mut Path, lst: &List) { walk_list(self, path, lst) }
+    fn visit_map(&mut self, path: &mut Path, map: &Map) { walk_map(self, path, map) }
+    fn visit_imap(&mut self, path: &mut Path, map: &IMap) { walk_imap(self, path, map) }
+}
+
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, rv: &RpcValue) {
+    let mut path = Path::new();
+    visitor.visit_rpcvalue(&mut path, rv);
+}
+
+pub fn walk_rpcvalue<V: Visitor + ?Sized>(visitor: &mut V, path: &mut Path, rv: &RpcValue) {
+    if rv.has_meta() {
+        visitor.visit_meta(path, rv.meta());
+    }
+    visitor.visit_value(path, rv.value());
+}
+
+pub fn walk_meta<V: Visitor + ?Sized>(visitor: &mut V, path: &mut Path, mm: &MetaMap) {
+    for kv in mm.0.iter() {
+        path.push(PathSegment::Meta(kv.key.clone()));
+        visitor.visit_meta_entry(path, &kv.key, &kv.value);
+        path.pop();
+    }
+}
+
+pub fn walk_value<V: Visitor + ?Sized>(visitor: &mut V, path: &mut Path, val: &Value) {
+    match val {
+        Value::Null => visitor.visit_null(path),
+        Value::Int(n) => visitor.visit_int(path, *n),
+        Value::UInt(n) => visitor.visit_uint(path, *n),
+        Value::Double(n) => visitor.visit_double(path, *n),
+        Value::Bool(b) => visitor.visit_bool(path, *b),
+        Value::DateTime(dt) => visitor.visit_datetime(path, dt),
+        Value::Decimal(d) => visitor.visit_decimal(path, d),
+        Value::String(s) => visitor.visit_string(path, s),
+        Value::Blob(b) => visitor.visit_blob(path, b),
+        Value::List(lst) => visitor.visit_list(path, lst),
+        Value::Map(map) => visitor.visit_map(path, map),
+        Value::IMap(map) => visitor.visit_imap(path, map),
+    }
+}
+
+pub fn walk_list<V: Visitor + ?Sized>(visitor: &mut V, path: &mut Path, lst: &List) {
+    for (ix, v) in lst.iter().enumerate() {
+        path.push(PathSegment::Index(ix));
+        visitor.visit_rpcvalue(path, v);
+        path.pop();
+    }
+}
+
+pub fn walk_map<V: Visitor + ?Sized>(visitor: &mut V, path: &mut Path, map: &Map) {
+    for (k, v) in map.iter() {
+        path.push(PathSegment::Key(k.clone()));
+        visitor.visit_rpcvalue(path, v);
+        path.pop();
+    }
+}
+
+pub fn walk_imap<V: Visitor + ?Sized>(visitor: &mut V, path: &mut Path, map: &IMap) {
+    for (k, v) in map.iter() {
+        path.push(PathSegment::IKey(*k));
+        visitor.visit_rpcvalue(path, v);
+        path.pop();
+    }
+}
+
+/// Same as `Visitor`, but visited values can be modified in place.
+///
+/// Strings, blobs, containers and meta are passed as `Arc`s, call `Arc::make_mut()` only
+/// when the value is going to be changed, so that values shared with other `RpcValue`s are copied on write only.
+/// Children of a shared container are visited on a copy of the child, the container is copied
+/// only when some child was changed, so walking a shared value without changes copies nothing.
+pub trait VisitorMut {
+    fn visit_rpcvalue(&mut self, path: &mut Path, rv: &mut RpcValue) { walk_rpcvalue_mut(self, path, rv) }
+    fn visit_meta(&mut self, path: &mut Path, mm: &mut Arc<MetaMap>) { walk_meta_mut(self, path, mm) }
+    fn visit_meta_entry(&mut self, path: &mut Path, _key: &MetaKey, val: &mut RpcValue) { self.visit_rpcvalue(path, val) }
+    fn visit_value(&mut self, path: &mut Path, val: &mut Value) { walk_value_mut(self, path, val) }
+
+    fn visit_null(&mut self, _path: &mut Path) {}
+    fn visit_int(&mut self, _path: &mut Path, _val: &mut i64) {}
+    fn visit_uint(&mut self, _path: &mut Path, _val: &mut u64) {}
+    fn visit_double(&mut self, _path: &mut Path, _val: &mut f64) {}
+    fn visit_bool(&mut self, _path: &mut Path, _val: &mut bool) {}
+    fn visit_datetime(&mut self, _path: &mut Path, _val: &mut DateTime) {}
+    fn visit_decimal(&mut self, _path: &mut Path, _val: &mut Decimal) {}
+    fn visit_string(&mut self, _path: &mut Path, _val: &mut Arc<String>) {}
+    fn visit_blob(&mut self, _path: &mut Path, _val: &mut Arc<Blob>) {}
+    fn visit_list(&mut self, path: &mut Path, lst: &mut Arc<List>) { walk_list_mut(self, path, lst) }
+    fn visit_map(&mut self, path: &mut Path, map: &mut Arc<Map>) { walk_map_mut(self, path, map) }
+    fn visit_imap(&mut self, path: &mut Path, map: &mut Arc<IMap>) { walk_imap_mut(self, path, map) }
+}
+
+pub fn walk_mut<V: VisitorMut + ?Sized>(visitor: &mut V, rv: &mut RpcValue) {
+    let mut path = Path::new();
+    visitor.visit_rpcvalue(&mut path, rv);
+}
+
+pub fn walk_rpcvalue_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &mut Path, rv: &mut RpcValue) {
+    if let Some(mm) = rv.meta_arc_mut() {
+        visitor.visit_meta(path, mm);
+    }
+    visitor.visit_value(path, rv.value_mut());
+}
+
+/// Visit copy of shared child, return it if it was changed
+fn visit_shared_child<V: VisitorMut + ?Sized>(visitor: &mut V, path: &mut Path, seg: PathSegment, child: &RpcValue) -> Option<RpcValue> {
+    let mut copy = child.clone();
+    path.push(seg);
+    visitor.visit_rpcvalue(path, &mut copy);
+    path.pop();
+    if copy.is_same(child) { None } else { Some(copy) }
+}
+
+pub fn walk_meta_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &mut Path, mm: &mut Arc<MetaMap>) {
+    if let Some(mm) = Arc::get_mut(mm) {
+        for kv in mm.0.iter_mut() {
+            path.push(PathSegment::Meta(kv.key.clone()));
+            visitor.visit_meta_entry(path, &kv.key, &mut kv.value);
+            path.pop();
+        }
+        return
+    }
+    let mut changed = Vec::new();
+    for (ix, kv) in mm.0.iter().enumerate() {
+        let mut copy = kv.value.clone();
+        path.push(PathSegment::Meta(kv.key.clone()));
+        visitor.visit_meta_entry(path, &kv.key, &mut copy);
+        path.pop();
+        if !copy.is_same(&kv.value) {
+            changed.push((ix, copy));
+        }
+    }
+    if !changed.is_empty() {
+        let mm = Arc::make_mut(mm);
+        for (ix, v) in changed {
+            mm.0[ix].value = v;
+        }
+    }
+}
+
+pub fn walk_value_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &mut Path, val: &mut Value) {
+    match val {
+        Value::Null => visitor.visit_null(path),
+        Value::Int(n) => visitor.visit_int(path, n),
+        Value::UInt(n) => visitor.visit_uint(path, n),
+        Value::Double(n) => visitor.visit_double(path, n),
+        Value::Bool(b) => visitor.visit_bool(path, b),
+        Value::DateTime(dt) => visitor.visit_datetime(path, dt),
+        Value::Decimal(d) => visitor.visit_decimal(path, d),
+        Value::String(s) => visitor.visit_string(path, s),
+        Value::Blob(b) => visitor.visit_blob(path, b),
+        Value::List(lst) => visitor.visit_list(path, lst),
+        Value::Map(map) => visitor.visit_map(path, map),
+        Value::IMap(map) => visitor.visit_imap(path, map),
+    }
+}
+
+pub fn walk_list_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &mut Path, lst: &mut Arc<List>) {
+    if let Some(lst) = Arc::get_mut(lst) {
+        for (ix, v) in lst.iter_mut().enumerate() {
+            path.push(PathSegment::Index(ix));
+            visitor.visit_rpcvalue(path, v);
+            path.pop();
+        }
+        return
+    }
+    let mut changed = Vec::new();
+    for (ix, v) in lst.iter().enumerate() {
+        if let Some(v) = visit_shared_child(visitor, path, PathSegment::Index(ix), v) {
+            changed.push((ix, v));
+        }
+    }
+    if !changed.is_empty() {
+        let lst = Arc::make_mut(lst);
+        for (ix, v) in changed {
+            lst[ix] = v;
+        }
+    }
+}
+
+pub fn walk_map_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &mut Path, map: &mut Arc<Map>) {
+    if let Some(map) = Arc::get_mut(map) {
+        for (k, v) in map.iter_mut() {
+            path.push(PathSegment::Key(k.clone()));
+            visitor.visit_rpcvalue(path, v);
+            path.pop();
+        }
+        return
+    }
+    let mut changed = Vec::new();
+    for (k, v) in map.iter() {
+        if let Some(v) = visit_shared_child(visitor, path, PathSegment::Key(k.clone()), v) {
+            changed.push((k.clone(), v));
+        }
+    }
+    if !changed.is_empty() {
+        let map = Arc::make_mut(map);
+        for (k, v) in changed {
+            map.insert(k, v);
+        }
+    }
+}
+
+pub fn walk_imap_mut<V: VisitorMut + ?Sized>(visitor: &mut V, path: &mut Path, map: &mut Arc<IMap>) {
+    if let Some(map) = Arc::get_mut(map) {
+        for (k, v) in map.iter_mut() {
+            path.push(PathSegment::IKey(*k));
+            visitor.visit_rpcvalue(path, v);
+            path.pop();
+        }
+        return
+    }
+    let mut changed = Vec::new();
+    for (k, v) in map.iter() {
+        if let Some(v) = visit_shared_child(visitor, path, PathSegment::IKey(*k), v) {
+            changed.push((*k, v));
+        }
+    }
+    if !changed.is_empty() {
+        let map = Arc::make_mut(map);
+        for (k, v) in changed {
+            map.insert(k, v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::sync::Arc;
+    use crate::{DateTime, RpcValue, Value};
+    use crate::visit::{walk, walk_mut, Path, Visitor, VisitorMut};
+    use crate::prelude::*;
+
+    #[test]
+    fn collect_paths() {
+        struct Strings(Vec<String>);
+        impl Visitor for Strings {
+            fn visit_string(&mut self, path: &mut Path, val: &str) {
+                self.0.push(format!("{}={}", path, val));
+            }
+        }
+        let rv = RpcValue::from_cpon(r#"<1:"a","foo":"b">{"x":[1,"c",i{3:<4:"d">"e"}]}"#).unwrap();
+        let mut v = Strings(Vec::new());
+        walk(&mut v, &rv);
+        assert_eq!(v.0, vec!["<1>=a", "<foo>=b", "x/1=c", "x/2/3/<4>=d", "x/2/3=e"]);
+    }
+
+    #[test]
+    fn datetimes_to_utc() {
+        struct ToUtc;
+        impl VisitorMut for ToUtc {
+            fn visit_datetime(&mut self, _path: &mut Path, val: &mut DateTime) {
+                *val = DateTime::from_epoch_msec(val.epoch_msec());
+            }
+        }
+        let mut rv = RpcValue::from_cpon(r#"<1:d"2021-11-08T01:02:03+05">[d"2021-11-08T01:02:03-0815",{"a":d"2021-11-08T01:02:03Z"}]"#).unwrap();
+        walk_mut(&mut ToUtc, &mut rv);
+        assert_eq!(rv.to_cpon(), r#"<1:d"2021-11-07T20:02:03Z">[d"2021-11-08T09:17:03Z",{"a":d"2021-11-08T01:02:03Z"}]"#);
+    }
+
+    #[test]
+    fn copy_on_write() {
+        struct Upper;
+        impl VisitorMut for Upper {
+            fn visit_string(&mut self, _path: &mut Path, val: &mut Arc<String>) {
+                if val.as_str() == "b" {
+                    *Arc::make_mut(val) = val.to_uppercase();
+                }
+            }
+        }
+        let orig = RpcValue::from_cpon(r#"<1:"a">{"x":[1,"a",i{3:<4:"a">"a"}],"y":["a"]}"#).unwrap();
+        let list = |rv: &RpcValue, key: &str| match rv.value() {
+            Value::Map(map) => match map.get(key).unwrap().value() {
+                Value::List(lst) => lst.clone(),
+                _ => panic!("List expected"),
+            },
+            _ => panic!("Map expected"),
+        };
+
+        // nothing changed, nothing copied
+        let mut rv = orig.clone();
+        walk_mut(&mut Upper, &mut rv);
+        assert!(rv.is_same(&orig));
+        assert!(Arc::ptr_eq(&list(&rv, "x"), &list(&orig, "x")));
+
+        // only containers on the path to changed value are copied
+        let orig = RpcValue::from_cpon(r#"<1:"a">{"x":[1,"a",i{3:<4:"b">"a"}],"y":["a"]}"#).unwrap();
+        let mut rv = orig.clone();
+        walk_mut(&mut Upper, &mut rv);
+        assert_eq!(rv.to_cpon(), r#"<1:"a">{"x":[1,"a",i{3:<4:"B">"a"}],"y":["a"]}"#);
+        assert_eq!(orig.to_cpon(), r#"<1:"a">{"x":[1,"a",i{3:<4:"b">"a"}],"y":["a"]}"#);
+        assert!(Arc::ptr_eq(&list(&rv, "y"), &list(&orig, "y")));
+        assert!(!Arc::ptr_eq(&list(&rv, "x"), &list(&orig, "x")));
+        assert!(Arc::ptr_eq(rv.meta_arc_mut().unwrap(), &orig.clone().meta_arc_mut().unwrap()));
+    }
+}
@@ -1,34 +1,90 @@
-use std::io::Write;
+use crate::io::{ByteSink, IoError};
 use crate::{RpcValue, Value, MetaMap};
 
-pub type WriteResult = std::io::Result<usize>;
+pub type WriteResult = Result<usize, IoError>;
 
+/// Size of `ByteWriter` buffer, bigger writes go to the sink directly
+const BUFFER_LEN: usize = 512;
+
+/// Bytes are collected in a buffer and passed to the sink when it is full
+/// or when a public writer method returns, see `flush_result()`.
+/// Writing byte by byte to an unbuffered sink, like `File` or `TcpStream`, costs syscall per byte.
 pub(crate) struct ByteWriter<'a, W>
 {
     write: &'a mut W,
     cnt: usize,
+    buffer: [u8; BUFFER_LEN],
+    buffer_len: usize,
 }
 
 impl<'a, W> ByteWriter<'a, W>
-    where W: Write
+    where W: ByteSink
 {
     pub(crate) fn new(write: &'a mut W) -> Self {
         ByteWriter {
             write,
             cnt: 0,
+            buffer: [0; BUFFER_LEN],
+            buffer_len: 0,
         }
     }
+    /// Bytes written so far including the buffered ones
     pub(crate) fn count(&self) -> usize { self.cnt }
+    #[inline]
     pub(crate) fn write_byte(&mut self, b: u8) -> WriteResult {
-        let arr: [u8; 1] = [b];
-        let n = self.write.write(&arr)?;
-        self.cnt += n;
-        Ok(n)
+        if self.buffer_len < BUFFER_LEN {
+            self.buffer[self.buffer_len] = b;
+            self.buffer_len += 1;
+            self.cnt += 1;
+            return Ok(1)
+        }
+        self.write_bytes(&[b])
     }
+    #[inline]
     pub(crate) fn write_bytes(&mut self, b: &[u8]) -> WriteResult {
-        let n = self.write.write(b)?;
-        self.cnt += n;
-        Ok(n)
+        if let Some(slot) = self.buffer.get_mut(self.buffer_len .. self.buffer_len + b.len()) {
+            slot.copy_from_slice(b);
+            self.buffer_len += b.len();
+            self.cnt += b.len();
+            return Ok(b.len())
+        }
+        self.write_bytes_flushed(b)
+    }
+    /// Slow path of `write_bytes()`, `b` does not fit the rest of buffer
+    #[inline(never)]
+    fn write_bytes_flushed(&mut self, b: &[u8]) -> WriteResult {
+        self.flush()?;
+        if b.len() < BUFFER_LEN {
+            self.buffer[.. b.len()].copy_from_slice(b);
+            self.buffer_len = b.len();
+        } else {
+            self.write.write_bytes(b)?;
+        }
+        self.cnt += b.len();
+        Ok(b.len())
+    }
+    fn flush(&mut self) -> Result<(), IoError> {
+        let len = self.buffer_len;
+        if len == 0 {
+            return Ok(())
+        }
+        self.buffer_len = 0;
+        self.write.write_bytes(&self.buffer[.. len])
+    }
+    /// Flush buffer at the end of every public writer method, so the whole value is in the sink then.
+    /// Nested values are written by private `do_write*()` methods, which do not flush.
+    /// Buffer is discarded on error, since it contains an incomplete value.
+    pub(crate) fn flush_result(&mut self, res: WriteResult) -> WriteResult {
+        match res {
+            Ok(n) => {
+                self.flush()?;
+                Ok(n)
+            }
+            Err(err) => {
+                self.buffer_len = 0;
+                Err(err)
+            }
+        }
     }
 }
 
@@ -36,4 +92,84 @@ pub trait Writer {
     fn write(&mut self, rv: &RpcValue) -> WriteResult;
     fn write_meta(&mut self, m: &MetaMap) -> WriteResult;
     fn write_value(&mut self, v: &Value) -> WriteResult;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+    use crate::io::{ByteSink, IoError};
+    use crate::{ChainPackWriter, CponWriter, RpcValue, Writer};
+
+    /// Records every `write_bytes()` call, the `fail_at` one fails
+    struct ChunkSink {
+        chunks: Vec<Vec<u8>>,
+        calls: usize,
+        fail_at: Option<usize>,
+    }
+    impl ChunkSink {
+        fn new() -> Self { ChunkSink { chunks: Vec::new(), calls: 0, fail_at: None } }
+        fn data(&self) -> Vec<u8> { self.chunks.concat() }
+    }
+    impl ByteSink for ChunkSink {
+        fn write_bytes(&mut self, buf: &[u8]) -> Result<(), IoError> {
+            self.calls += 1;
+            if self.fail_at == Some(self.calls) {
+                #[cfg(feature = "std")]
+                return Err(IoError::other("sink failed"));
+                #[cfg(not(feature = "std"))]
+                return Err(IoError::new("sink failed"));
+            }
+            self.chunks.push(buf.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_at_end_of_write() {
+        let rv = RpcValue::from_cpon(r#"<1:2,"a":[1,2,3]>{"foo":[1,2,{"bar":true}],"baz":i{1:"x"}}"#).unwrap();
+        let mut sink = ChunkSink::new();
+        let mut wr = ChainPackWriter::new(&mut sink);
+        let n = wr.write(&rv).unwrap();
+        assert_eq!(sink.chunks.len(), 1);
+        assert_eq!(sink.data(), rv.to_chainpack());
+        assert_eq!(n, sink.data().len());
+
+        // meta and value written separately are flushed each
+        let mut sink = ChunkSink::new();
+        let mut wr = CponWriter::new(&mut sink);
+        wr.write_meta(rv.meta()).unwrap();
+        wr.write_value(rv.value()).unwrap();
+        assert_eq!(sink.chunks.len(), 2);
+        assert_eq!(sink.data(), rv.to_cpon().into_bytes());
+
+        let mut sink = ChunkSink::new();
+        let n = ChainPackWriter::new(&mut sink).write_uint_data(300).unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(sink.data(), vec![0x81, 0x2c]);
+    }
+
+    #[test]
+    fn test_write_bigger_than_buffer() {
+        let blob: Vec<u8> = (0 .. 3 * super::BUFFER_LEN).map(|n| n as u8).collect();
+        let list: Vec<RpcValue> = (0 .. 1000).map(|n| if n % 100 == 0 { RpcValue::from(blob.clone()) } else { RpcValue::from(n) }).collect();
+        let rv = RpcValue::from(list);
+        let mut sink = ChunkSink::new();
+        let n = ChainPackWriter::new(&mut sink).write(&rv).unwrap();
+        assert_eq!(sink.data(), rv.to_chainpack());
+        assert_eq!(n, sink.data().len());
+        assert!(sink.chunks.iter().all(|chunk| chunk.len() <= super::BUFFER_LEN || chunk.len() == blob.len()));
+    }
+
+    #[test]
+    fn test_sink_error() {
+        let rv = RpcValue::from((0 .. 1000).map(RpcValue::from).collect::<Vec<_>>());
+        let mut sink = ChunkSink::new();
+        sink.fail_at = Some(2);
+        let mut wr = ChainPackWriter::new(&mut sink);
+        assert!(wr.write(&rv).is_err());
+        // rest of the failed value is discarded, next value is flushed as usual
+        assert_eq!(wr.write(&RpcValue::from(true)).unwrap(), 1);
+        assert_eq!(sink.chunks.len(), 2);
+        assert_eq!(sink.chunks[1], vec![0xfe]);
+    }
+}
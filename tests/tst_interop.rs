@@ -0,0 +1,182 @@
+//! Golden vectors shared with libshv, see `tests/interop/vectors.txt`
+use chainpack::RpcValue;
+
+const VECTORS: &str = include_str!("interop/vectors.txt");
+
+/// Vector lines this implementation does not pass as `(vector name, line kind, reason)`,
+/// they are reported by `test_interop_vectors` and fail `test_interop_known_failures`
+const KNOWN_FAILURES: &[(&str, &str, &str)] = &[
+    ("double-zero", "cpon", "Double is read as Decimal"),
+    ("double-minus-zero", "cpon", "Double is read as Decimal"),
+    ("double-1.5", "cpon", "Double is read as Decimal"),
+    ("double-minus-1", "cpon", "Double is read as Decimal"),
+    ("double-0.1", "cpon", "Double is read as Decimal"),
+    ("double-1e300", "cpon", "Double is read as Decimal, exponent out of range"),
+    ("double-min-positive", "cpon", "Double is read as Decimal, exponent out of range"),
+    ("double-subnormal", "cpon", "Double is read as Decimal, exponent out of range"),
+    ("double-inf", "cpon", "inf is not read"),
+    ("double-minus-inf", "cpon", "-inf is not read"),
+    ("double-nan", "cpon", "NaN is not read"),
+    ("decimal-123e2", "cpon", "Decimal with positive exponent is written without it"),
+];
+
+struct Vector {
+    name: String,
+    /// (line number, kind, payload)
+    lines: Vec<(usize, String, String)>,
+}
+
+/// Failed vector line
+struct Failure {
+    name: String,
+    kind: String,
+    msg: String,
+}
+
+fn parse_vectors(text: &str) -> Vec<Vector> {
+    let mut vectors: Vec<Vector> = Vec::new();
+    for (ix, line) in text.lines().enumerate() {
+        let line_no = ix + 1;
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let (kind, payload) = line.split_once(' ').unwrap_or_else(|| panic!("line {}: missing payload", line_no));
+        if kind == "name" {
+            vectors.push(Vector { name: payload.to_string(), lines: Vec::new() });
+            continue
+        }
+        let vector = vectors.last_mut().unwrap_or_else(|| panic!("line {}: '{}' before first name", line_no, kind));
+        vector.lines.push((line_no, kind.to_string(), payload.to_string()));
+    }
+    vectors
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    hex.split(' ').map(|b| u8::from_str_radix(b, 16).unwrap_or_else(|e| panic!("{} hex: {}", e, hex))).collect()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// ChainPack keeps type, Decimal exponent and NaN bits, so it is stricter than `==`
+fn same(rv1: &RpcValue, rv2: &RpcValue) -> bool {
+    rv1.to_chainpack() == rv2.to_chainpack()
+}
+
+/// Expected value of vector from its `value` line, `None` for error vectors
+fn expected_value(vector: &Vector) -> Option<RpcValue> {
+    let name = &vector.name;
+    let mut values = vector.lines.iter().filter(|(_, kind, _)| kind == "value");
+    let exp = values.next().map(|(line_no, _, json)| {
+        let rv = RpcValue::from_json(json).unwrap_or_else(|e| panic!("{} line {}: value {}, error: {}", name, line_no, json, e));
+        assert_eq!(rv.to_json().unwrap(), *json, "{} line {}: value is not canonical", name, line_no);
+        rv
+    });
+    assert!(values.next().is_none(), "vector '{}' has more values", name);
+    let is_error = vector.lines.iter().all(|(_, kind, _)| kind == "value" || kind.ends_with('!'));
+    match (&exp, is_error) {
+        (None, false) => panic!("vector '{}' has no value", name),
+        (Some(_), true) => panic!("error vector '{}' has value", name),
+        _ => {}
+    }
+    exp
+}
+
+/// Check all vectors, writer errors panic, reader errors are returned
+fn check_vectors() -> Vec<Failure> {
+    let vectors = parse_vectors(VECTORS);
+    let mut failures = Vec::new();
+    for vector in &vectors {
+        let name = &vector.name;
+        assert_eq!(vectors.iter().filter(|v| v.name == *name).count(), 1, "duplicate vector '{}'", name);
+        let exp = expected_value(vector);
+        if exp.is_some() {
+            let count = |kind: &str| vector.lines.iter().filter(|(_, k, _)| k == kind).count();
+            assert_eq!(count("cpon"), 1, "vector '{}' must have single Cpon written", name);
+            assert_eq!(count("chainpack"), 1, "vector '{}' must have single ChainPack written", name);
+        }
+        for (line_no, kind, payload) in &vector.lines {
+            let at = format!("{} line {}: {} {}", name, line_no, kind, payload);
+            let res = match (kind.as_str(), &exp) {
+                ("value", _) => Ok(()),
+                ("cpon", Some(exp)) | ("cpon<", Some(exp)) => {
+                    if kind == "cpon" {
+                        assert_eq!(&exp.to_cpon(), payload, "{}", at);
+                    }
+                    match RpcValue::from_cpon(payload) {
+                        Ok(rv) if same(&rv, exp) => Ok(()),
+                        Ok(rv) => Err(format!("{}, read: {}", at, rv.to_cpon())),
+                        Err(e) => Err(format!("{}, error: {}", at, e)),
+                    }
+                }
+                ("chainpack", Some(exp)) | ("chainpack<", Some(exp)) => {
+                    let data = from_hex(payload);
+                    if kind == "chainpack" {
+                        assert_eq!(to_hex(&exp.to_chainpack()), *payload, "{}", at);
+                        assert_eq!(exp.chainpack_len(), data.len(), "{}", at);
+                    }
+                    match RpcValue::from_chainpack(&data) {
+                        Ok(rv) if same(&rv, exp) => Ok(()),
+                        Ok(rv) => Err(format!("{}, read: {}", at, rv.to_cpon())),
+                        Err(e) => Err(format!("{}, error: {}", at, e)),
+                    }
+                }
+                ("cpon!", None) => match RpcValue::from_cpon(payload) {
+                    Ok(rv) => Err(format!("{}, read: {}", at, rv.to_cpon())),
+                    Err(_) => Ok(()),
+                },
+                ("chainpack!", None) => match RpcValue::from_chainpack(&from_hex(payload)) {
+                    Ok(rv) => Err(format!("{}, read: {}", at, rv.to_cpon())),
+                    Err(_) => Ok(()),
+                },
+                _ => panic!("{}, unexpected line", at),
+            };
+            if let Err(msg) = res {
+                failures.push(Failure { name: name.clone(), kind: kind.clone(), msg });
+            }
+        }
+    }
+    failures
+}
+
+fn is_known(name: &str, kind: &str) -> bool {
+    KNOWN_FAILURES.iter().any(|(n, k, _)| *n == name && *k == kind)
+}
+
+#[test]
+fn test_interop_vectors()
+{
+    let failures = check_vectors();
+    let unexpected: Vec<&str> = failures.iter().filter(|f| !is_known(&f.name, &f.kind)).map(|f| f.msg.as_str()).collect();
+    assert!(unexpected.is_empty(), "failed vectors:\n{}", unexpected.join("\n"));
+    for (name, kind, reason) in KNOWN_FAILURES {
+        assert!(failures.iter().any(|f| f.name == *name && f.kind == *kind), "known failure {} {} passes, remove it from KNOWN_FAILURES", name, kind);
+        eprintln!("known failure: {} {}: {}", name, kind, reason);
+    }
+}
+
+/// Fails until `KNOWN_FAILURES` is empty, run with `cargo test -- --ignored` to list them
+#[test]
+#[ignore = "known interop failures, see KNOWN_FAILURES"]
+fn test_interop_known_failures()
+{
+    let failures: Vec<String> = check_vectors().into_iter().map(|f| f.msg).collect();
+    assert!(failures.is_empty(), "failed vectors:\n{}", failures.join("\n"));
+}
+
+/// Cpon and ChainPack of the same vector must describe the same value
+#[test]
+fn test_interop_cpon_to_chainpack()
+{
+    for vector in parse_vectors(VECTORS) {
+        let cpon = vector.lines.iter().filter(|(_, kind, _)| (kind == "cpon" || kind == "cpon<") && !is_known(&vector.name, kind));
+        let chainpack = vector.lines.iter().find(|(_, kind, _)| kind == "chainpack");
+        if let Some((_, _, hex)) = chainpack {
+            for (line_no, _, text) in cpon {
+                let rv = RpcValue::from_cpon(text).unwrap();
+                assert_eq!(to_hex(&rv.to_chainpack()), *hex, "{} line {}", vector.name, line_no);
+            }
+        }
+    }
+}
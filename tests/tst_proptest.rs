@@ -0,0 +1,128 @@
+use chainpack::{RpcValue, ChainPackReader, ChainPackWriterOptions, CponReader, Reader};
+use chainpack::Decimal;
+use chainpack::arbitrary::ArbitraryOptions;
+use chainpack::visit::{self, Path, VisitorMut};
+use chainpack::rpcframe::RpcFrame;
+use proptest::prelude::*;
+use proptest::collection::vec;
+
+/// Replaces `Decimal`s with positive exponent, which Cpon writes without exponent, by equal value with zero exponent,
+/// so `123e2` becomes `12300` as it is read back. `Double`s do not round trip and are not generated for Cpon properties.
+struct CponLossless;
+impl VisitorMut for CponLossless {
+    fn visit_decimal(&mut self, _path: &mut Path, val: &mut Decimal) {
+        let (mantissa, exponent) = (val.mantissa(), val.exponent() as i32);
+        if exponent > 0 && mantissa.unsigned_abs().to_string().len() as i32 + exponent <= 9 {
+            *val = Decimal::new(mantissa * 10_i64.pow(exponent as u32), 0);
+        }
+    }
+}
+fn cpon_lossless() -> impl Strategy<Value = RpcValue> {
+    any_with::<RpcValue>(ArbitraryOptions { doubles: false, ..ArbitraryOptions::default() }).prop_map(|mut rv| {
+        visit::walk_mut(&mut CponLossless, &mut rv);
+        rv
+    })
+}
+
+/// Flip some bytes of valid data to get close to real world corruption
+fn corrupt(data: Vec<u8>, flips: Vec<(usize, u8)>) -> Vec<u8> {
+    let mut data = data;
+    if !data.is_empty() {
+        for (ix, b) in flips {
+            let ix = ix % data.len();
+            data[ix] ^= b;
+        }
+    }
+    data
+}
+
+/// Same as `cpon_reader` fuzz target
+fn read_cpon(data: &[u8]) {
+    for strict in [false, true].iter() {
+        let mut buff = data;
+        let mut rd = CponReader::new(&mut buff);
+        rd.set_strict(*strict);
+        if let Err(err) = rd.read() {
+            if let Ok(src) = std::str::from_utf8(data) {
+                let _ = err.diagnostic(src).to_string();
+            }
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn chainpack_round_trip(rv in any::<RpcValue>()) {
+        prop_assert_eq!(RpcValue::from_chainpack(&rv.to_chainpack()).unwrap(), rv);
+    }
+
+    #[test]
+    fn canonical_chainpack_round_trip(rv in any::<RpcValue>()) {
+        let data = rv.to_canonical_chainpack().unwrap();
+        prop_assert!(chainpack::is_canonical(&data));
+        prop_assert_eq!(RpcValue::from_chainpack(&data).unwrap().to_canonical_chainpack().unwrap(), data);
+    }
+
+    #[test]
+    fn cstring_chainpack_round_trip(rv in any::<RpcValue>()) {
+        let opts = ChainPackWriterOptions { cstring: true, ..Default::default() };
+        let data = rv.to_chainpack_with_options(&opts).unwrap();
+        prop_assert_eq!(RpcValue::from_chainpack(&data).unwrap(), rv);
+    }
+
+    #[test]
+    fn encoded_len(rv in any::<RpcValue>()) {
+        prop_assert_eq!(rv.chainpack_len(), rv.to_chainpack().len());
+        prop_assert_eq!(rv.cpon_len(""), rv.to_cpon().len());
+        prop_assert_eq!(rv.cpon_len("\t"), rv.to_cpon_indented("\t").unwrap().len());
+    }
+
+    #[test]
+    fn chainpack_reader_does_not_panic(data in vec(any::<u8>(), 0 .. 256)) {
+        let mut buff = &data[..];
+        let _ = ChainPackReader::new(&mut buff).read();
+    }
+
+    #[test]
+    fn corrupted_chainpack_does_not_panic(rv in any::<RpcValue>(), flips in vec((any::<usize>(), any::<u8>()), 1 .. 4)) {
+        let data = corrupt(rv.to_chainpack(), flips);
+        let mut buff = &data[..];
+        let _ = ChainPackReader::new(&mut buff).read();
+    }
+
+    #[test]
+    fn cpon_reader_does_not_panic(data in vec(any::<u8>(), 0 .. 256)) {
+        read_cpon(&data);
+    }
+
+    #[test]
+    fn corrupted_cpon_does_not_panic(rv in any::<RpcValue>(), flips in vec((any::<usize>(), any::<u8>()), 1 .. 4)) {
+        let data = corrupt(rv.to_cpon().into_bytes(), flips);
+        read_cpon(&data);
+    }
+
+    #[test]
+    fn rpcframe_parse_does_not_panic(data in vec(any::<u8>(), 0 .. 256)) {
+        if let Ok(Some((_, frame))) = RpcFrame::parse(&data) {
+            let _ = frame.to_rpcmesage();
+            let _ = format!("{:#}", frame);
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn cpon_round_trip(rv in cpon_lossless()) {
+        let cpon = rv.to_cpon();
+        let rv2 = RpcValue::from_cpon(&cpon).map_err(|e| TestCaseError::fail(format!("{} cpon: {}", e, cpon)))?;
+        prop_assert_eq!(&rv2, &rv, "cpon: {}", cpon);
+        let cpon2 = rv.to_cpon_indented("\t").unwrap();
+        prop_assert_eq!(RpcValue::from_cpon(&cpon2).unwrap(), rv, "cpon: {}", cpon2);
+    }
+
+    #[test]
+    fn cpon_text_is_stable(rv in any_with::<RpcValue>(ArbitraryOptions { doubles: false, ..ArbitraryOptions::default() })) {
+        let cpon = RpcValue::from_cpon(&rv.to_cpon()).unwrap().to_cpon();
+        prop_assert_eq!(RpcValue::from_cpon(&cpon).unwrap().to_cpon(), cpon);
+    }
+}
@@ -1,4 +1,5 @@
-use chainpack::{RpcValue, Decimal};
+use chainpack::{RpcValue, Decimal, ChainPackReader, CponReader, rpcvalue, make_map, is_canonical, ChainPackWriterOptions};
+use chainpack::rpcframe::RpcFrame;
 use std::mem::size_of;
 
 fn from_chainpack(data: &[u8]) -> RpcValue {
@@ -235,7 +236,7 @@ fn test_conversions()
             "baz" : 1,
             "baz" : 1, // single inside multi
             */
-		}"#, "{\"baz\":1,\"foo\":\"bar\"}"],
+		}"#, if cfg!(feature = "preserve_order") { "{\"foo\":\"bar\",\"baz\":1}" } else { "{\"baz\":1,\"foo\":\"bar\"}" }],
         ["<1:2>[3,<4:5>6]", ""],
         ["<4:\"svete\">i{2:<4:\"svete\">[0,1]}", ""],
         ["d\"2019-05-03T11:30:00-0700\"", "d\"2019-05-03T11:30:00-07\""],
@@ -257,6 +258,173 @@ fn test_conversions()
         assert_eq!(cpn2, cpon2);
     }
 }
+#[test]
+fn test_rpcvalue_macro()
+{
+    assert_eq!(rpcvalue!(null), from_cpon("null"));
+    assert_eq!(rpcvalue!(true), from_cpon("true"));
+    assert_eq!(rpcvalue!(-123), from_cpon("-123"));
+    assert_eq!(rpcvalue!(123u64), from_cpon("123u"));
+    assert_eq!(rpcvalue!("foo"), from_cpon(r#""foo""#));
+    assert_eq!(rpcvalue!(b"a\tb"), from_cpon(r#"b"a\tb""#));
+    assert_eq!(rpcvalue!(d"2021-11-08T01:02:03.456-0815"), from_cpon(r#"d"2021-11-08T01:02:03.456-0815""#));
+    assert_eq!(rpcvalue!([]), from_cpon("[]"));
+    assert_eq!(rpcvalue!([1, -2, "a", [null], ]), from_cpon(r#"[1,-2,"a",[null]]"#));
+    assert_eq!(rpcvalue!({}), from_cpon("{}"));
+    assert_eq!(rpcvalue!({"foo": 1, "bar": {"baz": [1, 2]}}), from_cpon(r#"{"foo":1,"bar":{"baz":[1,2]}}"#));
+    assert_eq!(rpcvalue!(i{}), from_cpon("i{}"));
+    assert_eq!(rpcvalue!(i{1: "a", -2: i{3: 4}}), from_cpon(r#"i{1:"a",-2:i{3:4}}"#));
+    assert_eq!(rpcvalue!(<>1), from_cpon("<>1"));
+    assert_eq!(rpcvalue!(<1: 1, 10: "ls">i{1: [1, "a", true, null]}), from_cpon(r#"<1:1,10:"ls">i{1:[1,"a",true,null]}"#));
+    assert_eq!(rpcvalue!(<1: (<2: 3>4), "foo": [5]>[<6: 7>8]), from_cpon(r#"<1:<2:3>4,"foo":[5]>[<6:7>8]"#));
+    assert_eq!(rpcvalue!([<1: 2, 3: 4>5, 6, <7: "a", 8: [9]>[10, <11: 12, 13: 14>15]]), from_cpon(r#"[<1:2,3:4>5,6,<7:"a",8:[9]>[10,<11:12,13:14>15]]"#));
+    assert_eq!(rpcvalue!({"a": <1: 2, 3: -4>5, "b": 6}), from_cpon(r#"{"a":<1:2,3:-4>5,"b":6}"#));
+    assert_eq!(rpcvalue!(i{1: <1: 2, 3: 4>null, -2: <5: 6, 7: 8>d"2021-11-08T01:02:03Z"}), from_cpon(r#"i{1:<1:2,3:4>null,-2:<5:6,7:8>d"2021-11-08T01:02:03Z"}"#));
+    let lst = rpcvalue!([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96, 97, 98, 99]);
+    assert_eq!(lst, RpcValue::from((0..100).map(RpcValue::from).collect::<chainpack::List>()));
+    let lst = rpcvalue!([-1, -2, -3, -4, -5, -6, -7, -8, -9, -10, -11, -12, -13, -14, -15, -16, -17, -18, -19, -20, -21, -22, -23, -24, -25, -26, -27, -28, -29, -30, -31, -32, -33, -34, -35, -36, -37, -38, -39, -40, -41, -42, -43, -44, -45, -46, -47, -48, -49, -50, -51, -52, -53, -54, -55, -56, -57, -58, -59, -60, -61, -62, -63, -64, -65, -66, -67, -68, -69, -70]);
+    assert_eq!(lst, RpcValue::from((1..71).map(|n| RpcValue::from(-n)).collect::<chainpack::List>()));
+    let key = "baz";
+    let n = 41;
+    let lst = vec![RpcValue::from(1)];
+    assert_eq!(rpcvalue!({key: n + 1, "lst": lst.clone(), "s": format!("{}-{}", key, n)}), from_cpon(r#"{"baz":42,"lst":[1],"s":"baz-41"}"#));
+    assert_eq!(RpcValue::from(make_map!("a" => 1, "b" => "c")), from_cpon(r#"{"a":1,"b":"c"}"#));
+}
+#[cfg(feature = "preserve_order")]
+#[test]
+fn test_preserve_order()
+{
+    let cpon = r#"<8:1,1:2>{"zz":1,"aa":i{3:"c",1:"a",-2:"b"},"mm":{"b":1,"a":2}}"#;
+    let rv = from_cpon(cpon);
+    assert_eq!(to_cpon(&rv), cpon);
+    assert_eq!(to_cpon(&from_chainpack(&to_chainpack(&rv))), cpon);
+    assert_eq!(rv, from_cpon(r#"<8:1,1:2>{"aa":i{-2:"b",1:"a",3:"c"},"mm":{"a":2,"b":1},"zz":1}"#));
+}
+
+#[test]
+fn test_encoded_len()
+{
+    for n in [0, 1, 63, 64, 127, 128, -1, -64, i64::MAX, i64::MIN + 1].iter() {
+        let rv = RpcValue::from(*n);
+        assert_eq!(rv.chainpack_len(), to_chainpack(&rv).len());
+    }
+}
+
+#[test]
+fn test_values_stream()
+{
+    let values = [RpcValue::from(0u64), RpcValue::null(), from_cpon(r#"<1:2>[1,{"a":0u}]"#), RpcValue::from(0u64)];
+    let mut data: Vec<u8> = Vec::new();
+    for rv in values.iter() {
+        data.extend(to_chainpack(rv));
+    }
+    let mut buff = &data[..];
+    let mut rd = ChainPackReader::new(&mut buff);
+    let read: Vec<RpcValue> = rd.values().map(|rv| rv.unwrap()).collect();
+    assert_eq!(read, values);
+
+    // end of stream inside value
+    let mut buff = &data[.. data.len() - 3];
+    let mut rd = ChainPackReader::new(&mut buff);
+    let read: Vec<_> = rd.values().collect();
+    assert_eq!(read.len(), 3);
+    assert!(read[2].is_err());
+
+    let cpon = "0u null\n<1:2>[1,{\"a\":0u}] /* last */ 0u\n";
+    let mut buff = cpon.as_bytes();
+    let mut rd = CponReader::new(&mut buff);
+    rd.set_strict(true);
+    let read: Vec<RpcValue> = rd.values().map(|rv| rv.unwrap()).collect();
+    assert_eq!(read, values);
+
+    let mut buff = "1 [2".as_bytes();
+    let mut rd = CponReader::new(&mut buff);
+    let read: Vec<_> = rd.values().collect();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read[1].as_ref().unwrap_err().msg, "Unexpected end of stream, expected value");
+
+    // NUL byte is not end of input
+    let mut buff = "1\n [2] \0 3".as_bytes();
+    let mut rd = CponReader::new(&mut buff);
+    let read: Vec<_> = rd.values().collect();
+    assert_eq!(read.len(), 3);
+    let err = read[2].as_ref().unwrap_err();
+    assert_eq!((err.msg.as_str(), err.line, err.col), ("Unexpected NUL byte", 2, 6));
+
+    let mut buff = "".as_bytes();
+    let mut rd = CponReader::new(&mut buff);
+    assert_eq!(rd.values().count(), 0);
+}
+
+#[test]
+fn test_malformed_chainpack()
+{
+    // Int with 19 data bytes
+    assert!(RpcValue::from_chainpack(&[0x82, 0xff]).is_err());
+    // DateTime seconds overflowing i64 milliseconds
+    assert!(RpcValue::from_chainpack(&[0x8d, 0xf4, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    // frame without meta, empty frame and frame length overflow
+    assert!(RpcFrame::parse(&[0x01, 0x01, 0x00, 0x00, 0x00, 0x00]).is_err());
+    assert!(RpcFrame::parse(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x00]).is_err());
+    assert!(RpcFrame::parse(&[0xf4, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    // meta longer than frame
+    let mut data = vec![0x02, 0x01];
+    data.extend(to_chainpack(&from_cpon("<1:2>null")));
+    assert!(RpcFrame::parse(&data).is_err());
+}
+
+#[test]
+fn test_canonical_chainpack()
+{
+    let rv1 = from_cpon(r#"<"b":1,2:3,1:4>{"y":1.50,"x":[0.00,100e-2,-120.]}"#);
+    let rv2 = from_cpon(r#"<1:4,"b":1,2:3>{"x":[0.,1.,-12e1],"y":15e-1}"#);
+    assert_ne!(to_chainpack(&rv1), to_chainpack(&rv2));
+    assert_eq!(rv1.to_canonical_chainpack().unwrap(), rv2.to_canonical_chainpack().unwrap());
+    assert_eq!(from_chainpack(&rv1.to_canonical_chainpack().unwrap()), from_cpon(r#"<1:4,2:3,"b":1>{"x":[0.,1.,-12e1],"y":1.5}"#));
+    let hash = |rv: &RpcValue| {
+        use std::hash::Hasher;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        rv.hash_canonical(&mut hasher).unwrap();
+        hasher.finish()
+    };
+    assert_eq!(hash(&rv1), hash(&rv2));
+    assert_eq!(rv1.sign_canonical(|data| data.len()).unwrap(), rv1.to_canonical_chainpack().unwrap().len());
+
+    // equal values give equal bytes
+    assert_eq!(RpcValue::from(-0.0), RpcValue::from(0.0));
+    assert_eq!(RpcValue::from(-0.0).to_canonical_chainpack().unwrap(), RpcValue::from(0.0).to_canonical_chainpack().unwrap());
+
+    assert!(is_canonical(&rv1.to_canonical_chainpack().unwrap()));
+    assert!(!is_canonical(&to_chainpack(&rv1)));
+    assert!(is_canonical(&[0x41]));
+    // Int 1 in two bytes
+    assert!(!is_canonical(&[0x82, 0x80, 0x01]));
+    assert!(!is_canonical(&[0x8e, b'a', 0]));
+    assert!(is_canonical(&[0x86, 0x01, b'a']));
+    assert!(!is_canonical(&[0x41, 0x41]));
+    assert!(!is_canonical(&[]));
+    assert!(!is_canonical(&[0xff]));
+}
+
+#[test]
+fn test_cstring()
+{
+    let opts = ChainPackWriterOptions { cstring: true, ..Default::default() };
+    let rv = RpcValue::from("a\0b\\0c\\");
+    let data = rv.to_chainpack_with_options(&opts).unwrap();
+    assert_eq!(data, b"\x8ea\\0b\\\\0c\\\\\0");
+    assert_eq!(from_chainpack(&data), rv);
+    assert_eq!(from_chainpack(&to_chainpack(&rv)), rv);
+    assert_eq!(to_cpon(&rv), r#""a\0b\\0c\\""#);
+    assert_eq!(from_cpon(&to_cpon(&rv)), rv);
+    // map keys and meta keys are strings too
+    let rv = from_cpon(r#"<"k\0":"v">{"":"\0","x":[""]}"#);
+    let data = rv.to_chainpack_with_options(&opts).unwrap();
+    assert!(!data.contains(&0x86));
+    assert_eq!(from_chainpack(&data), rv);
+    assert_eq!(rv.to_chainpack_with_options(&ChainPackWriterOptions { canonical: true, cstring: true }).unwrap(), rv.to_canonical_chainpack().unwrap());
+}
+
 /*
 void testDateTime()
 {